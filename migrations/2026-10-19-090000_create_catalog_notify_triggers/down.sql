@@ -0,0 +1,5 @@
+-- Drop catalogue change notification triggers
+DROP TRIGGER IF EXISTS variant_changed_notify ON product_variants;
+DROP TRIGGER IF EXISTS product_updated_notify ON products;
+DROP FUNCTION IF EXISTS notify_variant_changed();
+DROP FUNCTION IF EXISTS notify_product_updated();
@@ -0,0 +1,47 @@
+-- Publish catalogue changes over LISTEN/NOTIFY for GraphQL subscriptions.
+-- Payloads only carry identifiers and changed values to stay well below
+-- the 8000 byte NOTIFY limit.
+
+CREATE OR REPLACE FUNCTION notify_product_updated() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('product_updated', json_build_object('id', NEW.id)::text);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER product_updated_notify
+    AFTER INSERT OR UPDATE ON products
+    FOR EACH ROW EXECUTE PROCEDURE notify_product_updated();
+
+CREATE OR REPLACE FUNCTION notify_variant_changed() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'INSERT' OR NEW.stock_quantity IS DISTINCT FROM OLD.stock_quantity THEN
+        PERFORM pg_notify('variant_stock_changed', json_build_object(
+            'variant_id', NEW.id,
+            'product_id', NEW.product_id,
+            'sku', NEW.sku,
+            'previous_quantity', CASE WHEN TG_OP = 'UPDATE' THEN OLD.stock_quantity END,
+            'stock_quantity', NEW.stock_quantity
+        )::text);
+    END IF;
+
+    IF TG_OP = 'INSERT'
+        OR NEW.price_amount IS DISTINCT FROM OLD.price_amount
+        OR NEW.price_currency IS DISTINCT FROM OLD.price_currency THEN
+        PERFORM pg_notify('price_changed', json_build_object(
+            'variant_id', NEW.id,
+            'product_id', NEW.product_id,
+            'sku', NEW.sku,
+            'previous_amount', CASE WHEN TG_OP = 'UPDATE' THEN OLD.price_amount::text END,
+            'price_amount', NEW.price_amount::text,
+            'price_currency', NEW.price_currency
+        )::text);
+    END IF;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER variant_changed_notify
+    AFTER INSERT OR UPDATE ON product_variants
+    FOR EACH ROW EXECUTE PROCEDURE notify_variant_changed();
@@ -0,0 +1,3 @@
+-- Drop stock ledger tables and their dependencies
+DROP TABLE IF EXISTS stock_movements CASCADE;
+DROP TABLE IF EXISTS stock_reservations CASCADE;
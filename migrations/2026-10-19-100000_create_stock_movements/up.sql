@@ -0,0 +1,40 @@
+-- Create stock_reservations table for units held against a variant
+CREATE TABLE stock_reservations (
+    id SERIAL PRIMARY KEY,
+    variant_id INTEGER NOT NULL REFERENCES product_variants(id) ON DELETE CASCADE,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    status VARCHAR NOT NULL DEFAULT 'ACTIVE'
+        CHECK (status IN ('ACTIVE', 'RELEASED', 'CONSUMED')),
+    reference VARCHAR,
+    expires_at TIMESTAMP,
+    released_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Active reservations are summed for every availableQuantity lookup
+CREATE INDEX idx_stock_reservations_active ON stock_reservations(variant_id) WHERE status = 'ACTIVE';
+CREATE INDEX idx_stock_reservations_reference ON stock_reservations(reference);
+
+-- Set up automatic updated_at trigger
+SELECT diesel_manage_updated_at('stock_reservations');
+
+-- Create stock_movements ledger; rows are append-only
+CREATE TABLE stock_movements (
+    id SERIAL PRIMARY KEY,
+    variant_id INTEGER NOT NULL REFERENCES product_variants(id) ON DELETE CASCADE,
+    movement_type VARCHAR NOT NULL
+        CHECK (movement_type IN ('RECEIPT', 'SALE', 'ADJUSTMENT', 'RETURN', 'RESERVATION', 'RELEASE')),
+    -- Signed change to on-hand stock; reservations and releases record the held units
+    quantity INTEGER NOT NULL,
+    stock_after INTEGER NOT NULL,
+    reason TEXT,
+    reference VARCHAR,
+    reservation_id INTEGER REFERENCES stock_reservations(id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create indexes for efficient queries
+CREATE INDEX idx_stock_movements_variant_id ON stock_movements(variant_id, created_at);
+CREATE INDEX idx_stock_movements_type ON stock_movements(movement_type);
+CREATE INDEX idx_stock_movements_reference ON stock_movements(reference);
@@ -0,0 +1,4 @@
+-- Drop locations, stock levels and their dependencies
+ALTER TABLE stock_movements DROP COLUMN IF EXISTS location_id;
+DROP TABLE IF EXISTS stock_levels CASCADE;
+DROP TABLE IF EXISTS locations CASCADE;
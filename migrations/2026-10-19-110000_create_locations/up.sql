@@ -0,0 +1,43 @@
+-- Create locations table for warehouses and other stock-holding sites
+CREATE TABLE locations (
+    id SERIAL PRIMARY KEY,
+    code VARCHAR UNIQUE NOT NULL,
+    name VARCHAR NOT NULL,
+    is_active BOOLEAN NOT NULL DEFAULT true,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Set up automatic updated_at trigger
+SELECT diesel_manage_updated_at('locations');
+
+-- Create stock_levels table; product_variants.stock_quantity holds their sum
+CREATE TABLE stock_levels (
+    id SERIAL PRIMARY KEY,
+    variant_id INTEGER NOT NULL REFERENCES product_variants(id) ON DELETE CASCADE,
+    location_id INTEGER NOT NULL REFERENCES locations(id) ON DELETE CASCADE,
+    quantity INTEGER NOT NULL DEFAULT 0 CHECK (quantity >= 0),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(variant_id, location_id)
+);
+
+-- Create indexes for efficient queries
+CREATE INDEX idx_stock_levels_variant_id ON stock_levels(variant_id);
+CREATE INDEX idx_stock_levels_location_in_stock ON stock_levels(location_id, variant_id) WHERE quantity > 0;
+
+-- Set up automatic updated_at trigger
+SELECT diesel_manage_updated_at('stock_levels');
+
+-- Movements are recorded against the location whose stock they changed
+ALTER TABLE stock_movements ADD COLUMN location_id INTEGER REFERENCES locations(id) ON DELETE SET NULL;
+CREATE INDEX idx_stock_movements_location_id ON stock_movements(location_id);
+
+-- Existing stock moves to a default location
+INSERT INTO locations (code, name) VALUES ('DEFAULT', 'Default warehouse');
+
+INSERT INTO stock_levels (variant_id, location_id, quantity)
+SELECT v.id, l.id, v.stock_quantity
+FROM product_variants v
+CROSS JOIN locations l
+WHERE l.code = 'DEFAULT' AND v.stock_quantity > 0;
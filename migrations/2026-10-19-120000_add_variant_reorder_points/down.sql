@@ -0,0 +1,4 @@
+-- Drop low stock notifications and reorder points
+DROP TRIGGER IF EXISTS variant_low_stock_notify ON product_variants;
+DROP FUNCTION IF EXISTS notify_low_stock();
+ALTER TABLE product_variants DROP COLUMN IF EXISTS reorder_point;
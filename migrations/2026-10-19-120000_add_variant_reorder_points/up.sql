@@ -0,0 +1,33 @@
+-- Stock level at which purchasing should reorder; NULL disables alerts
+ALTER TABLE product_variants ADD COLUMN reorder_point INTEGER CHECK (reorder_point >= 0);
+
+CREATE INDEX idx_product_variants_reorder ON product_variants(stock_quantity, reorder_point)
+    WHERE reorder_point IS NOT NULL;
+
+-- Notify once when stock drops below the reorder point, not on every change below it
+CREATE OR REPLACE FUNCTION notify_low_stock() RETURNS trigger AS $$
+BEGIN
+    IF NEW.reorder_point IS NOT NULL
+        AND NEW.stock_quantity < NEW.reorder_point
+        AND (
+            TG_OP = 'INSERT'
+            OR OLD.reorder_point IS NULL
+            OR OLD.stock_quantity >= OLD.reorder_point
+            OR NEW.reorder_point > OLD.reorder_point
+        ) THEN
+        PERFORM pg_notify('low_stock', json_build_object(
+            'variant_id', NEW.id,
+            'product_id', NEW.product_id,
+            'sku', NEW.sku,
+            'stock_quantity', NEW.stock_quantity,
+            'reorder_point', NEW.reorder_point
+        )::text);
+    END IF;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER variant_low_stock_notify
+    AFTER INSERT OR UPDATE ON product_variants
+    FOR EACH ROW EXECUTE PROCEDURE notify_low_stock();
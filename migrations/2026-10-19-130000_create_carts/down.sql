@@ -0,0 +1,3 @@
+-- Drop cart tables and their dependencies
+DROP TABLE IF EXISTS cart_lines CASCADE;
+DROP TABLE IF EXISTS carts CASCADE;
@@ -0,0 +1,36 @@
+-- Create carts table; ids are random so guest carts cannot be guessed
+CREATE TABLE carts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    -- Set for customer-owned carts, NULL for guest carts
+    customer_id INTEGER,
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+    status VARCHAR NOT NULL DEFAULT 'OPEN'
+        CHECK (status IN ('OPEN', 'CHECKED_OUT', 'ABANDONED')),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create indexes for efficient queries
+CREATE INDEX idx_carts_customer_id ON carts(customer_id);
+CREATE INDEX idx_carts_status ON carts(status);
+
+-- Set up automatic updated_at trigger
+SELECT diesel_manage_updated_at('carts');
+
+-- Create cart_lines table; prices are resolved from product_variants on read
+CREATE TABLE cart_lines (
+    id SERIAL PRIMARY KEY,
+    cart_id UUID NOT NULL REFERENCES carts(id) ON DELETE CASCADE,
+    variant_id INTEGER NOT NULL REFERENCES product_variants(id) ON DELETE CASCADE,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(cart_id, variant_id)
+);
+
+-- Create indexes for efficient queries
+CREATE INDEX idx_cart_lines_cart_id ON cart_lines(cart_id);
+CREATE INDEX idx_cart_lines_variant_id ON cart_lines(variant_id);
+
+-- Set up automatic updated_at trigger
+SELECT diesel_manage_updated_at('cart_lines');
@@ -0,0 +1,5 @@
+-- Drop order tables and their dependencies
+DROP TABLE IF EXISTS order_status_history CASCADE;
+DROP TABLE IF EXISTS order_lines CASCADE;
+DROP TABLE IF EXISTS orders CASCADE;
+DROP SEQUENCE IF EXISTS order_number_seq;
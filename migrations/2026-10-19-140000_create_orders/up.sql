@@ -0,0 +1,59 @@
+-- Human-facing order numbers, e.g. SO-000042
+CREATE SEQUENCE order_number_seq;
+
+-- Create orders table; lines and totals are snapshots taken at checkout
+CREATE TABLE orders (
+    id SERIAL PRIMARY KEY,
+    order_number VARCHAR UNIQUE NOT NULL
+        DEFAULT ('SO-' || LPAD(nextval('order_number_seq')::text, 6, '0')),
+    cart_id UUID REFERENCES carts(id) ON DELETE SET NULL,
+    customer_id INTEGER,
+    status VARCHAR NOT NULL DEFAULT 'PENDING' CHECK (status IN ('PENDING')),
+    currency VARCHAR(3) NOT NULL,
+    subtotal_amount DECIMAL(12,2) NOT NULL,
+    total_amount DECIMAL(12,2) NOT NULL,
+    shipping_address JSONB NOT NULL,
+    billing_address JSONB NOT NULL,
+    placed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER SEQUENCE order_number_seq OWNED BY orders.order_number;
+
+-- Create indexes for efficient queries
+CREATE INDEX idx_orders_customer_id ON orders(customer_id);
+CREATE INDEX idx_orders_status ON orders(status);
+CREATE INDEX idx_orders_placed_at ON orders(placed_at);
+
+-- Set up automatic updated_at trigger
+SELECT diesel_manage_updated_at('orders');
+
+-- Create order_lines table; variant_id is informational once the variant is gone
+CREATE TABLE order_lines (
+    id SERIAL PRIMARY KEY,
+    order_id INTEGER NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    variant_id INTEGER REFERENCES product_variants(id) ON DELETE SET NULL,
+    sku VARCHAR NOT NULL,
+    product_name VARCHAR NOT NULL,
+    unit_price_amount DECIMAL(10,2) NOT NULL,
+    currency VARCHAR(3) NOT NULL,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    line_total_amount DECIMAL(12,2) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_order_lines_order_id ON order_lines(order_id);
+CREATE INDEX idx_order_lines_variant_id ON order_lines(variant_id);
+
+-- Create order_status_history table
+CREATE TABLE order_status_history (
+    id SERIAL PRIMARY KEY,
+    order_id INTEGER NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    from_status VARCHAR,
+    to_status VARCHAR NOT NULL,
+    note TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_order_status_history_order_id ON order_status_history(order_id, created_at);
@@ -0,0 +1,12 @@
+-- Revert to the single PENDING order state
+ALTER TABLE order_lines DROP COLUMN IF EXISTS shipped_quantity;
+
+ALTER TABLE orders
+    DROP COLUMN IF EXISTS paid_at,
+    DROP COLUMN IF EXISTS shipped_at,
+    DROP COLUMN IF EXISTS fulfilled_at,
+    DROP COLUMN IF EXISTS cancelled_at,
+    DROP COLUMN IF EXISTS refunded_at;
+
+ALTER TABLE orders DROP CONSTRAINT orders_status_check;
+ALTER TABLE orders ADD CONSTRAINT orders_status_check CHECK (status IN ('PENDING'));
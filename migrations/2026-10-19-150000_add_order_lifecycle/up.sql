@@ -0,0 +1,17 @@
+-- Full order lifecycle; transitions are enforced by the application
+ALTER TABLE orders DROP CONSTRAINT orders_status_check;
+ALTER TABLE orders ADD CONSTRAINT orders_status_check CHECK (
+    status IN ('PENDING', 'PAID', 'PARTIALLY_SHIPPED', 'FULFILLED', 'CANCELLED', 'REFUNDED')
+);
+
+-- When the order first entered each state
+ALTER TABLE orders
+    ADD COLUMN paid_at TIMESTAMP,
+    ADD COLUMN shipped_at TIMESTAMP,
+    ADD COLUMN fulfilled_at TIMESTAMP,
+    ADD COLUMN cancelled_at TIMESTAMP,
+    ADD COLUMN refunded_at TIMESTAMP;
+
+-- Units of each line handed to the carrier so far
+ALTER TABLE order_lines ADD COLUMN shipped_quantity INTEGER NOT NULL DEFAULT 0
+    CHECK (shipped_quantity >= 0 AND shipped_quantity <= quantity);
@@ -0,0 +1,2 @@
+-- Drop payment_attempts table
+DROP TABLE IF EXISTS payment_attempts;
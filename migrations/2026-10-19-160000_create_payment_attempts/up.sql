@@ -0,0 +1,26 @@
+-- Every call made to a payment provider, kept for reconciliation.
+-- Rows are written before the call so interrupted attempts stay visible as PENDING.
+CREATE TABLE payment_attempts (
+    id SERIAL PRIMARY KEY,
+    order_id INTEGER NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    -- Sent to the provider so a retried call is never applied twice
+    idempotency_key UUID UNIQUE NOT NULL DEFAULT gen_random_uuid(),
+    provider VARCHAR NOT NULL,
+    operation VARCHAR NOT NULL CHECK (operation IN ('AUTHORIZE', 'CAPTURE', 'VOID', 'REFUND')),
+    status VARCHAR NOT NULL DEFAULT 'PENDING'
+        CHECK (status IN ('PENDING', 'SUCCEEDED', 'DECLINED', 'FAILED')),
+    -- Always in the order currency
+    amount DECIMAL(12,2) NOT NULL CHECK (amount >= 0),
+    currency VARCHAR(3) NOT NULL,
+    -- The authorization a capture or void acts on, or the capture a refund returns
+    parent_attempt_id INTEGER REFERENCES payment_attempts(id),
+    provider_reference VARCHAR,
+    failure_reason TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    completed_at TIMESTAMP
+);
+
+-- Create indexes for efficient queries
+CREATE INDEX idx_payment_attempts_order_id ON payment_attempts(order_id, created_at);
+CREATE INDEX idx_payment_attempts_parent_attempt_id ON payment_attempts(parent_attempt_id);
+CREATE INDEX idx_payment_attempts_status ON payment_attempts(status);
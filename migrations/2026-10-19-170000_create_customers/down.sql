@@ -0,0 +1,5 @@
+-- Drop customer tables and the references to them
+ALTER TABLE orders DROP CONSTRAINT IF EXISTS orders_customer_id_fkey;
+ALTER TABLE carts DROP CONSTRAINT IF EXISTS carts_customer_id_fkey;
+DROP TABLE IF EXISTS customer_sessions CASCADE;
+DROP TABLE IF EXISTS customers CASCADE;
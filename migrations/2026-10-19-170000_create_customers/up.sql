@@ -0,0 +1,34 @@
+-- Create customers table; passwords are stored as Argon2 PHC strings
+CREATE TABLE customers (
+    id SERIAL PRIMARY KEY,
+    email VARCHAR NOT NULL,
+    password_hash VARCHAR NOT NULL,
+    name VARCHAR,
+    last_login_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Emails are unique regardless of case
+CREATE UNIQUE INDEX idx_customers_email ON customers(LOWER(email));
+
+-- Set up automatic updated_at trigger
+SELECT diesel_manage_updated_at('customers');
+
+-- Opaque bearer tokens; only a SHA-256 digest of each token is kept
+CREATE TABLE customer_sessions (
+    id SERIAL PRIMARY KEY,
+    customer_id INTEGER NOT NULL REFERENCES customers(id) ON DELETE CASCADE,
+    token_hash VARCHAR(64) UNIQUE NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_customer_sessions_customer_id ON customer_sessions(customer_id);
+
+-- Carts and orders already carry a customer_id; make it a real reference
+ALTER TABLE carts ADD CONSTRAINT carts_customer_id_fkey
+    FOREIGN KEY (customer_id) REFERENCES customers(id) ON DELETE SET NULL;
+ALTER TABLE orders ADD CONSTRAINT orders_customer_id_fkey
+    FOREIGN KEY (customer_id) REFERENCES customers(id) ON DELETE SET NULL;
@@ -0,0 +1,3 @@
+-- Remove roles and cost prices
+ALTER TABLE product_variants DROP COLUMN IF EXISTS cost_amount;
+ALTER TABLE customers DROP COLUMN IF EXISTS role;
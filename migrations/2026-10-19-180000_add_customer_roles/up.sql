@@ -0,0 +1,7 @@
+-- Staff are accounts with an elevated role; promote the first admin by hand:
+--   UPDATE customers SET role = 'ADMIN' WHERE email = '...';
+ALTER TABLE customers ADD COLUMN role VARCHAR NOT NULL DEFAULT 'CUSTOMER'
+    CHECK (role IN ('CUSTOMER', 'CATALOGUE_EDITOR', 'INVENTORY_MANAGER', 'ADMIN'));
+
+-- Unit cost paid to the supplier; only visible to staff
+ALTER TABLE product_variants ADD COLUMN cost_amount DECIMAL(10,2) CHECK (cost_amount >= 0);
@@ -0,0 +1,16 @@
+-- Keys for unattended integrations; only a SHA-256 digest of each key is kept
+CREATE TABLE api_keys (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR NOT NULL,
+    -- First characters of the key, shown so admins can tell keys apart
+    key_prefix VARCHAR NOT NULL,
+    key_hash VARCHAR(64) UNIQUE NOT NULL,
+    scopes TEXT[] NOT NULL CHECK (
+        scopes <@ ARRAY['READ_CATALOGUE', 'WRITE_INVENTORY', 'WRITE_CATALOGUE']::TEXT[]
+    ),
+    rate_limit_per_minute INTEGER NOT NULL DEFAULT 600 CHECK (rate_limit_per_minute > 0),
+    created_by INTEGER REFERENCES customers(id) ON DELETE SET NULL,
+    last_used_at TIMESTAMP,
+    revoked_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
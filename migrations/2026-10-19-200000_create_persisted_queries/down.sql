@@ -0,0 +1,2 @@
+-- Drop persisted_queries table
+DROP TABLE IF EXISTS persisted_queries;
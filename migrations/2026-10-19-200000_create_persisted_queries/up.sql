@@ -0,0 +1,9 @@
+-- Query documents addressed by the SHA-256 hash of their text. Automatic persisted
+-- queries are stored on first use; registered ones are allowed in strict mode.
+CREATE TABLE persisted_queries (
+    hash VARCHAR(64) PRIMARY KEY,
+    query TEXT NOT NULL,
+    name VARCHAR,
+    registered BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
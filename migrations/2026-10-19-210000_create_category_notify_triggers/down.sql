@@ -0,0 +1,5 @@
+-- Drop category change notification triggers
+DROP TRIGGER IF EXISTS category_membership_notify ON product_category_junction;
+DROP TRIGGER IF EXISTS category_updated_notify ON categories;
+DROP FUNCTION IF EXISTS notify_category_membership_changed();
+DROP FUNCTION IF EXISTS notify_category_updated();
@@ -0,0 +1,50 @@
+-- Publish category changes, including products joining or leaving a category,
+-- so cached category listings can be invalidated
+
+CREATE OR REPLACE FUNCTION notify_category_updated() RETURNS trigger AS $$
+DECLARE
+    category categories%ROWTYPE;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        category := OLD;
+    ELSE
+        category := NEW;
+    END IF;
+
+    PERFORM pg_notify('category_updated', json_build_object(
+        'id', category.id,
+        'slug', category.slug,
+        'previous_slug', CASE WHEN TG_OP = 'UPDATE' AND OLD.slug <> NEW.slug THEN OLD.slug END
+    )::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER category_updated_notify
+    AFTER INSERT OR UPDATE OR DELETE ON categories
+    FOR EACH ROW EXECUTE PROCEDURE notify_category_updated();
+
+CREATE OR REPLACE FUNCTION notify_category_membership_changed() RETURNS trigger AS $$
+DECLARE
+    membership product_category_junction%ROWTYPE;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        membership := OLD;
+    ELSE
+        membership := NEW;
+    END IF;
+
+    PERFORM pg_notify('category_updated', json_build_object(
+        'id', c.id,
+        'slug', c.slug,
+        'previous_slug', NULL
+    )::text)
+    FROM categories c
+    WHERE c.id = membership.category_id;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER category_membership_notify
+    AFTER INSERT OR UPDATE OR DELETE ON product_category_junction
+    FOR EACH ROW EXECUTE PROCEDURE notify_category_membership_changed();
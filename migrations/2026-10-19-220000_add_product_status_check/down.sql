@@ -0,0 +1,2 @@
+-- Allow any product status again
+ALTER TABLE products DROP CONSTRAINT IF EXISTS products_status_check;
@@ -0,0 +1,23 @@
+-- Restrict products to the known lifecycle states. Differently written spellings of a
+-- known state are normalised; any other value stops the migration, to be fixed by hand
+-- rather than silently taking products off sale.
+UPDATE products SET status = UPPER(TRIM(status))
+WHERE status <> UPPER(TRIM(status));
+
+DO $$
+DECLARE
+    unknown TEXT;
+BEGIN
+    SELECT string_agg(DISTINCT quote_literal(status), ', ') INTO unknown
+    FROM products
+    WHERE status NOT IN ('DRAFT', 'ACTIVE', 'ARCHIVED', 'SCHEDULED');
+
+    IF unknown IS NOT NULL THEN
+        RAISE EXCEPTION 'products have unknown statuses: %', unknown
+            USING HINT = 'Set them to DRAFT, ACTIVE, ARCHIVED or SCHEDULED and run the migration again';
+    END IF;
+END $$;
+
+ALTER TABLE products
+    ADD CONSTRAINT products_status_check
+    CHECK (status IN ('DRAFT', 'ACTIVE', 'ARCHIVED', 'SCHEDULED'));
@@ -0,0 +1,8 @@
+-- Remove product publishing schedules
+DROP INDEX IF EXISTS idx_products_unpublish_at;
+DROP INDEX IF EXISTS idx_products_publish_at;
+ALTER TABLE products
+    DROP CONSTRAINT IF EXISTS products_scheduled_publish_at_check,
+    DROP CONSTRAINT IF EXISTS products_schedule_check,
+    DROP COLUMN IF EXISTS unpublish_at,
+    DROP COLUMN IF EXISTS publish_at;
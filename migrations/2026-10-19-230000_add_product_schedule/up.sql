@@ -0,0 +1,12 @@
+-- When a scheduled product goes on sale and when an active one is withdrawn, in UTC
+ALTER TABLE products
+    ADD COLUMN publish_at TIMESTAMP,
+    ADD COLUMN unpublish_at TIMESTAMP,
+    ADD CONSTRAINT products_schedule_check
+        CHECK (publish_at IS NULL OR unpublish_at IS NULL OR unpublish_at > publish_at),
+    ADD CONSTRAINT products_scheduled_publish_at_check
+        CHECK (status <> 'SCHEDULED' OR publish_at IS NOT NULL);
+
+-- The publishing scheduler only looks at products with a pending change
+CREATE INDEX idx_products_publish_at ON products(publish_at) WHERE status = 'SCHEDULED';
+CREATE INDEX idx_products_unpublish_at ON products(unpublish_at) WHERE status = 'ACTIVE';
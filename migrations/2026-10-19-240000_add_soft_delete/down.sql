@@ -0,0 +1,11 @@
+-- Remove soft delete; rows that were deleted become visible again
+DROP TRIGGER IF EXISTS media_deleted_notify ON product_media;
+DROP TRIGGER IF EXISTS variant_deleted_notify ON product_variants;
+DROP FUNCTION IF EXISTS notify_product_child_deleted();
+DROP INDEX IF EXISTS idx_product_media_live;
+DROP INDEX IF EXISTS idx_product_variants_live;
+DROP INDEX IF EXISTS idx_products_live_status;
+ALTER TABLE product_media DROP COLUMN IF EXISTS deleted_at;
+ALTER TABLE categories DROP COLUMN IF EXISTS deleted_at;
+ALTER TABLE product_variants DROP COLUMN IF EXISTS deleted_at;
+ALTER TABLE products DROP COLUMN IF EXISTS deleted_at;
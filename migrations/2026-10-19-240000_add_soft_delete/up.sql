@@ -0,0 +1,30 @@
+-- Deleting catalogue entities only marks them, so they can be restored together with
+-- the variants and media removed along with them
+ALTER TABLE products ADD COLUMN deleted_at TIMESTAMP;
+ALTER TABLE product_variants ADD COLUMN deleted_at TIMESTAMP;
+ALTER TABLE categories ADD COLUMN deleted_at TIMESTAMP;
+ALTER TABLE product_media ADD COLUMN deleted_at TIMESTAMP;
+
+-- Catalogue reads only ever look at rows that were not deleted
+CREATE INDEX idx_products_live_status ON products(status) WHERE deleted_at IS NULL;
+CREATE INDEX idx_product_variants_live ON product_variants(product_id) WHERE deleted_at IS NULL;
+CREATE INDEX idx_product_media_live ON product_media(product_id) WHERE deleted_at IS NULL;
+
+-- Removing or restoring a variant or media item changes how its product reads, so
+-- announce it as a product change to invalidate cached responses
+CREATE OR REPLACE FUNCTION notify_product_child_deleted() RETURNS trigger AS $$
+BEGIN
+    IF NEW.deleted_at IS DISTINCT FROM OLD.deleted_at THEN
+        PERFORM pg_notify('product_updated', json_build_object('id', NEW.product_id)::text);
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER variant_deleted_notify
+    AFTER UPDATE OF deleted_at ON product_variants
+    FOR EACH ROW EXECUTE PROCEDURE notify_product_child_deleted();
+
+CREATE TRIGGER media_deleted_notify
+    AFTER UPDATE OF deleted_at ON product_media
+    FOR EACH ROW EXECUTE PROCEDURE notify_product_child_deleted();
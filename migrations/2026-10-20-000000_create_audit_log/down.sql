@@ -0,0 +1,8 @@
+-- Stop auditing catalogue changes and drop the recorded history
+DROP TRIGGER IF EXISTS product_attributes_audit ON product_attributes;
+DROP TRIGGER IF EXISTS product_media_audit ON product_media;
+DROP TRIGGER IF EXISTS categories_audit ON categories;
+DROP TRIGGER IF EXISTS product_variants_audit ON product_variants;
+DROP TRIGGER IF EXISTS products_audit ON products;
+DROP FUNCTION IF EXISTS record_audit_log();
+DROP TABLE IF EXISTS audit_log;
@@ -0,0 +1,76 @@
+-- Every change to the catalogue, with who made it and the values before and after.
+-- Rows are written by triggers, so no code path can change the catalogue unrecorded.
+CREATE TABLE audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    entity_type VARCHAR NOT NULL,
+    entity_id INTEGER NOT NULL,
+    action VARCHAR NOT NULL,
+    -- Set by the application with `set_config('audit.actor', ..., true)`
+    actor VARCHAR,
+    -- {"column": {"before": ..., "after": ...}} for each changed column
+    diff JSONB NOT NULL,
+    changed_at TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+    CONSTRAINT audit_log_action_check
+        CHECK (action IN ('CREATE', 'UPDATE', 'DELETE', 'RESTORE'))
+);
+
+CREATE INDEX idx_audit_log_entity ON audit_log(entity_id, entity_type, changed_at);
+
+CREATE OR REPLACE FUNCTION record_audit_log() RETURNS trigger AS $$
+DECLARE
+    old_row JSONB := CASE WHEN TG_OP <> 'INSERT' THEN to_jsonb(OLD) END;
+    new_row JSONB := CASE WHEN TG_OP <> 'DELETE' THEN to_jsonb(NEW) END;
+    changes JSONB;
+    change_action VARCHAR;
+BEGIN
+    SELECT jsonb_object_agg(key, jsonb_build_object('before', old_row -> key, 'after', new_row -> key))
+    INTO changes
+    FROM jsonb_object_keys(COALESCE(new_row, old_row)) AS key
+    WHERE key NOT IN ('created_at', 'updated_at')
+      AND (old_row -> key) IS DISTINCT FROM (new_row -> key);
+
+    -- Touching only the timestamps is not a change worth recording
+    IF changes IS NULL THEN
+        RETURN NULL;
+    END IF;
+
+    -- Soft deletes and restores are updates of `deleted_at`
+    change_action := CASE
+        WHEN TG_OP = 'INSERT' THEN 'CREATE'
+        WHEN TG_OP = 'DELETE' THEN 'DELETE'
+        WHEN changes ? 'deleted_at' AND new_row ->> 'deleted_at' IS NOT NULL THEN 'DELETE'
+        WHEN changes ? 'deleted_at' THEN 'RESTORE'
+        ELSE 'UPDATE'
+    END;
+
+    INSERT INTO audit_log (entity_type, entity_id, action, actor, diff)
+    VALUES (
+        TG_TABLE_NAME,
+        (COALESCE(new_row, old_row) ->> 'id')::integer,
+        change_action,
+        NULLIF(current_setting('audit.actor', true), ''),
+        changes
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER products_audit
+    AFTER INSERT OR UPDATE OR DELETE ON products
+    FOR EACH ROW EXECUTE PROCEDURE record_audit_log();
+
+CREATE TRIGGER product_variants_audit
+    AFTER INSERT OR UPDATE OR DELETE ON product_variants
+    FOR EACH ROW EXECUTE PROCEDURE record_audit_log();
+
+CREATE TRIGGER categories_audit
+    AFTER INSERT OR UPDATE OR DELETE ON categories
+    FOR EACH ROW EXECUTE PROCEDURE record_audit_log();
+
+CREATE TRIGGER product_media_audit
+    AFTER INSERT OR UPDATE OR DELETE ON product_media
+    FOR EACH ROW EXECUTE PROCEDURE record_audit_log();
+
+CREATE TRIGGER product_attributes_audit
+    AFTER INSERT OR UPDATE OR DELETE ON product_attributes
+    FOR EACH ROW EXECUTE PROCEDURE record_audit_log();
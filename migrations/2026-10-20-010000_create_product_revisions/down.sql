@@ -0,0 +1,8 @@
+-- Stop recording product revisions and drop them
+DROP TRIGGER IF EXISTS product_category_junction_revision ON product_category_junction;
+DROP TRIGGER IF EXISTS product_attributes_revision ON product_attributes;
+DROP TRIGGER IF EXISTS product_variants_revision ON product_variants;
+DROP TRIGGER IF EXISTS products_revision ON products;
+DROP FUNCTION IF EXISTS record_product_revision();
+DROP TABLE IF EXISTS product_revisions;
+DROP FUNCTION IF EXISTS product_snapshot(INTEGER);
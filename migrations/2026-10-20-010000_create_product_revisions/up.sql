@@ -0,0 +1,142 @@
+-- Versioned snapshots of each product's core fields, variants, attributes and category
+-- links, so earlier states can be inspected and reverted to
+
+-- The parts of a product a revision captures. Stock is left out: it is managed through
+-- the stock ledger and never reverted.
+CREATE OR REPLACE FUNCTION product_snapshot(target_id INTEGER) RETURNS JSONB AS $$
+    SELECT jsonb_build_object(
+        'product', jsonb_build_object(
+            'name', p.name,
+            'slug', p.slug,
+            'description', p.description,
+            'status', p.status,
+            'publish_at', p.publish_at,
+            'unpublish_at', p.unpublish_at,
+            'deleted_at', p.deleted_at
+        ),
+        'variants', COALESCE((
+            SELECT jsonb_agg(jsonb_build_object(
+                'id', v.id,
+                'sku', v.sku,
+                'price_amount', v.price_amount,
+                'price_currency', v.price_currency,
+                'cost_amount', v.cost_amount,
+                'attributes', v.attributes,
+                'is_active', v.is_active,
+                'reorder_point', v.reorder_point,
+                'deleted_at', v.deleted_at
+            ) ORDER BY v.id)
+            FROM product_variants v WHERE v.product_id = p.id
+        ), '[]'),
+        'attributes', COALESCE((
+            SELECT jsonb_agg(jsonb_build_object(
+                'namespace', a.namespace,
+                'attribute_key', a.attribute_key,
+                'attribute_value', a.attribute_value,
+                'value_type', a.value_type,
+                'is_searchable', a.is_searchable
+            ) ORDER BY a.namespace, a.attribute_key)
+            FROM product_attributes a WHERE a.product_id = p.id
+        ), '[]'),
+        'categories', COALESCE((
+            SELECT jsonb_agg(jsonb_build_object(
+                'category_id', pc.category_id,
+                'is_primary', pc.is_primary
+            ) ORDER BY pc.category_id)
+            FROM product_category_junction pc WHERE pc.product_id = p.id
+        ), '[]')
+    )
+    FROM products p
+    WHERE p.id = target_id
+$$ LANGUAGE sql STABLE;
+
+CREATE TABLE product_revisions (
+    id BIGSERIAL PRIMARY KEY,
+    product_id INTEGER NOT NULL REFERENCES products(id) ON DELETE CASCADE,
+    revision INTEGER NOT NULL,
+    snapshot JSONB NOT NULL,
+    -- Same convention as audit_log.actor
+    actor VARCHAR,
+    -- Lets the triggers take a single revision per product and transaction
+    transaction_id BIGINT NOT NULL DEFAULT txid_current(),
+    created_at TIMESTAMP NOT NULL DEFAULT (NOW() AT TIME ZONE 'UTC'),
+    UNIQUE(product_id, revision)
+);
+
+CREATE INDEX idx_product_revisions_transaction ON product_revisions(product_id, transaction_id);
+
+-- Every existing product starts at revision 1
+INSERT INTO product_revisions (product_id, revision, snapshot)
+SELECT id, 1, product_snapshot(id) FROM products;
+
+-- Runs when the transaction commits, so a change touching many rows of a product
+-- yields one revision of its final state. Nothing is recorded when the snapshot
+-- matches the latest revision.
+CREATE OR REPLACE FUNCTION record_product_revision() RETURNS trigger AS $$
+DECLARE
+    changed_row JSONB;
+    target_id INTEGER;
+    current_snapshot JSONB;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        changed_row := to_jsonb(OLD);
+    ELSE
+        changed_row := to_jsonb(NEW);
+    END IF;
+    target_id := COALESCE(changed_row ->> 'product_id', changed_row ->> 'id')::integer;
+
+    IF EXISTS (
+        SELECT 1 FROM product_revisions
+        WHERE product_id = target_id AND transaction_id = txid_current()
+    ) THEN
+        RETURN NULL;
+    END IF;
+
+    -- NULL once the product itself was deleted
+    current_snapshot := product_snapshot(target_id);
+    IF current_snapshot IS NULL OR current_snapshot IS NOT DISTINCT FROM (
+        SELECT snapshot FROM product_revisions
+        WHERE product_id = target_id
+        ORDER BY revision DESC
+        LIMIT 1
+    ) THEN
+        RETURN NULL;
+    END IF;
+
+    -- Serialise revision numbers of the product
+    PERFORM 1 FROM products WHERE id = target_id FOR NO KEY UPDATE;
+
+    INSERT INTO product_revisions (product_id, revision, snapshot, actor)
+    SELECT target_id, COALESCE(MAX(revision), 0) + 1, current_snapshot,
+           NULLIF(current_setting('audit.actor', true), '')
+    FROM product_revisions
+    WHERE product_id = target_id;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE CONSTRAINT TRIGGER products_revision
+    AFTER INSERT OR DELETE
+        OR UPDATE OF name, slug, description, status, publish_at, unpublish_at, deleted_at
+    ON products
+    DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW EXECUTE PROCEDURE record_product_revision();
+
+-- Stock changes alone never make a revision
+CREATE CONSTRAINT TRIGGER product_variants_revision
+    AFTER INSERT OR DELETE
+        OR UPDATE OF product_id, sku, price_amount, price_currency, cost_amount, attributes,
+            is_active, reorder_point, deleted_at
+    ON product_variants
+    DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW EXECUTE PROCEDURE record_product_revision();
+
+CREATE CONSTRAINT TRIGGER product_attributes_revision
+    AFTER INSERT OR UPDATE OR DELETE ON product_attributes
+    DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW EXECUTE PROCEDURE record_product_revision();
+
+CREATE CONSTRAINT TRIGGER product_category_junction_revision
+    AFTER INSERT OR UPDATE OR DELETE ON product_category_junction
+    DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW EXECUTE PROCEDURE record_product_revision();
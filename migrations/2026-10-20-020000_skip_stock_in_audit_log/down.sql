@@ -0,0 +1,39 @@
+-- Record stock changes in the audit log again
+CREATE OR REPLACE FUNCTION record_audit_log() RETURNS trigger AS $$
+DECLARE
+    old_row JSONB := CASE WHEN TG_OP <> 'INSERT' THEN to_jsonb(OLD) END;
+    new_row JSONB := CASE WHEN TG_OP <> 'DELETE' THEN to_jsonb(NEW) END;
+    changes JSONB;
+    change_action VARCHAR;
+BEGIN
+    SELECT jsonb_object_agg(key, jsonb_build_object('before', old_row -> key, 'after', new_row -> key))
+    INTO changes
+    FROM jsonb_object_keys(COALESCE(new_row, old_row)) AS key
+    WHERE key NOT IN ('created_at', 'updated_at')
+      AND (old_row -> key) IS DISTINCT FROM (new_row -> key);
+
+    -- Touching only the timestamps is not a change worth recording
+    IF changes IS NULL THEN
+        RETURN NULL;
+    END IF;
+
+    -- Soft deletes and restores are updates of `deleted_at`
+    change_action := CASE
+        WHEN TG_OP = 'INSERT' THEN 'CREATE'
+        WHEN TG_OP = 'DELETE' THEN 'DELETE'
+        WHEN changes ? 'deleted_at' AND new_row ->> 'deleted_at' IS NOT NULL THEN 'DELETE'
+        WHEN changes ? 'deleted_at' THEN 'RESTORE'
+        ELSE 'UPDATE'
+    END;
+
+    INSERT INTO audit_log (entity_type, entity_id, action, actor, diff)
+    VALUES (
+        TG_TABLE_NAME,
+        (COALESCE(new_row, old_row) ->> 'id')::integer,
+        change_action,
+        NULLIF(current_setting('audit.actor', true), ''),
+        changes
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,3 @@
+-- Drop the link between stock movements and orders
+DROP INDEX IF EXISTS idx_stock_movements_order_id;
+ALTER TABLE stock_movements DROP COLUMN IF EXISTS order_id;
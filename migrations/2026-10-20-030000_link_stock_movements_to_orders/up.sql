@@ -0,0 +1,17 @@
+-- Tie the stock an order sells and returns to the order itself; `reference` is free
+-- text that any stock adjustment may reuse
+ALTER TABLE stock_movements ADD COLUMN order_id INTEGER REFERENCES orders(id) ON DELETE SET NULL;
+CREATE INDEX idx_stock_movements_order_id ON stock_movements(order_id) WHERE order_id IS NOT NULL;
+
+-- Sales written at checkout share the order's transaction, and so its timestamp
+UPDATE stock_movements m SET order_id = o.id
+FROM orders o
+WHERE m.movement_type = 'SALE' AND m.reference = o.order_number AND m.reason IS NULL
+  AND m.created_at = o.placed_at;
+
+-- Returns written when the order was cancelled
+UPDATE stock_movements m SET order_id = o.id
+FROM orders o
+WHERE m.movement_type = 'RETURN' AND m.reference = o.order_number
+  AND m.reason = 'Order ' || o.order_number || ' cancelled'
+  AND m.created_at = o.cancelled_at;
@@ -0,0 +1,41 @@
+use async_graphql::{Context, Guard, Result};
+
+use super::{AuthError, Principal, Role};
+
+/// Restricts a field to accounts holding a role, or API keys scoped to act as one
+pub struct RoleGuard {
+    required: Option<Role>,
+}
+
+impl RoleGuard {
+    /// Callers holding `role`; admins always pass
+    pub fn new(role: Role) -> Self {
+        Self {
+            required: Some(role),
+        }
+    }
+
+    /// Any staff role
+    pub fn staff() -> Self {
+        Self { required: None }
+    }
+}
+
+impl Guard for RoleGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        let principal = Principal::current(ctx);
+        if principal.is_anonymous() {
+            return Err(AuthError::Unauthenticated.into());
+        }
+
+        let allowed = match self.required {
+            Some(role) => principal.has_role(role),
+            None => principal.is_staff(),
+        };
+        if !allowed {
+            return Err(AuthError::Forbidden.into());
+        }
+
+        Ok(())
+    }
+}
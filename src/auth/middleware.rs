@@ -0,0 +1,125 @@
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+use std::time::Duration;
+
+use super::{ApiKeyStore, AuthError, Principal, SessionStore, api_keys};
+use crate::rate_limit::{Quota, RateLimiter, retry_after_secs};
+
+/// Resolves bearer tokens, whether session tokens or API keys
+#[derive(Debug, Clone)]
+pub struct Authenticator {
+    sessions: SessionStore,
+    api_keys: ApiKeyStore,
+    key_limiter: RateLimiter,
+}
+
+impl Authenticator {
+    pub fn new(sessions: SessionStore, api_keys: ApiKeyStore) -> Self {
+        Self {
+            sessions,
+            api_keys,
+            key_limiter: RateLimiter::new(),
+        }
+    }
+
+    /// Resolve a token, returning `None` if it is unknown, expired or revoked
+    pub async fn resolve(&self, token: &str) -> Result<Option<Principal>, AuthError> {
+        if token.starts_with(api_keys::KEY_PREFIX) {
+            self.api_keys.authenticate(token).await
+        } else {
+            self.sessions.authenticate(token).await
+        }
+    }
+}
+
+/// Extract the token from an `Authorization: Bearer <token>` header value
+pub fn bearer_token(value: &str) -> Option<&str> {
+    let (scheme, token) = value.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+
+    let token = token.trim();
+    (!token.is_empty()).then_some(token)
+}
+
+fn authorization(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::AUTHORIZATION)?.to_str().ok()
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "errors": [{ "message": message }] }))).into_response()
+}
+
+/// `429 Too Many Requests` telling the client how many whole seconds to back off
+pub fn too_many_requests(retry_after: Duration) -> Response {
+    let seconds = retry_after_secs(retry_after);
+    let mut response = error_response(StatusCode::TOO_MANY_REQUESTS, "rate limit exceeded");
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+    response
+}
+
+/// Resolve the caller from the `Authorization` header and attach a [`Principal`]
+/// to the request. Requests without a token proceed anonymously; a token that does
+/// not resolve is rejected so clients know to log in again. API keys are held to
+/// their own per-minute request limit.
+pub async fn authenticate(
+    State(auth): State<Authenticator>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let principal = match authorization(request.headers()) {
+        None => Principal::Anonymous,
+        Some(value) => {
+            let Some(token) = bearer_token(value) else {
+                return error_response(
+                    StatusCode::UNAUTHORIZED,
+                    "expected an 'Authorization: Bearer <token>' header",
+                );
+            };
+
+            match auth.resolve(token).await {
+                Ok(Some(principal)) => principal,
+                Ok(None) => {
+                    return error_response(
+                        StatusCode::UNAUTHORIZED,
+                        "invalid, expired or revoked token",
+                    );
+                }
+                Err(err) => {
+                    tracing::error!("Failed to resolve bearer token: {}", err);
+                    return error_response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "failed to authenticate request",
+                    );
+                }
+            }
+        }
+    };
+
+    if let Principal::ApiKey {
+        key_id,
+        rate_limit_per_minute,
+        ..
+    } = &principal
+    {
+        let quota = Quota::per_minute(*rate_limit_per_minute);
+        if let Err(retry_after) = auth
+            .key_limiter
+            .check(&format!("key:{}", key_id), quota, 1.0)
+        {
+            return too_many_requests(retry_after);
+        }
+    }
+
+    request.extensions_mut().insert(principal);
+    next.run(request).await
+}
@@ -0,0 +1,223 @@
+//! Authentication: password hashing, session tokens, API keys and the request principal
+
+pub mod api_keys;
+pub mod guard;
+pub mod middleware;
+pub mod password;
+pub mod session;
+pub mod token;
+
+pub use api_keys::{ApiKeyScope, ApiKeyStore};
+pub use guard::RoleGuard;
+pub use middleware::{Authenticator, authenticate};
+pub use session::SessionStore;
+
+use async_graphql::{Context, Enum, Request};
+use std::{any::TypeId, str::FromStr};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("authentication required")]
+    Unauthenticated,
+    #[error("not permitted for this account")]
+    Forbidden,
+    #[error("invalid email or password")]
+    InvalidCredentials,
+    #[error("an account already exists for '{0}'")]
+    EmailTaken(String),
+    #[error("'{0}' is not a valid email address")]
+    InvalidEmail(String),
+    #[error(
+        "password must be at least {} characters",
+        password::MIN_PASSWORD_LENGTH
+    )]
+    WeakPassword,
+    #[error("password hashing failed: {0}")]
+    PasswordHash(String),
+    #[error("unknown value '{0}'")]
+    UnknownValue(String),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// What an account is allowed to do
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// Shops on the storefront
+    Customer,
+    /// Maintains products, variants, categories and media
+    CatalogueEditor,
+    /// Manages stock, locations and fulfilment
+    InventoryManager,
+    /// Everything, including account administration
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Customer => "CUSTOMER",
+            Self::CatalogueEditor => "CATALOGUE_EDITOR",
+            Self::InventoryManager => "INVENTORY_MANAGER",
+            Self::Admin => "ADMIN",
+        }
+    }
+
+    pub fn is_staff(self) -> bool {
+        self != Self::Customer
+    }
+
+    /// Whether this role may act as `required`; admins may act as anyone
+    pub fn grants(self, required: Role) -> bool {
+        self == required || self == Self::Admin
+    }
+}
+
+impl FromStr for Role {
+    type Err = AuthError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "CUSTOMER" => Ok(Self::Customer),
+            "CATALOGUE_EDITOR" => Ok(Self::CatalogueEditor),
+            "INVENTORY_MANAGER" => Ok(Self::InventoryManager),
+            "ADMIN" => Ok(Self::Admin),
+            other => Err(AuthError::UnknownValue(other.to_string())),
+        }
+    }
+}
+
+/// Who is making the current request
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Principal {
+    #[default]
+    Anonymous,
+    Customer {
+        customer_id: i32,
+        session_id: i32,
+        role: Role,
+    },
+    /// An integration calling with an API key
+    ApiKey {
+        key_id: i32,
+        scopes: Vec<ApiKeyScope>,
+        rate_limit_per_minute: u32,
+    },
+}
+
+static ANONYMOUS: Principal = Principal::Anonymous;
+
+impl Principal {
+    /// The principal attached to a GraphQL request, anonymous when none was attached
+    pub fn current<'a>(ctx: &Context<'a>) -> &'a Principal {
+        ctx.data_opt::<Principal>().unwrap_or(&ANONYMOUS)
+    }
+
+    /// The principal attached to a request that has not started executing yet, as
+    /// seen by schema extensions
+    pub fn from_request(request: &Request) -> &Principal {
+        request
+            .data
+            .get(&TypeId::of::<Principal>())
+            .and_then(|principal| principal.downcast_ref::<Principal>())
+            .unwrap_or(&ANONYMOUS)
+    }
+
+    pub fn is_anonymous(&self) -> bool {
+        matches!(self, Self::Anonymous)
+    }
+
+    pub fn customer_id(&self) -> Option<i32> {
+        match self {
+            Self::Customer { customer_id, .. } => Some(*customer_id),
+            Self::Anonymous | Self::ApiKey { .. } => None,
+        }
+    }
+
+    pub fn require_customer(&self) -> Result<i32, AuthError> {
+        self.customer_id().ok_or(AuthError::Unauthenticated)
+    }
+
+    /// The account role; API keys have scopes instead
+    pub fn role(&self) -> Option<Role> {
+        match self {
+            Self::Customer { role, .. } => Some(*role),
+            Self::Anonymous | Self::ApiKey { .. } => None,
+        }
+    }
+
+    /// Whether the caller holds `role`, directly, as an admin or through a key scope
+    pub fn has_role(&self, role: Role) -> bool {
+        match self {
+            Self::Anonymous => false,
+            Self::Customer { role: own, .. } => own.grants(role),
+            Self::ApiKey { scopes, .. } => scopes.iter().any(|scope| scope.grants(role)),
+        }
+    }
+
+    /// Staff accounts, and API keys with a scope granting a staff role, see unpublished
+    /// products, stock figures and costs
+    pub fn is_staff(&self) -> bool {
+        match self {
+            Self::Anonymous => false,
+            Self::Customer { role, .. } => role.is_staff(),
+            Self::ApiKey { scopes, .. } => scopes.iter().any(|scope| {
+                [Role::CatalogueEditor, Role::InventoryManager, Role::Admin]
+                    .into_iter()
+                    .any(|role| scope.grants(role))
+            }),
+        }
+    }
+
+    /// Whether the caller may see a record owned by `owner`; unowned (guest) records
+    /// are reachable by anyone who knows their id
+    pub fn can_access(&self, owner: Option<i32>) -> bool {
+        owner.is_none() || owner == self.customer_id()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_key(scopes: Vec<ApiKeyScope>) -> Principal {
+        Principal::ApiKey {
+            key_id: 1,
+            scopes,
+            rate_limit_per_minute: 60,
+        }
+    }
+
+    #[test]
+    fn read_only_api_keys_are_not_staff() {
+        assert!(!api_key(vec![ApiKeyScope::ReadCatalogue]).is_staff());
+        assert!(!api_key(vec![]).is_staff());
+    }
+
+    #[test]
+    fn api_keys_with_a_write_scope_are_staff() {
+        assert!(api_key(vec![ApiKeyScope::WriteInventory]).is_staff());
+        assert!(
+            api_key(vec![
+                ApiKeyScope::ReadCatalogue,
+                ApiKeyScope::WriteCatalogue
+            ])
+            .is_staff()
+        );
+        assert!(!api_key(vec![ApiKeyScope::WriteCatalogue]).has_role(Role::Admin));
+    }
+
+    #[test]
+    fn customers_are_staff_by_role() {
+        let customer = |role| Principal::Customer {
+            customer_id: 1,
+            session_id: 1,
+            role,
+        };
+
+        assert!(!customer(Role::Customer).is_staff());
+        assert!(customer(Role::CatalogueEditor).is_staff());
+        assert!(customer(Role::Admin).is_staff());
+        assert!(!Principal::Anonymous.is_staff());
+    }
+}
@@ -0,0 +1,41 @@
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+
+use super::AuthError;
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Hash a password with Argon2id into a PHC string.
+///
+/// Hashing is deliberately slow, so it runs on the blocking thread pool.
+pub async fn hash_password(password: String) -> Result<String, AuthError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AuthError::WeakPassword);
+    }
+
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|err| AuthError::PasswordHash(err.to_string()))
+    })
+    .await
+    .map_err(|err| AuthError::PasswordHash(err.to_string()))?
+}
+
+/// Check a password against a stored PHC string
+pub async fn verify_password(password: String, password_hash: String) -> Result<bool, AuthError> {
+    tokio::task::spawn_blocking(move || {
+        let parsed = PasswordHash::new(&password_hash)
+            .map_err(|err| AuthError::PasswordHash(err.to_string()))?;
+
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok())
+    })
+    .await
+    .map_err(|err| AuthError::PasswordHash(err.to_string()))?
+}
@@ -0,0 +1,81 @@
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+use std::time::Duration;
+
+use super::{AuthError, Principal, Role, token};
+
+/// Prefix of every session token
+pub const TOKEN_PREFIX: &str = "st_";
+
+/// A freshly issued session token; the plain token is only ever returned here
+#[derive(Debug, Clone)]
+pub struct IssuedSession {
+    pub token: String,
+    pub expires_at: NaiveDateTime,
+}
+
+/// Issues, resolves and revokes opaque session tokens
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    pool: PgPool,
+    ttl: Duration,
+}
+
+impl SessionStore {
+    pub fn new(pool: PgPool, ttl: Duration) -> Self {
+        Self { pool, ttl }
+    }
+
+    pub async fn issue(&self, customer_id: i32) -> Result<IssuedSession, AuthError> {
+        let token = token::generate(TOKEN_PREFIX);
+
+        let expires_at: NaiveDateTime = sqlx::query_scalar(
+            "INSERT INTO customer_sessions (customer_id, token_hash, expires_at)
+             VALUES ($1, $2, CURRENT_TIMESTAMP + $3::int8 * INTERVAL '1 second')
+             RETURNING expires_at",
+        )
+        .bind(customer_id)
+        .bind(token::hash(&token))
+        .bind(self.ttl.as_secs() as i64)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(IssuedSession { token, expires_at })
+    }
+
+    /// Resolve a bearer token, returning `None` if it is unknown, expired or revoked
+    pub async fn authenticate(&self, token: &str) -> Result<Option<Principal>, AuthError> {
+        let session: Option<(i32, i32, String)> = sqlx::query_as(
+            "SELECT s.id, s.customer_id, c.role
+             FROM customer_sessions s
+             JOIN customers c ON c.id = s.customer_id
+             WHERE s.token_hash = $1 AND s.revoked_at IS NULL
+               AND s.expires_at > CURRENT_TIMESTAMP",
+        )
+        .bind(token::hash(token))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        session
+            .map(|(session_id, customer_id, role)| {
+                Ok(Principal::Customer {
+                    customer_id,
+                    session_id,
+                    role: role.parse::<Role>()?,
+                })
+            })
+            .transpose()
+    }
+
+    pub async fn revoke(&self, session_id: i32) -> Result<bool, AuthError> {
+        let revoked = sqlx::query(
+            "UPDATE customer_sessions SET revoked_at = CURRENT_TIMESTAMP
+             WHERE id = $1 AND revoked_at IS NULL",
+        )
+        .bind(session_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(revoked.rows_affected() > 0)
+    }
+}
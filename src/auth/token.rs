@@ -0,0 +1,26 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+/// A random bearer secret with a recognisable prefix so leaked tokens are easy to
+/// spot in logs and secret scanners
+pub fn generate(prefix: &str) -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+
+    format!("{}{}", prefix, to_hex(&bytes))
+}
+
+/// SHA-256 of a token, hex encoded; tokens carry enough entropy that no salt is needed
+pub fn hash(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
@@ -1,4 +1,10 @@
 use std::env;
+use std::time::Duration;
+
+use crate::persisted_queries::{PersistedQueryBackend, PersistedQueryMode};
+use crate::rate_limit::{Budgets, Quota};
+use crate::slow_query_log::SlowQueryLog;
+use crate::telemetry::LogFormat;
 
 /// Application configuration
 #[derive(Debug, Clone)]
@@ -6,6 +12,104 @@ pub struct AppConfig {
     pub use_database: bool,
     pub database_url: Option<String>,
     pub database_max_connections: u32,
+    /// How long in-flight requests may keep running after a shutdown signal
+    pub shutdown_timeout: Duration,
+    /// How long a login session token stays valid
+    pub session_ttl: Duration,
+    /// Query complexity points each client may spend per minute; `None` when turned off
+    pub query_budget_per_minute: Option<u32>,
+    /// Mutation complexity points each client may spend per minute; `None` when turned off
+    pub mutation_budget_per_minute: Option<u32>,
+    /// Whether clients may send query hashes, and whether only registered ones
+    pub persisted_query_mode: PersistedQueryMode,
+    /// Where automatic persisted queries are kept; strict mode always uses Postgres
+    pub persisted_query_backend: PersistedQueryBackend,
+    /// Queries the in-memory persisted query store holds
+    pub persisted_query_cache_size: usize,
+    /// Catalogue responses kept in the in-process response cache; 0 disables it
+    pub response_cache_capacity: usize,
+    /// Which browser origins may call the API
+    pub cors: CorsConfig,
+    /// Whether logs are written as text or JSON lines
+    pub log_format: LogFormat,
+    /// OTLP/gRPC collector spans are exported to, if any
+    pub otlp_endpoint: Option<String>,
+    /// SQL statements running longer are logged; zero disables the slow query log
+    pub slow_query_threshold: Duration,
+    /// Log the `EXPLAIN (ANALYZE, BUFFERS)` plan of slow queries; development only
+    pub slow_query_explain: bool,
+    /// How often scheduled product publishing and unpublishing is applied; at least a second
+    pub publishing_interval: Duration,
+    /// How often payments of cancelled and refunded orders that failed to release are retried
+    pub payment_release_interval: Duration,
+}
+
+/// Cross-origin policy for browser clients
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorsConfig {
+    /// Exact origins such as `https://shop.example.com`, wildcard subdomains such as
+    /// `https://*.example.com`, or `*` for any origin. Empty allows no cross-origin calls.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    /// Let browsers send cookies and `Authorization` headers cross-origin
+    pub allow_credentials: bool,
+    /// How long browsers may cache a preflight response
+    pub max_age: Duration,
+}
+
+impl CorsConfig {
+    pub fn from_env() -> Self {
+        let allow_credentials = env::var("CORS_ALLOW_CREDENTIALS")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+
+        let max_age_secs = env::var("CORS_MAX_AGE_SECS")
+            .unwrap_or_else(|_| "600".to_string())
+            .parse()
+            .unwrap_or(600);
+
+        Self {
+            allowed_origins: env_list("CORS_ALLOWED_ORIGINS", ""),
+            allowed_methods: env_list("CORS_ALLOWED_METHODS", "GET,POST,OPTIONS"),
+            allowed_headers: env_list(
+                "CORS_ALLOWED_HEADERS",
+                "authorization,content-type,if-none-match",
+            ),
+            allow_credentials,
+            max_age: Duration::from_secs(max_age_secs),
+        }
+    }
+
+    /// Any origin, without credentials; only suitable for local development
+    pub fn permissive() -> Self {
+        Self {
+            allowed_origins: vec!["*".to_string()],
+            ..Self::from_env()
+        }
+    }
+}
+
+/// A per-minute budget from an environment variable; `off` turns the limit off, while
+/// 0 denies every request
+fn env_budget(name: &str, default: u32) -> Option<u32> {
+    match env::var(name) {
+        Ok(value) if value.trim().eq_ignore_ascii_case("off") => None,
+        Ok(value) => Some(value.trim().parse().unwrap_or(default)),
+        Err(_) => Some(default),
+    }
+}
+
+/// Comma-separated values of an environment variable, trimmed, without empty entries
+fn env_list(name: &str, default: &str) -> Vec<String> {
+    env::var(name)
+        .unwrap_or_else(|_| default.to_string())
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .collect()
 }
 
 impl AppConfig {
@@ -23,10 +127,90 @@ impl AppConfig {
             .parse()
             .unwrap_or(10);
 
+        let shutdown_timeout_secs = env::var("SHUTDOWN_TIMEOUT_SECS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse()
+            .unwrap_or(30);
+
+        let session_ttl_hours = env::var("SESSION_TTL_HOURS")
+            .unwrap_or_else(|_| "720".to_string())
+            .parse()
+            .unwrap_or(720);
+
+        let query_budget_per_minute = env_budget("QUERY_BUDGET_PER_MINUTE", 3000);
+        let mutation_budget_per_minute = env_budget("MUTATION_BUDGET_PER_MINUTE", 300);
+
+        let persisted_query_mode = env::var("PERSISTED_QUERIES")
+            .unwrap_or_else(|_| "automatic".to_string())
+            .parse()
+            .unwrap_or(PersistedQueryMode::Automatic);
+
+        let persisted_query_backend = env::var("PERSISTED_QUERY_STORE")
+            .unwrap_or_else(|_| "memory".to_string())
+            .parse()
+            .unwrap_or(PersistedQueryBackend::Memory);
+
+        let persisted_query_cache_size = env::var("PERSISTED_QUERY_CACHE_SIZE")
+            .unwrap_or_else(|_| "1000".to_string())
+            .parse()
+            .unwrap_or(1000);
+
+        let response_cache_capacity = env::var("RESPONSE_CACHE_CAPACITY")
+            .unwrap_or_else(|_| "10000".to_string())
+            .parse()
+            .unwrap_or(10_000);
+
+        let log_format = env::var("LOG_FORMAT")
+            .unwrap_or_else(|_| "text".to_string())
+            .parse()
+            .unwrap_or(LogFormat::Text);
+
+        let otlp_endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .ok()
+            .filter(|endpoint| !endpoint.is_empty());
+
+        let slow_query_threshold_ms = env::var("SLOW_QUERY_THRESHOLD_MS")
+            .unwrap_or_else(|_| "500".to_string())
+            .parse()
+            .unwrap_or(500);
+
+        let slow_query_explain = env::var("SLOW_QUERY_EXPLAIN")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+
+        // A zero period would make the scheduler's interval timer panic
+        let publishing_interval_secs = env::var("PUBLISHING_INTERVAL_SECS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<u64>()
+            .unwrap_or(30)
+            .max(1);
+
+        let payment_release_interval_secs = env::var("PAYMENT_RELEASE_INTERVAL_SECS")
+            .unwrap_or_else(|_| "60".to_string())
+            .parse::<u64>()
+            .unwrap_or(60)
+            .max(1);
+
         Self {
             use_database,
             database_url,
             database_max_connections,
+            shutdown_timeout: Duration::from_secs(shutdown_timeout_secs),
+            session_ttl: Duration::from_secs(session_ttl_hours * 60 * 60),
+            query_budget_per_minute,
+            mutation_budget_per_minute,
+            persisted_query_mode,
+            persisted_query_backend,
+            persisted_query_cache_size,
+            response_cache_capacity,
+            cors: CorsConfig::from_env(),
+            log_format,
+            otlp_endpoint,
+            slow_query_threshold: Duration::from_millis(slow_query_threshold_ms),
+            slow_query_explain,
+            publishing_interval: Duration::from_secs(publishing_interval_secs),
+            payment_release_interval: Duration::from_secs(payment_release_interval_secs),
         }
     }
 
@@ -36,6 +220,21 @@ impl AppConfig {
             use_database: false,
             database_url: None,
             database_max_connections: 10,
+            shutdown_timeout: Duration::from_secs(30),
+            session_ttl: Duration::from_secs(720 * 60 * 60),
+            query_budget_per_minute: Some(3000),
+            mutation_budget_per_minute: Some(300),
+            persisted_query_mode: PersistedQueryMode::Automatic,
+            persisted_query_backend: PersistedQueryBackend::Memory,
+            persisted_query_cache_size: 1000,
+            response_cache_capacity: 10_000,
+            cors: CorsConfig::permissive(),
+            log_format: LogFormat::Text,
+            otlp_endpoint: None,
+            slow_query_threshold: Duration::from_millis(500),
+            slow_query_explain: true,
+            publishing_interval: Duration::from_secs(30),
+            payment_release_interval: Duration::from_secs(60),
         }
     }
 
@@ -45,6 +244,37 @@ impl AppConfig {
             use_database: true,
             database_url: Some(database_url),
             database_max_connections: 10,
+            shutdown_timeout: Duration::from_secs(30),
+            session_ttl: Duration::from_secs(720 * 60 * 60),
+            query_budget_per_minute: Some(3000),
+            mutation_budget_per_minute: Some(300),
+            persisted_query_mode: PersistedQueryMode::Automatic,
+            persisted_query_backend: PersistedQueryBackend::Memory,
+            persisted_query_cache_size: 1000,
+            response_cache_capacity: 10_000,
+            cors: CorsConfig::from_env(),
+            log_format: LogFormat::Json,
+            otlp_endpoint: None,
+            slow_query_threshold: Duration::from_millis(500),
+            slow_query_explain: false,
+            publishing_interval: Duration::from_secs(30),
+            payment_release_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl AppConfig {
+    pub fn budgets(&self) -> Budgets {
+        Budgets {
+            queries: self.query_budget_per_minute.map(Quota::per_minute),
+            mutations: self.mutation_budget_per_minute.map(Quota::per_minute),
+        }
+    }
+
+    pub fn slow_query_log(&self) -> SlowQueryLog {
+        SlowQueryLog {
+            threshold: self.slow_query_threshold,
+            explain: self.slow_query_explain,
         }
     }
 }
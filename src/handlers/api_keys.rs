@@ -0,0 +1,158 @@
+use async_graphql::{Context, InputObject, Object, Result, SimpleObject};
+use chrono::NaiveDateTime;
+use sqlx::{FromRow, PgPool, Row, postgres::PgRow};
+
+use crate::auth::{ApiKeyScope, ApiKeyStore, Principal, Role, RoleGuard};
+
+const API_KEY_COLUMNS: &str =
+    "id, name, key_prefix, scopes, rate_limit_per_minute, last_used_at, revoked_at, created_at";
+
+/// Requests per minute allowed when a key is created without an explicit limit
+const DEFAULT_RATE_LIMIT_PER_MINUTE: i32 = 600;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiKeyError {
+    #[error("an API key needs at least one scope")]
+    NoScopes,
+    #[error("rate limit must be positive, got {0}")]
+    InvalidRateLimit(i32),
+}
+
+/// An API key as shown to admins; the key itself is never stored
+#[derive(Debug, Clone, SimpleObject)]
+pub struct ApiKey {
+    pub id: i32,
+    pub name: String,
+    /// Leading characters of the key, to tell keys apart
+    pub key_prefix: String,
+    pub scopes: Vec<ApiKeyScope>,
+    pub rate_limit_per_minute: i32,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl<'r> FromRow<'r, PgRow> for ApiKey {
+    fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            key_prefix: row.try_get("key_prefix")?,
+            scopes: row
+                .try_get::<Vec<String>, _>("scopes")?
+                .iter()
+                .map(|scope| scope.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|err| sqlx::Error::Decode(Box::new(err)))?,
+            rate_limit_per_minute: row.try_get("rate_limit_per_minute")?,
+            last_used_at: row.try_get("last_used_at")?,
+            revoked_at: row.try_get("revoked_at")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+#[derive(InputObject)]
+pub struct CreateApiKeyInput {
+    pub name: String,
+    pub scopes: Vec<ApiKeyScope>,
+    pub rate_limit_per_minute: Option<i32>,
+}
+
+/// Result of creating an API key
+#[derive(SimpleObject)]
+pub struct CreatedApiKey {
+    /// Send as `Authorization: Bearer <key>`; it cannot be retrieved again
+    pub key: String,
+    pub api_key: ApiKey,
+}
+
+#[derive(Default)]
+pub struct ApiKeyQuery;
+
+#[Object]
+impl ApiKeyQuery {
+    /// All API keys, including revoked ones
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn api_keys(&self, ctx: &Context<'_>) -> Result<Vec<ApiKey>> {
+        let db = ctx.data::<PgPool>()?;
+
+        let keys = sqlx::query_as::<_, ApiKey>(&format!(
+            "SELECT {} FROM api_keys ORDER BY created_at DESC, id DESC",
+            API_KEY_COLUMNS
+        ))
+        .fetch_all(db)
+        .await?;
+
+        Ok(keys)
+    }
+}
+
+#[derive(Default)]
+pub struct ApiKeyMutation;
+
+#[Object]
+impl ApiKeyMutation {
+    /// Issue a key for an unattended integration
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn create_api_key(
+        &self,
+        ctx: &Context<'_>,
+        input: CreateApiKeyInput,
+    ) -> Result<CreatedApiKey> {
+        let db = ctx.data::<PgPool>()?;
+
+        if input.scopes.is_empty() {
+            return Err(ApiKeyError::NoScopes.into());
+        }
+        let rate_limit = input
+            .rate_limit_per_minute
+            .unwrap_or(DEFAULT_RATE_LIMIT_PER_MINUTE);
+        if rate_limit <= 0 {
+            return Err(ApiKeyError::InvalidRateLimit(rate_limit).into());
+        }
+
+        let mut scopes: Vec<&str> = input.scopes.iter().map(|scope| scope.as_str()).collect();
+        scopes.sort_unstable();
+        scopes.dedup();
+
+        let generated = ApiKeyStore::generate();
+        let api_key = sqlx::query_as::<_, ApiKey>(&format!(
+            "INSERT INTO api_keys (name, key_prefix, key_hash, scopes, rate_limit_per_minute, created_by)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             RETURNING {}",
+            API_KEY_COLUMNS
+        ))
+        .bind(input.name.trim())
+        .bind(&generated.key_prefix)
+        .bind(&generated.key_hash)
+        .bind(scopes)
+        .bind(rate_limit)
+        .bind(Principal::current(ctx).customer_id())
+        .fetch_one(db)
+        .await?;
+
+        Ok(CreatedApiKey {
+            key: generated.key,
+            api_key,
+        })
+    }
+
+    /// Revoke a key; requests using it are rejected immediately
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn revoke_api_key(&self, ctx: &Context<'_>, id: i32) -> Result<Option<ApiKey>> {
+        let db = ctx.data::<PgPool>()?;
+
+        let api_key = sqlx::query_as::<_, ApiKey>(&format!(
+            "UPDATE api_keys SET revoked_at = COALESCE(revoked_at, CURRENT_TIMESTAMP)
+             WHERE id = $1
+             RETURNING {}",
+            API_KEY_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(db)
+        .await?;
+
+        Ok(api_key)
+    }
+}
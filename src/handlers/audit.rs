@@ -0,0 +1,189 @@
+use async_graphql::{Context, Enum, Object, Result, SimpleObject};
+use chrono::NaiveDateTime;
+use sqlx::{FromRow, PgConnection, PgPool, Postgres, Row, Transaction, postgres::PgRow};
+use std::str::FromStr;
+
+use crate::auth::{Principal, Role, RoleGuard};
+
+/// Actor recorded for changes applied by the publishing scheduler
+pub const SCHEDULER_ACTOR: &str = "system:publishing";
+
+/// Most entries returned by one `auditTrail` query
+const MAX_AUDIT_ENTRIES: i64 = 500;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditError {
+    #[error("unknown value '{0}'")]
+    UnknownValue(String),
+}
+
+/// Kind of catalogue record an audit entry is about
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AuditEntity {
+    Product,
+    Variant,
+    Category,
+    Media,
+    Attribute,
+}
+
+impl AuditEntity {
+    /// The audited table
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Product => "products",
+            Self::Variant => "product_variants",
+            Self::Category => "categories",
+            Self::Media => "product_media",
+            Self::Attribute => "product_attributes",
+        }
+    }
+}
+
+impl FromStr for AuditEntity {
+    type Err = AuditError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "products" => Ok(Self::Product),
+            "product_variants" => Ok(Self::Variant),
+            "categories" => Ok(Self::Category),
+            "product_media" => Ok(Self::Media),
+            "product_attributes" => Ok(Self::Attribute),
+            other => Err(AuditError::UnknownValue(other.to_string())),
+        }
+    }
+}
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AuditAction {
+    Create,
+    Update,
+    /// Removed, or soft-deleted
+    Delete,
+    /// Brought back after a soft delete
+    Restore,
+}
+
+impl AuditAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Create => "CREATE",
+            Self::Update => "UPDATE",
+            Self::Delete => "DELETE",
+            Self::Restore => "RESTORE",
+        }
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = AuditError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "CREATE" => Ok(Self::Create),
+            "UPDATE" => Ok(Self::Update),
+            "DELETE" => Ok(Self::Delete),
+            "RESTORE" => Ok(Self::Restore),
+            other => Err(AuditError::UnknownValue(other.to_string())),
+        }
+    }
+}
+
+/// One recorded change to a catalogue record
+#[derive(Debug, Clone, SimpleObject)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub entity_type: AuditEntity,
+    pub entity_id: i32,
+    pub action: AuditAction,
+    /// `customer:<id>`, `api_key:<id>` or `system:<task>`; `None` for changes made
+    /// outside the API
+    pub actor: Option<String>,
+    /// `{"column": {"before": ..., "after": ...}}` for every column that changed
+    pub diff: serde_json::Value,
+    pub changed_at: NaiveDateTime,
+}
+
+impl<'r> FromRow<'r, PgRow> for AuditEntry {
+    fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+        let decode_err = |err: AuditError| sqlx::Error::Decode(Box::new(err));
+
+        Ok(Self {
+            id: row.try_get("id")?,
+            entity_type: row
+                .try_get::<String, _>("entity_type")?
+                .parse()
+                .map_err(decode_err)?,
+            entity_id: row.try_get("entity_id")?,
+            action: row
+                .try_get::<String, _>("action")?
+                .parse()
+                .map_err(decode_err)?,
+            actor: row.try_get("actor")?,
+            diff: row.try_get("diff")?,
+            changed_at: row.try_get("changed_at")?,
+        })
+    }
+}
+
+/// How a principal is named in the audit log
+pub fn actor(principal: &Principal) -> Option<String> {
+    match principal {
+        Principal::Anonymous => None,
+        Principal::Customer { customer_id, .. } => Some(format!("customer:{}", customer_id)),
+        Principal::ApiKey { key_id, .. } => Some(format!("api_key:{}", key_id)),
+    }
+}
+
+/// Attribute the catalogue changes of the current transaction to `actor`
+pub async fn set_actor(conn: &mut PgConnection, actor: Option<&str>) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT set_config('audit.actor', $1, true)")
+        .bind(actor.unwrap_or_default())
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Begin a transaction whose catalogue changes are attributed to `principal`
+pub async fn begin(
+    pool: &PgPool,
+    principal: &Principal,
+) -> Result<Transaction<'static, Postgres>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    set_actor(&mut tx, actor(principal).as_deref()).await?;
+    Ok(tx)
+}
+
+#[derive(Default)]
+pub struct AuditQuery;
+
+#[Object]
+impl AuditQuery {
+    /// Recorded changes to the catalogue record `entityId`, newest first. Ids are only
+    /// unique per kind of record, so pass `entityType` to leave out the others.
+    #[graphql(guard = "RoleGuard::new(Role::Admin)", cache_control(private))]
+    async fn audit_trail(
+        &self,
+        ctx: &Context<'_>,
+        entity_id: i32,
+        entity_type: Option<AuditEntity>,
+    ) -> Result<Vec<AuditEntry>> {
+        let db = ctx.data::<PgPool>()?;
+
+        let entries = sqlx::query_as::<_, AuditEntry>(
+            "SELECT id, entity_type, entity_id, action, actor, diff, changed_at
+             FROM audit_log
+             WHERE entity_id = $1 AND ($2::varchar IS NULL OR entity_type = $2)
+             ORDER BY changed_at DESC, id DESC
+             LIMIT $3",
+        )
+        .bind(entity_id)
+        .bind(entity_type.map(AuditEntity::as_str))
+        .bind(MAX_AUDIT_ENTRIES)
+        .fetch_all(db)
+        .await?;
+
+        Ok(entries)
+    }
+}
@@ -0,0 +1,541 @@
+use async_graphql::{ComplexObject, Context, Enum, InputObject, Object, Result, SimpleObject};
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use sqlx::{PgConnection, PgPool, Row, postgres::PgRow};
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::auth::Principal;
+use crate::handlers::inventory::AVAILABLE_QUANTITY_SQL;
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CartStatus {
+    Open,
+    CheckedOut,
+    Abandoned,
+}
+
+impl CartStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Open => "OPEN",
+            Self::CheckedOut => "CHECKED_OUT",
+            Self::Abandoned => "ABANDONED",
+        }
+    }
+}
+
+impl FromStr for CartStatus {
+    type Err = CartError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "OPEN" => Ok(Self::Open),
+            "CHECKED_OUT" => Ok(Self::CheckedOut),
+            "ABANDONED" => Ok(Self::Abandoned),
+            other => Err(CartError::UnknownValue(other.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CartError {
+    #[error("cart {0} not found")]
+    UnknownCart(Uuid),
+    #[error("cart {0} is no longer open")]
+    CartNotOpen(Uuid),
+    #[error("cart line {0} not found")]
+    UnknownLine(i32),
+    #[error("variant with sku '{0}' not found")]
+    UnknownSku(String),
+    #[error("variant '{0}' is not available for sale")]
+    VariantInactive(String),
+    #[error("variant '{sku}' is priced in {variant_currency}, but the cart uses {cart_currency}")]
+    CurrencyMismatch {
+        sku: String,
+        variant_currency: String,
+        cart_currency: String,
+    },
+    #[error("insufficient stock for '{sku}': requested {requested}, available {available}")]
+    InsufficientStock {
+        sku: String,
+        requested: i32,
+        available: i32,
+    },
+    #[error("quantity must be positive")]
+    InvalidQuantity,
+    #[error("invalid currency '{0}': expected a three-letter ISO 4217 code")]
+    InvalidCurrency(String),
+    #[error("unknown value '{0}'")]
+    UnknownValue(String),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// A cart line resolved against the current variant price and stock
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(complex)]
+pub struct CartLine {
+    pub id: i32,
+    pub variant_id: i32,
+    pub product_id: i32,
+    pub sku: String,
+    pub product_name: String,
+    pub quantity: i32,
+    #[graphql(skip)]
+    pub unit_price_amount: Decimal,
+    pub price_currency: String,
+    /// Both the variant and its product are on sale
+    pub is_active: bool,
+    pub available_quantity: i32,
+}
+
+#[ComplexObject]
+impl CartLine {
+    async fn unit_price_amount(&self) -> String {
+        self.unit_price_amount.to_string()
+    }
+
+    async fn line_total_amount(&self) -> String {
+        self.line_total().to_string()
+    }
+}
+
+impl CartLine {
+    fn from_row(row: &PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            variant_id: row.try_get("variant_id")?,
+            product_id: row.try_get("product_id")?,
+            sku: row.try_get("sku")?,
+            product_name: row.try_get("product_name")?,
+            quantity: row.try_get("quantity")?,
+            unit_price_amount: row.try_get("price_amount")?,
+            price_currency: row.try_get("price_currency")?,
+            is_active: row.try_get("is_active")?,
+            available_quantity: row.try_get("available_quantity")?,
+        })
+    }
+
+    pub fn line_total(&self) -> Decimal {
+        self.unit_price_amount * Decimal::from(self.quantity)
+    }
+
+    /// Whether the line can currently be bought as is
+    pub fn is_purchasable(&self) -> bool {
+        self.is_active && self.available_quantity >= self.quantity
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cart {
+    pub id: Uuid,
+    pub customer_id: Option<i32>,
+    pub currency: String,
+    pub status: CartStatus,
+    pub lines: Vec<CartLine>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl Cart {
+    pub fn subtotal(&self) -> Decimal {
+        self.lines.iter().map(CartLine::line_total).sum()
+    }
+}
+
+#[Object]
+impl Cart {
+    async fn id(&self) -> Uuid {
+        self.id
+    }
+
+    async fn customer_id(&self) -> Option<i32> {
+        self.customer_id
+    }
+
+    async fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    async fn status(&self) -> CartStatus {
+        self.status
+    }
+
+    async fn lines(&self) -> &[CartLine] {
+        &self.lines
+    }
+
+    async fn item_count(&self) -> i32 {
+        self.lines.iter().map(|line| line.quantity).sum()
+    }
+
+    /// Sum of line totals at current prices, as a decimal string
+    async fn subtotal_amount(&self) -> String {
+        self.subtotal().to_string()
+    }
+
+    /// False when any line is inactive or exceeds available stock
+    async fn is_purchasable(&self) -> bool {
+        !self.lines.is_empty() && self.lines.iter().all(CartLine::is_purchasable)
+    }
+
+    async fn created_at(&self) -> NaiveDateTime {
+        self.created_at
+    }
+
+    async fn updated_at(&self) -> NaiveDateTime {
+        self.updated_at
+    }
+}
+
+#[derive(InputObject)]
+pub struct AddCartLineInput {
+    pub cart_id: Uuid,
+    pub sku: String,
+    pub quantity: i32,
+}
+
+#[derive(InputObject)]
+pub struct UpdateCartLineInput {
+    pub cart_id: Uuid,
+    pub line_id: i32,
+    /// Setting the quantity to zero removes the line
+    pub quantity: i32,
+}
+
+/// Load a cart and resolve its lines against current variant data
+pub async fn load_cart(conn: &mut PgConnection, cart_id: Uuid) -> Result<Option<Cart>, CartError> {
+    let Some(row) = sqlx::query(
+        "SELECT id, customer_id, currency, status, created_at, updated_at
+         FROM carts WHERE id = $1",
+    )
+    .bind(cart_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let sql = format!(
+        "SELECT l.id, l.variant_id, l.quantity, product_variants.product_id,
+                product_variants.sku, products.name AS product_name,
+                product_variants.price_amount, product_variants.price_currency,
+                (product_variants.is_active AND product_variants.deleted_at IS NULL
+                 AND products.status = 'ACTIVE' AND products.deleted_at IS NULL) AS is_active, {}
+         FROM cart_lines l
+         JOIN product_variants ON product_variants.id = l.variant_id
+         JOIN products ON products.id = product_variants.product_id
+         WHERE l.cart_id = $1
+         ORDER BY l.id",
+        AVAILABLE_QUANTITY_SQL
+    );
+    let lines = sqlx::query(&sql)
+        .bind(cart_id)
+        .try_map(|row: PgRow| CartLine::from_row(&row))
+        .fetch_all(&mut *conn)
+        .await?;
+
+    Ok(Some(Cart {
+        id: row.try_get("id")?,
+        customer_id: row.try_get("customer_id")?,
+        currency: row.try_get("currency")?,
+        status: row
+            .try_get::<String, _>("status")?
+            .parse()
+            .map_err(|err| sqlx::Error::Decode(Box::new(err)))?,
+        lines,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    }))
+}
+
+/// Lock an open cart for the rest of the transaction and mark it as modified,
+/// returning its currency.
+///
+/// Carts owned by someone else look like unknown carts. A guest cart touched by a
+/// logged-in customer becomes theirs.
+async fn lock_open_cart(
+    conn: &mut PgConnection,
+    cart_id: Uuid,
+    principal: &Principal,
+) -> Result<String, CartError> {
+    let row = sqlx::query(
+        "UPDATE carts SET updated_at = CURRENT_TIMESTAMP, customer_id = COALESCE(customer_id, $2)
+         WHERE id = $1 AND (customer_id IS NULL OR customer_id = $2)
+         RETURNING currency, status",
+    )
+    .bind(cart_id)
+    .bind(principal.customer_id())
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(CartError::UnknownCart(cart_id))?;
+
+    if row.get::<String, _>("status") != CartStatus::Open.as_str() {
+        return Err(CartError::CartNotOpen(cart_id));
+    }
+
+    Ok(row.get("currency"))
+}
+
+/// Uppercase an ISO 4217 currency code, rejecting anything not made of three letters
+fn parse_currency(currency: &str) -> Result<String, CartError> {
+    if currency.len() == 3 && currency.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(currency.to_ascii_uppercase())
+    } else {
+        Err(CartError::InvalidCurrency(currency.to_string()))
+    }
+}
+
+/// Check that a variant can be sold in `quantity` units in the cart currency
+async fn validate_variant(
+    conn: &mut PgConnection,
+    variant_id: i32,
+    quantity: i32,
+    cart_currency: &str,
+) -> Result<(), CartError> {
+    let sql = format!(
+        "SELECT sku, price_currency, {},
+                (is_active AND deleted_at IS NULL AND EXISTS (
+                    SELECT 1 FROM products p
+                    WHERE p.id = product_variants.product_id AND p.status = 'ACTIVE'
+                      AND p.deleted_at IS NULL
+                )) AS is_active
+         FROM product_variants WHERE id = $1",
+        AVAILABLE_QUANTITY_SQL
+    );
+    let row = sqlx::query(&sql)
+        .bind(variant_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let sku: String = row.get("sku");
+    if !row.get::<bool, _>("is_active") {
+        return Err(CartError::VariantInactive(sku));
+    }
+
+    let variant_currency: String = row.get("price_currency");
+    if variant_currency != cart_currency {
+        return Err(CartError::CurrencyMismatch {
+            sku,
+            variant_currency,
+            cart_currency: cart_currency.to_string(),
+        });
+    }
+
+    let available: i32 = row.get("available_quantity");
+    if available < quantity {
+        return Err(CartError::InsufficientStock {
+            sku,
+            requested: quantity,
+            available,
+        });
+    }
+
+    Ok(())
+}
+
+async fn load_existing_cart(conn: &mut PgConnection, cart_id: Uuid) -> Result<Cart, CartError> {
+    load_cart(conn, cart_id)
+        .await?
+        .ok_or(CartError::UnknownCart(cart_id))
+}
+
+#[derive(Default)]
+pub struct CartQuery;
+
+#[Object]
+impl CartQuery {
+    async fn cart(&self, ctx: &Context<'_>, id: Uuid) -> Result<Option<Cart>> {
+        let db = ctx.data::<PgPool>()?;
+        let principal = Principal::current(ctx);
+        let mut conn = db.acquire().await?;
+
+        let cart = load_cart(&mut conn, id).await?;
+
+        Ok(cart.filter(|cart| principal.can_access(cart.customer_id)))
+    }
+
+    /// Open carts belonging to the logged-in customer, most recently updated first
+    async fn my_carts(&self, ctx: &Context<'_>) -> Result<Vec<Cart>> {
+        let customer_id = Principal::current(ctx).require_customer()?;
+        let db = ctx.data::<PgPool>()?;
+        let mut conn = db.acquire().await?;
+
+        let ids: Vec<Uuid> = sqlx::query_scalar(
+            "SELECT id FROM carts WHERE customer_id = $1 AND status = $2
+             ORDER BY updated_at DESC",
+        )
+        .bind(customer_id)
+        .bind(CartStatus::Open.as_str())
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut carts = Vec::with_capacity(ids.len());
+        for id in ids {
+            carts.push(load_existing_cart(&mut conn, id).await?);
+        }
+
+        Ok(carts)
+    }
+}
+
+#[derive(Default)]
+pub struct CartMutation;
+
+#[Object]
+impl CartMutation {
+    /// Create a cart owned by the logged-in customer, or a guest cart
+    async fn create_cart(&self, ctx: &Context<'_>, currency: Option<String>) -> Result<Cart> {
+        let currency = currency.as_deref().map(parse_currency).transpose()?;
+        let db = ctx.data::<PgPool>()?;
+        let principal = Principal::current(ctx);
+        let mut conn = db.acquire().await?;
+
+        let cart_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO carts (currency, customer_id) VALUES (COALESCE($1, 'USD'), $2)
+             RETURNING id",
+        )
+        .bind(currency)
+        .bind(principal.customer_id())
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(load_existing_cart(&mut conn, cart_id).await?)
+    }
+
+    /// Add units of a variant, merging with an existing line for the same variant
+    async fn add_cart_line(&self, ctx: &Context<'_>, input: AddCartLineInput) -> Result<Cart> {
+        if input.quantity <= 0 {
+            return Err(CartError::InvalidQuantity.into());
+        }
+        let db = ctx.data::<PgPool>()?;
+
+        let mut tx = db.begin().await?;
+        let currency = lock_open_cart(&mut tx, input.cart_id, Principal::current(ctx)).await?;
+
+        let variant_id: i32 = sqlx::query_scalar(
+            "SELECT id FROM product_variants WHERE sku = $1 AND deleted_at IS NULL",
+        )
+        .bind(&input.sku)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| CartError::UnknownSku(input.sku.clone()))?;
+
+        let existing: i32 = sqlx::query_scalar(
+            "SELECT quantity FROM cart_lines WHERE cart_id = $1 AND variant_id = $2",
+        )
+        .bind(input.cart_id)
+        .bind(variant_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .unwrap_or(0);
+
+        validate_variant(&mut tx, variant_id, existing + input.quantity, &currency).await?;
+
+        sqlx::query(
+            "INSERT INTO cart_lines (cart_id, variant_id, quantity) VALUES ($1, $2, $3)
+             ON CONFLICT (cart_id, variant_id)
+             DO UPDATE SET quantity = cart_lines.quantity + EXCLUDED.quantity",
+        )
+        .bind(input.cart_id)
+        .bind(variant_id)
+        .bind(input.quantity)
+        .execute(&mut *tx)
+        .await?;
+
+        let cart = load_existing_cart(&mut tx, input.cart_id).await?;
+        tx.commit().await?;
+
+        Ok(cart)
+    }
+
+    async fn update_cart_line(
+        &self,
+        ctx: &Context<'_>,
+        input: UpdateCartLineInput,
+    ) -> Result<Cart> {
+        if input.quantity < 0 {
+            return Err(CartError::InvalidQuantity.into());
+        }
+        let db = ctx.data::<PgPool>()?;
+
+        let mut tx = db.begin().await?;
+        let currency = lock_open_cart(&mut tx, input.cart_id, Principal::current(ctx)).await?;
+
+        let variant_id: i32 =
+            sqlx::query_scalar("SELECT variant_id FROM cart_lines WHERE id = $1 AND cart_id = $2")
+                .bind(input.line_id)
+                .bind(input.cart_id)
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or(CartError::UnknownLine(input.line_id))?;
+
+        if input.quantity == 0 {
+            sqlx::query("DELETE FROM cart_lines WHERE id = $1")
+                .bind(input.line_id)
+                .execute(&mut *tx)
+                .await?;
+        } else {
+            validate_variant(&mut tx, variant_id, input.quantity, &currency).await?;
+
+            sqlx::query("UPDATE cart_lines SET quantity = $1 WHERE id = $2")
+                .bind(input.quantity)
+                .bind(input.line_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let cart = load_existing_cart(&mut tx, input.cart_id).await?;
+        tx.commit().await?;
+
+        Ok(cart)
+    }
+
+    async fn remove_cart_line(
+        &self,
+        ctx: &Context<'_>,
+        cart_id: Uuid,
+        line_id: i32,
+    ) -> Result<Cart> {
+        let db = ctx.data::<PgPool>()?;
+
+        let mut tx = db.begin().await?;
+        lock_open_cart(&mut tx, cart_id, Principal::current(ctx)).await?;
+
+        let removed = sqlx::query("DELETE FROM cart_lines WHERE id = $1 AND cart_id = $2")
+            .bind(line_id)
+            .bind(cart_id)
+            .execute(&mut *tx)
+            .await?;
+        if removed.rows_affected() == 0 {
+            return Err(CartError::UnknownLine(line_id).into());
+        }
+
+        let cart = load_existing_cart(&mut tx, cart_id).await?;
+        tx.commit().await?;
+
+        Ok(cart)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn currencies_are_uppercased() {
+        assert_eq!(parse_currency("eur").unwrap(), "EUR");
+        assert_eq!(parse_currency("Usd").unwrap(), "USD");
+    }
+
+    #[test]
+    fn currencies_must_be_three_letters() {
+        for currency in ["", "US", "USDT", "U$D", "12A", " EUR", "ÉUR"] {
+            assert!(matches!(
+                parse_currency(currency),
+                Err(CartError::InvalidCurrency(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,571 @@
+use async_graphql::{Context, Enum, Object, Result};
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use std::str::FromStr;
+
+use crate::auth::{AuthError, Principal, Role, RoleGuard};
+use crate::handlers::{
+    audit,
+    inventory::AVAILABLE_QUANTITY_SQL,
+    queries::{PRODUCT_COLUMNS, ProductGQL},
+    variant_loader::VariantGQL,
+};
+
+/// Lifecycle state of a product
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProductStatus {
+    /// Being prepared; only staff can see it
+    Draft,
+    /// On sale in the storefront
+    Active,
+    /// Withdrawn from sale, kept for order history
+    Archived,
+    /// Ready and waiting to go on sale
+    Scheduled,
+}
+
+impl ProductStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Draft => "DRAFT",
+            Self::Active => "ACTIVE",
+            Self::Archived => "ARCHIVED",
+            Self::Scheduled => "SCHEDULED",
+        }
+    }
+
+    /// Whether a product in this state may move to `next`
+    pub fn can_transition_to(self, next: Self) -> bool {
+        matches!(
+            (self, next),
+            (Self::Draft, Self::Scheduled | Self::Active | Self::Archived)
+                | (Self::Scheduled, Self::Draft | Self::Active | Self::Archived)
+                | (Self::Active, Self::Draft | Self::Archived)
+                | (Self::Archived, Self::Draft)
+        )
+    }
+}
+
+impl FromStr for ProductStatus {
+    type Err = CatalogError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "DRAFT" => Ok(Self::Draft),
+            "ACTIVE" => Ok(Self::Active),
+            "ARCHIVED" => Ok(Self::Archived),
+            "SCHEDULED" => Ok(Self::Scheduled),
+            other => Err(CatalogError::UnknownValue(other.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CatalogError {
+    #[error("unknown product {0}")]
+    UnknownProduct(i32),
+    #[error("unknown variant {0}")]
+    UnknownVariant(i32),
+    #[error("unknown category {0}")]
+    UnknownCategory(i32),
+    #[error("unknown media {0}")]
+    UnknownMedia(i32),
+    #[error("product {0} is deleted; restore it first")]
+    ProductDeleted(i32),
+    #[error("product {product_id} has no revision {revision}")]
+    UnknownRevision { product_id: i32, revision: i32 },
+    #[error("cannot move product from {} to {}", from.as_str(), to.as_str())]
+    InvalidTransition {
+        from: ProductStatus,
+        to: ProductStatus,
+    },
+    #[error("invalid schedule: {0}")]
+    InvalidSchedule(String),
+    #[error("slug '{0}' is already used by another product")]
+    SlugTaken(String),
+    #[error("sku '{0}' is already used by another variant")]
+    SkuTaken(String),
+    #[error("unknown value '{0}'")]
+    UnknownValue(String),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+pub fn decode_err(err: CatalogError) -> sqlx::Error {
+    sqlx::Error::Decode(Box::new(err))
+}
+
+/// Whether the caller asked for deleted catalogue entries, which only admins may see
+pub fn check_include_deleted(
+    ctx: &Context<'_>,
+    requested: Option<bool>,
+) -> Result<bool, AuthError> {
+    if !requested.unwrap_or(false) {
+        return Ok(false);
+    }
+    if !Principal::current(ctx).has_role(Role::Admin) {
+        return Err(AuthError::Forbidden);
+    }
+    Ok(true)
+}
+
+/// Lock a product for the rest of the transaction; deleted products look unknown
+pub async fn lock_product(
+    conn: &mut PgConnection,
+    product_id: i32,
+) -> Result<ProductGQL, CatalogError> {
+    sqlx::query_as::<_, ProductGQL>(&format!(
+        "SELECT {} FROM products WHERE id = $1 AND deleted_at IS NULL FOR NO KEY UPDATE",
+        PRODUCT_COLUMNS
+    ))
+    .bind(product_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(CatalogError::UnknownProduct(product_id))
+}
+
+/// Move a locked product to a new state
+pub async fn transition_product(
+    conn: &mut PgConnection,
+    product: &ProductGQL,
+    to_status: ProductStatus,
+) -> Result<ProductGQL, CatalogError> {
+    let from_status = product.status.unwrap_or(ProductStatus::Draft);
+    if !from_status.can_transition_to(to_status) {
+        return Err(CatalogError::InvalidTransition {
+            from: from_status,
+            to: to_status,
+        });
+    }
+    if to_status == ProductStatus::Scheduled && product.publish_at.is_none() {
+        return Err(CatalogError::InvalidSchedule(
+            "set publishAt with scheduleProduct to schedule a product".to_string(),
+        ));
+    }
+
+    // An archived product keeps no pending withdrawal, which would otherwise archive it
+    // again as soon as it is back on sale
+    let updated = sqlx::query_as::<_, ProductGQL>(&format!(
+        "UPDATE products
+         SET status = $1, unpublish_at = CASE WHEN $1 = 'ARCHIVED' THEN NULL ELSE unpublish_at END
+         WHERE id = $2 RETURNING {}",
+        PRODUCT_COLUMNS
+    ))
+    .bind(to_status.as_str())
+    .bind(product.id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(updated)
+}
+
+#[derive(Default)]
+pub struct CatalogMutation;
+
+#[Object]
+impl CatalogMutation {
+    /// Move a product through its lifecycle: drafts may be scheduled, activated or
+    /// archived, scheduled products activated, active products archived or returned to
+    /// draft, and archived products returned to draft
+    #[graphql(guard = "RoleGuard::new(Role::CatalogueEditor)")]
+    async fn set_product_status(
+        &self,
+        ctx: &Context<'_>,
+        product_id: i32,
+        status: ProductStatus,
+    ) -> Result<ProductGQL> {
+        let db = ctx.data::<PgPool>()?;
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+
+        let product = lock_product(&mut tx, product_id).await?;
+        let updated = transition_product(&mut tx, &product, status).await?;
+
+        tx.commit().await?;
+        Ok(updated)
+    }
+
+    /// Set or clear (with `null`) when a product goes on sale and when it is withdrawn,
+    /// in UTC. Giving a draft a `publishAt` schedules it; clearing the `publishAt` of a
+    /// scheduled product returns it to draft.
+    #[graphql(guard = "RoleGuard::new(Role::CatalogueEditor)")]
+    async fn schedule_product(
+        &self,
+        ctx: &Context<'_>,
+        product_id: i32,
+        publish_at: Option<NaiveDateTime>,
+        unpublish_at: Option<NaiveDateTime>,
+    ) -> Result<ProductGQL> {
+        let db = ctx.data::<PgPool>()?;
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+
+        let product = lock_product(&mut tx, product_id).await?;
+        let updated = schedule_product(&mut tx, &product, publish_at, unpublish_at).await?;
+
+        tx.commit().await?;
+        Ok(updated)
+    }
+
+    /// Delete a product together with its variants and media. Nothing is removed from
+    /// the database; `restoreProduct` brings it all back.
+    #[graphql(guard = "RoleGuard::new(Role::CatalogueEditor)")]
+    async fn delete_product(&self, ctx: &Context<'_>, product_id: i32) -> Result<ProductGQL> {
+        let db = ctx.data::<PgPool>()?;
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+
+        let deleted = delete_product(&mut tx, product_id).await?;
+
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    /// Restore a deleted product with the variants and media deleted along with it
+    #[graphql(guard = "RoleGuard::new(Role::CatalogueEditor)")]
+    async fn restore_product(&self, ctx: &Context<'_>, product_id: i32) -> Result<ProductGQL> {
+        let db = ctx.data::<PgPool>()?;
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+
+        let restored = restore_product(&mut tx, product_id).await?;
+
+        tx.commit().await?;
+        Ok(restored)
+    }
+
+    /// Delete a variant; it can no longer be added to carts
+    #[graphql(guard = "RoleGuard::new(Role::CatalogueEditor)")]
+    async fn delete_variant(&self, ctx: &Context<'_>, variant_id: i32) -> Result<VariantGQL> {
+        let db = ctx.data::<PgPool>()?;
+        let sql = format!(
+            "UPDATE product_variants SET deleted_at = NOW() AT TIME ZONE 'UTC'
+             WHERE id = $1 AND deleted_at IS NULL
+             RETURNING {}",
+            variant_columns()
+        );
+
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+        let variant = sqlx::query_as::<_, VariantGQL>(&sql)
+            .bind(variant_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(CatalogError::UnknownVariant(variant_id))?;
+
+        tx.commit().await?;
+        Ok(variant)
+    }
+
+    /// Restore a deleted variant of a product that is not itself deleted
+    #[graphql(guard = "RoleGuard::new(Role::CatalogueEditor)")]
+    async fn restore_variant(&self, ctx: &Context<'_>, variant_id: i32) -> Result<VariantGQL> {
+        let db = ctx.data::<PgPool>()?;
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+
+        lock_live_parent(&mut tx, "product_variants", variant_id)
+            .await?
+            .ok_or(CatalogError::UnknownVariant(variant_id))?;
+
+        let sql = format!(
+            "UPDATE product_variants SET deleted_at = NULL WHERE id = $1 RETURNING {}",
+            variant_columns()
+        );
+        let variant = sqlx::query_as::<_, VariantGQL>(&sql)
+            .bind(variant_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(variant)
+    }
+
+    /// Delete a category; its products stay in the catalogue but are no longer listed
+    /// under it
+    #[graphql(guard = "RoleGuard::new(Role::CatalogueEditor)")]
+    async fn delete_category(&self, ctx: &Context<'_>, category_id: i32) -> Result<bool> {
+        let db = ctx.data::<PgPool>()?;
+
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+        let deleted = sqlx::query(
+            "UPDATE categories SET deleted_at = NOW() AT TIME ZONE 'UTC'
+             WHERE id = $1 AND deleted_at IS NULL",
+        )
+        .bind(category_id)
+        .execute(&mut *tx)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(CatalogError::UnknownCategory(category_id).into());
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Restore a deleted category
+    #[graphql(guard = "RoleGuard::new(Role::CatalogueEditor)")]
+    async fn restore_category(&self, ctx: &Context<'_>, category_id: i32) -> Result<bool> {
+        let db = ctx.data::<PgPool>()?;
+
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+        let restored = sqlx::query("UPDATE categories SET deleted_at = NULL WHERE id = $1")
+            .bind(category_id)
+            .execute(&mut *tx)
+            .await?;
+        if restored.rows_affected() == 0 {
+            return Err(CatalogError::UnknownCategory(category_id).into());
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Delete a media item of a product
+    #[graphql(guard = "RoleGuard::new(Role::CatalogueEditor)")]
+    async fn delete_media(&self, ctx: &Context<'_>, media_id: i32) -> Result<bool> {
+        let db = ctx.data::<PgPool>()?;
+
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+        let deleted = sqlx::query(
+            "UPDATE product_media SET deleted_at = NOW() AT TIME ZONE 'UTC'
+             WHERE id = $1 AND deleted_at IS NULL",
+        )
+        .bind(media_id)
+        .execute(&mut *tx)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(CatalogError::UnknownMedia(media_id).into());
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Restore a deleted media item of a product that is not itself deleted
+    #[graphql(guard = "RoleGuard::new(Role::CatalogueEditor)")]
+    async fn restore_media(&self, ctx: &Context<'_>, media_id: i32) -> Result<bool> {
+        let db = ctx.data::<PgPool>()?;
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+
+        lock_live_parent(&mut tx, "product_media", media_id)
+            .await?
+            .ok_or(CatalogError::UnknownMedia(media_id))?;
+
+        sqlx::query("UPDATE product_media SET deleted_at = NULL WHERE id = $1")
+            .bind(media_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+}
+
+/// Columns of a variant returned by the catalogue mutations
+fn variant_columns() -> String {
+    format!(
+        "id, product_id, sku, price_amount, price_currency, cost_amount, stock_quantity, {},
+         reorder_point, is_active, attributes, deleted_at",
+        AVAILABLE_QUANTITY_SQL
+    )
+}
+
+/// Lock the product owning a row of `table` (variants or media), so it cannot be
+/// deleted meanwhile; `None` when there is no such row. Children of a deleted product
+/// are only restored with it.
+async fn lock_live_parent(
+    conn: &mut PgConnection,
+    table: &str,
+    id: i32,
+) -> Result<Option<i32>, CatalogError> {
+    let parent: Option<(i32, bool)> = sqlx::query_as(&format!(
+        "SELECT p.id, p.deleted_at IS NOT NULL FROM {} c
+         JOIN products p ON p.id = c.product_id
+         WHERE c.id = $1
+         FOR NO KEY UPDATE OF p",
+        table
+    ))
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    match parent {
+        Some((product_id, true)) => Err(CatalogError::ProductDeleted(product_id)),
+        Some((product_id, false)) => Ok(Some(product_id)),
+        None => Ok(None),
+    }
+}
+
+/// Delete a product, and with it every variant and media item still live. Children
+/// share the product's `deleted_at`, which is how a restore tells them apart from
+/// children deleted on their own earlier.
+pub async fn delete_product(
+    conn: &mut PgConnection,
+    product_id: i32,
+) -> Result<ProductGQL, CatalogError> {
+    let product = lock_product(conn, product_id).await?;
+
+    let deleted = sqlx::query_as::<_, ProductGQL>(&format!(
+        "UPDATE products SET deleted_at = NOW() AT TIME ZONE 'UTC' WHERE id = $1 RETURNING {}",
+        PRODUCT_COLUMNS
+    ))
+    .bind(product.id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    for table in ["product_variants", "product_media"] {
+        sqlx::query(&format!(
+            "UPDATE {} SET deleted_at = $1 WHERE product_id = $2 AND deleted_at IS NULL",
+            table
+        ))
+        .bind(deleted.deleted_at)
+        .bind(product.id)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(deleted)
+}
+
+/// Restore a deleted product and the children deleted together with it; restoring a
+/// product that is not deleted changes nothing
+pub async fn restore_product(
+    conn: &mut PgConnection,
+    product_id: i32,
+) -> Result<ProductGQL, CatalogError> {
+    let product = sqlx::query_as::<_, ProductGQL>(&format!(
+        "SELECT {} FROM products WHERE id = $1 FOR NO KEY UPDATE",
+        PRODUCT_COLUMNS
+    ))
+    .bind(product_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(CatalogError::UnknownProduct(product_id))?;
+    let Some(deleted_at) = product.deleted_at else {
+        return Ok(product);
+    };
+
+    for table in ["product_variants", "product_media"] {
+        sqlx::query(&format!(
+            "UPDATE {} SET deleted_at = NULL WHERE product_id = $1 AND deleted_at = $2",
+            table
+        ))
+        .bind(product.id)
+        .bind(deleted_at)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    let restored = sqlx::query_as::<_, ProductGQL>(&format!(
+        "UPDATE products SET deleted_at = NULL WHERE id = $1 RETURNING {}",
+        PRODUCT_COLUMNS
+    ))
+    .bind(product.id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(restored)
+}
+
+/// Replace the publishing schedule of a locked product, moving it between DRAFT and
+/// SCHEDULED to match
+pub async fn schedule_product(
+    conn: &mut PgConnection,
+    product: &ProductGQL,
+    publish_at: Option<NaiveDateTime>,
+    unpublish_at: Option<NaiveDateTime>,
+) -> Result<ProductGQL, CatalogError> {
+    if matches!((publish_at, unpublish_at), (Some(publish), Some(unpublish)) if unpublish <= publish)
+    {
+        return Err(CatalogError::InvalidSchedule(
+            "unpublishAt must be after publishAt".to_string(),
+        ));
+    }
+    if unpublish_at.is_some_and(|unpublish_at| unpublish_at <= Utc::now().naive_utc()) {
+        return Err(CatalogError::InvalidSchedule(
+            "unpublishAt must be in the future".to_string(),
+        ));
+    }
+
+    let from_status = product.status.unwrap_or(ProductStatus::Draft);
+    let to_status = match (from_status, publish_at) {
+        (ProductStatus::Draft | ProductStatus::Scheduled, Some(_)) => ProductStatus::Scheduled,
+        (ProductStatus::Scheduled, None) => ProductStatus::Draft,
+        (status, None) => status,
+        (_, Some(_)) => {
+            return Err(CatalogError::InvalidSchedule(
+                "only drafts can be scheduled for publishing".to_string(),
+            ));
+        }
+    };
+
+    let updated = sqlx::query_as::<_, ProductGQL>(&format!(
+        "UPDATE products SET status = $1, publish_at = $2, unpublish_at = $3
+         WHERE id = $4 RETURNING {}",
+        PRODUCT_COLUMNS
+    ))
+    .bind(to_status.as_str())
+    .bind(publish_at)
+    .bind(unpublish_at)
+    .bind(product.id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [ProductStatus; 4] = [
+        ProductStatus::Draft,
+        ProductStatus::Active,
+        ProductStatus::Archived,
+        ProductStatus::Scheduled,
+    ];
+
+    #[test]
+    fn allowed_transitions() {
+        use ProductStatus::*;
+
+        let allowed = [
+            (Draft, Scheduled),
+            (Draft, Active),
+            (Draft, Archived),
+            (Scheduled, Draft),
+            (Scheduled, Active),
+            (Scheduled, Archived),
+            (Active, Draft),
+            (Active, Archived),
+            (Archived, Draft),
+        ];
+
+        for from in ALL {
+            for to in ALL {
+                assert_eq!(
+                    from.can_transition_to(to),
+                    allowed.contains(&(from, to)),
+                    "{} -> {}",
+                    from.as_str(),
+                    to.as_str()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn archived_products_go_back_through_draft() {
+        assert!(!ProductStatus::Archived.can_transition_to(ProductStatus::Active));
+        assert!(!ProductStatus::Archived.can_transition_to(ProductStatus::Scheduled));
+        assert!(ProductStatus::Archived.can_transition_to(ProductStatus::Draft));
+    }
+
+    #[test]
+    fn active_products_cannot_be_rescheduled() {
+        assert!(!ProductStatus::Active.can_transition_to(ProductStatus::Scheduled));
+    }
+
+    #[test]
+    fn status_round_trips_through_its_column_value() {
+        for status in ALL {
+            assert_eq!(status.as_str().parse::<ProductStatus>().unwrap(), status);
+        }
+        assert!("PUBLISHED".parse::<ProductStatus>().is_err());
+    }
+}
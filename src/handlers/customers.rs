@@ -0,0 +1,189 @@
+use async_graphql::{Context, InputObject, Object, Result, SimpleObject};
+use chrono::NaiveDateTime;
+use sqlx::{FromRow, PgPool, Row, postgres::PgRow};
+
+use crate::auth::{
+    AuthError, Principal, Role, RoleGuard, SessionStore,
+    password::{hash_password, verify_password},
+};
+
+const CUSTOMER_COLUMNS: &str = "id, email, name, role, created_at";
+
+#[derive(Debug, Clone, SimpleObject)]
+pub struct Customer {
+    pub id: i32,
+    pub email: String,
+    pub name: Option<String>,
+    pub role: Role,
+    pub created_at: NaiveDateTime,
+}
+
+impl<'r> FromRow<'r, PgRow> for Customer {
+    fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            email: row.try_get("email")?,
+            name: row.try_get("name")?,
+            role: row
+                .try_get::<String, _>("role")?
+                .parse()
+                .map_err(|err| sqlx::Error::Decode(Box::new(err)))?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+#[derive(InputObject)]
+pub struct RegisterInput {
+    pub email: String,
+    pub password: String,
+    pub name: Option<String>,
+}
+
+/// Result of registering or logging in
+#[derive(SimpleObject)]
+pub struct AuthPayload {
+    /// Send as `Authorization: Bearer <token>`; it cannot be retrieved again
+    pub token: String,
+    pub expires_at: NaiveDateTime,
+    pub customer: Customer,
+}
+
+fn normalize_email(email: &str) -> Result<String, AuthError> {
+    let email = email.trim().to_lowercase();
+    match email.split_once('@') {
+        Some((local, domain)) if !local.is_empty() && domain.contains('.') => Ok(email),
+        _ => Err(AuthError::InvalidEmail(email)),
+    }
+}
+
+#[derive(Default)]
+pub struct CustomerQuery;
+
+#[Object]
+impl CustomerQuery {
+    /// The logged-in customer, or null for anonymous requests
+    async fn me(&self, ctx: &Context<'_>) -> Result<Option<Customer>> {
+        let Some(customer_id) = Principal::current(ctx).customer_id() else {
+            return Ok(None);
+        };
+        let db = ctx.data::<PgPool>()?;
+
+        let customer = sqlx::query_as::<_, Customer>(&format!(
+            "SELECT {} FROM customers WHERE id = $1",
+            CUSTOMER_COLUMNS
+        ))
+        .bind(customer_id)
+        .fetch_optional(db)
+        .await?;
+
+        Ok(customer)
+    }
+}
+
+#[derive(Default)]
+pub struct CustomerMutation;
+
+#[Object]
+impl CustomerMutation {
+    /// Create an account and log it in
+    async fn register(&self, ctx: &Context<'_>, input: RegisterInput) -> Result<AuthPayload> {
+        let db = ctx.data::<PgPool>()?;
+        let sessions = ctx.data::<SessionStore>()?;
+
+        let email = normalize_email(&input.email)?;
+        let password_hash = hash_password(input.password).await?;
+
+        let customer = sqlx::query_as::<_, Customer>(&format!(
+            "INSERT INTO customers (email, password_hash, name, last_login_at)
+             VALUES ($1, $2, $3, CURRENT_TIMESTAMP)
+             ON CONFLICT (LOWER(email)) DO NOTHING
+             RETURNING {}",
+            CUSTOMER_COLUMNS
+        ))
+        .bind(&email)
+        .bind(password_hash)
+        .bind(input.name)
+        .fetch_optional(db)
+        .await?
+        .ok_or(AuthError::EmailTaken(email))?;
+
+        let session = sessions.issue(customer.id).await?;
+
+        Ok(AuthPayload {
+            token: session.token,
+            expires_at: session.expires_at,
+            customer,
+        })
+    }
+
+    async fn login(
+        &self,
+        ctx: &Context<'_>,
+        email: String,
+        password: String,
+    ) -> Result<AuthPayload> {
+        let db = ctx.data::<PgPool>()?;
+        let sessions = ctx.data::<SessionStore>()?;
+
+        let email = normalize_email(&email).map_err(|_| AuthError::InvalidCredentials)?;
+        let (customer_id, password_hash): (i32, String) =
+            sqlx::query_as("SELECT id, password_hash FROM customers WHERE LOWER(email) = $1")
+                .bind(&email)
+                .fetch_optional(db)
+                .await?
+                .ok_or(AuthError::InvalidCredentials)?;
+
+        if !verify_password(password, password_hash).await? {
+            return Err(AuthError::InvalidCredentials.into());
+        }
+
+        let customer = sqlx::query_as::<_, Customer>(&format!(
+            "UPDATE customers SET last_login_at = CURRENT_TIMESTAMP WHERE id = $1 RETURNING {}",
+            CUSTOMER_COLUMNS
+        ))
+        .bind(customer_id)
+        .fetch_one(db)
+        .await?;
+
+        let session = sessions.issue(customer.id).await?;
+
+        Ok(AuthPayload {
+            token: session.token,
+            expires_at: session.expires_at,
+            customer,
+        })
+    }
+
+    /// Grant or remove a staff role
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn set_customer_role(
+        &self,
+        ctx: &Context<'_>,
+        customer_id: i32,
+        role: Role,
+    ) -> Result<Option<Customer>> {
+        let db = ctx.data::<PgPool>()?;
+
+        let customer = sqlx::query_as::<_, Customer>(&format!(
+            "UPDATE customers SET role = $1 WHERE id = $2 RETURNING {}",
+            CUSTOMER_COLUMNS
+        ))
+        .bind(role.as_str())
+        .bind(customer_id)
+        .fetch_optional(db)
+        .await?;
+
+        Ok(customer)
+    }
+
+    /// Revoke the session used for this request
+    async fn logout(&self, ctx: &Context<'_>) -> Result<bool> {
+        let Principal::Customer { session_id, .. } = Principal::current(ctx) else {
+            return Err(AuthError::Unauthenticated.into());
+        };
+        let sessions = ctx.data::<SessionStore>()?;
+
+        Ok(sessions.revoke(*session_id).await?)
+    }
+}
@@ -0,0 +1,196 @@
+use async_graphql::{
+    SimpleObject,
+    futures_util::{Stream, stream},
+};
+use serde::Deserialize;
+use sqlx::{PgPool, postgres::PgListener};
+use std::time::Duration;
+use tokio::{sync::broadcast, task::JoinHandle};
+
+use crate::shutdown::Shutdown;
+
+/// NOTIFY channels published by the catalogue triggers
+pub const PRODUCT_UPDATED_CHANNEL: &str = "product_updated";
+pub const VARIANT_STOCK_CHANGED_CHANNEL: &str = "variant_stock_changed";
+pub const PRICE_CHANGED_CHANNEL: &str = "price_changed";
+pub const LOW_STOCK_CHANNEL: &str = "low_stock";
+pub const CATEGORY_UPDATED_CHANNEL: &str = "category_updated";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProductUpdated {
+    pub id: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, SimpleObject)]
+pub struct StockChange {
+    pub variant_id: i32,
+    pub product_id: i32,
+    pub sku: String,
+    /// `None` when the variant was just created
+    pub previous_quantity: Option<i32>,
+    pub stock_quantity: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, SimpleObject)]
+pub struct PriceChange {
+    pub variant_id: i32,
+    pub product_id: i32,
+    pub sku: String,
+    /// `None` when the variant was just created
+    pub previous_amount: Option<String>,
+    pub price_amount: String,
+    pub price_currency: String,
+}
+
+/// Raised when a variant's stock drops below its reorder point
+#[derive(Debug, Clone, Deserialize, SimpleObject)]
+pub struct LowStockAlert {
+    pub variant_id: i32,
+    pub product_id: i32,
+    pub sku: String,
+    pub stock_quantity: i32,
+    pub reorder_point: i32,
+}
+
+/// A category was created, renamed or removed, or a product joined or left it
+#[derive(Debug, Clone, Deserialize)]
+pub struct CategoryUpdated {
+    pub id: i32,
+    pub slug: String,
+    /// Set when the slug was just changed
+    pub previous_slug: Option<String>,
+}
+
+/// A catalogue change received from Postgres
+#[derive(Debug, Clone)]
+pub enum CatalogEvent {
+    ProductUpdated(ProductUpdated),
+    StockChanged(StockChange),
+    PriceChanged(PriceChange),
+    LowStock(LowStockAlert),
+    CategoryUpdated(CategoryUpdated),
+}
+
+impl CatalogEvent {
+    /// Decode a notification payload, returning `None` for unknown channels
+    pub fn from_notification(channel: &str, payload: &str) -> serde_json::Result<Option<Self>> {
+        let event = match channel {
+            PRODUCT_UPDATED_CHANNEL => Self::ProductUpdated(serde_json::from_str(payload)?),
+            VARIANT_STOCK_CHANGED_CHANNEL => Self::StockChanged(serde_json::from_str(payload)?),
+            PRICE_CHANGED_CHANNEL => Self::PriceChanged(serde_json::from_str(payload)?),
+            LOW_STOCK_CHANNEL => Self::LowStock(serde_json::from_str(payload)?),
+            CATEGORY_UPDATED_CHANNEL => Self::CategoryUpdated(serde_json::from_str(payload)?),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(event))
+    }
+}
+
+/// In-process fan-out of catalogue events to every open subscription
+#[derive(Debug, Clone)]
+pub struct CatalogEvents {
+    sender: broadcast::Sender<CatalogEvent>,
+}
+
+impl CatalogEvents {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    pub fn publish(&self, event: CatalogEvent) {
+        // No receivers just means nobody is subscribed right now
+        let _ = self.sender.send(event);
+    }
+
+    /// Stream of events published after this call
+    pub fn stream(&self) -> impl Stream<Item = CatalogEvent> + Send + 'static {
+        stream::unfold(self.sender.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Subscriber lagged behind, skipped {} events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Spawn a task that LISTENs on the catalogue channels until shutdown
+    pub fn spawn_listener(&self, pool: PgPool, shutdown: Shutdown) -> JoinHandle<()> {
+        let events = self.clone();
+
+        tokio::spawn(async move {
+            let mut listener = loop {
+                match PgListener::connect_with(&pool).await {
+                    Ok(listener) => break listener,
+                    Err(err) => {
+                        tracing::error!("Failed to connect catalogue listener: {}", err);
+                        tokio::select! {
+                            _ = tokio::time::sleep(Duration::from_secs(5)) => continue,
+                            _ = shutdown.wait() => return,
+                        }
+                    }
+                }
+            };
+
+            if let Err(err) = listener
+                .listen_all([
+                    PRODUCT_UPDATED_CHANNEL,
+                    VARIANT_STOCK_CHANGED_CHANNEL,
+                    PRICE_CHANGED_CHANNEL,
+                    LOW_STOCK_CHANNEL,
+                    CATEGORY_UPDATED_CHANNEL,
+                ])
+                .await
+            {
+                tracing::error!("Failed to LISTEN on catalogue channels: {}", err);
+                return;
+            }
+
+            loop {
+                tokio::select! {
+                    notification = listener.recv() => match notification {
+                        Ok(notification) => match CatalogEvent::from_notification(
+                            notification.channel(),
+                            notification.payload(),
+                        ) {
+                            Ok(Some(event)) => {
+                                if let CatalogEvent::LowStock(alert) = &event {
+                                    tracing::warn!(
+                                        sku = %alert.sku,
+                                        stock_quantity = alert.stock_quantity,
+                                        reorder_point = alert.reorder_point,
+                                        "Variant stock fell below its reorder point"
+                                    );
+                                }
+                                events.publish(event)
+                            }
+                            Ok(None) => {}
+                            Err(err) => tracing::warn!(
+                                "Invalid payload on {}: {}",
+                                notification.channel(),
+                                err
+                            ),
+                        },
+                        // The listener reconnects on the next `recv` call
+                        Err(err) => {
+                            tracing::warn!("Catalogue listener connection lost: {}", err);
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                        }
+                    },
+                    _ = shutdown.wait() => break,
+                }
+            }
+        })
+    }
+}
+
+impl Default for CatalogEvents {
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
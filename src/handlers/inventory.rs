@@ -0,0 +1,761 @@
+use async_graphql::{Context, Enum, InputObject, Object, Result, SimpleObject};
+use chrono::NaiveDateTime;
+use sqlx::{FromRow, PgConnection, PgPool, Row, postgres::PgRow};
+use std::str::FromStr;
+
+use crate::auth::{Principal, Role, RoleGuard};
+use crate::handlers::{audit, variant_loader::VariantGQL};
+
+/// SQL expression for on-hand stock minus units held by unexpired reservations.
+/// Expects `product_variants` to be in scope.
+pub const AVAILABLE_QUANTITY_SQL: &str = "(stock_quantity - COALESCE((
+        SELECT SUM(r.quantity) FROM stock_reservations r
+        WHERE r.variant_id = product_variants.id
+          AND r.status = 'ACTIVE'
+          AND (r.expires_at IS NULL OR r.expires_at > NOW() AT TIME ZONE 'UTC')
+    ), 0))::int4 AS available_quantity";
+
+/// Location that receives stock changes when the caller does not name one
+pub const DEFAULT_LOCATION_CODE: &str = "DEFAULT";
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StockMovementType {
+    Receipt,
+    Sale,
+    Adjustment,
+    Return,
+    Reservation,
+    Release,
+}
+
+impl StockMovementType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Receipt => "RECEIPT",
+            Self::Sale => "SALE",
+            Self::Adjustment => "ADJUSTMENT",
+            Self::Return => "RETURN",
+            Self::Reservation => "RESERVATION",
+            Self::Release => "RELEASE",
+        }
+    }
+}
+
+impl FromStr for StockMovementType {
+    type Err = InventoryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "RECEIPT" => Ok(Self::Receipt),
+            "SALE" => Ok(Self::Sale),
+            "ADJUSTMENT" => Ok(Self::Adjustment),
+            "RETURN" => Ok(Self::Return),
+            "RESERVATION" => Ok(Self::Reservation),
+            "RELEASE" => Ok(Self::Release),
+            other => Err(InventoryError::UnknownValue(other.to_string())),
+        }
+    }
+}
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReservationStatus {
+    Active,
+    Released,
+    Consumed,
+}
+
+impl ReservationStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Active => "ACTIVE",
+            Self::Released => "RELEASED",
+            Self::Consumed => "CONSUMED",
+        }
+    }
+}
+
+impl FromStr for ReservationStatus {
+    type Err = InventoryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ACTIVE" => Ok(Self::Active),
+            "RELEASED" => Ok(Self::Released),
+            "CONSUMED" => Ok(Self::Consumed),
+            other => Err(InventoryError::UnknownValue(other.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InventoryError {
+    #[error("variant with sku '{0}' not found")]
+    UnknownSku(String),
+    #[error("location '{0}' not found")]
+    UnknownLocation(String),
+    #[error("reservation {0} not found")]
+    UnknownReservation(i32),
+    #[error("reservation {0} is no longer active")]
+    ReservationNotActive(i32),
+    #[error("insufficient stock for '{sku}': requested {requested}, available {available}")]
+    InsufficientStock {
+        sku: String,
+        requested: i32,
+        available: i32,
+    },
+    #[error("{0}")]
+    InvalidQuantity(String),
+    #[error("reservations must expire at least one second from now, got {0} seconds")]
+    InvalidExpiry(i64),
+    #[error("unknown value '{0}'")]
+    UnknownValue(String),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+fn decode_err(err: InventoryError) -> sqlx::Error {
+    sqlx::Error::Decode(Box::new(err))
+}
+
+/// Warehouse or other site that holds stock
+#[derive(Debug, Clone, SimpleObject, FromRow)]
+pub struct Location {
+    pub id: i32,
+    pub code: String,
+    pub name: String,
+    pub is_active: bool,
+}
+
+/// On-hand quantity of a variant at one location
+#[derive(Debug, Clone, SimpleObject, FromRow)]
+pub struct LocationStock {
+    #[graphql(skip)]
+    pub variant_id: i32,
+    pub location_id: i32,
+    pub location_code: String,
+    pub location_name: String,
+    pub quantity: i32,
+}
+
+/// A single entry in the stock ledger
+#[derive(Debug, Clone, SimpleObject)]
+pub struct StockMovement {
+    pub id: i32,
+    pub variant_id: i32,
+    /// Location whose stock changed; `None` for reservations and releases
+    pub location_id: Option<i32>,
+    pub movement_type: StockMovementType,
+    /// Signed change to on-hand stock, or the held units for reservations and releases
+    pub quantity: i32,
+    pub stock_after: i32,
+    pub reason: Option<String>,
+    pub reference: Option<String>,
+    pub reservation_id: Option<i32>,
+    pub created_at: NaiveDateTime,
+}
+
+impl<'r> FromRow<'r, PgRow> for StockMovement {
+    fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            variant_id: row.try_get("variant_id")?,
+            location_id: row.try_get("location_id")?,
+            movement_type: row
+                .try_get::<String, _>("movement_type")?
+                .parse()
+                .map_err(decode_err)?,
+            quantity: row.try_get("quantity")?,
+            stock_after: row.try_get("stock_after")?,
+            reason: row.try_get("reason")?,
+            reference: row.try_get("reference")?,
+            reservation_id: row.try_get("reservation_id")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+/// Units held against a variant until released or consumed
+#[derive(Debug, Clone, SimpleObject)]
+pub struct StockReservation {
+    pub id: i32,
+    pub variant_id: i32,
+    pub quantity: i32,
+    pub status: ReservationStatus,
+    pub reference: Option<String>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub released_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl<'r> FromRow<'r, PgRow> for StockReservation {
+    fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            variant_id: row.try_get("variant_id")?,
+            quantity: row.try_get("quantity")?,
+            status: row
+                .try_get::<String, _>("status")?
+                .parse()
+                .map_err(decode_err)?,
+            reference: row.try_get("reference")?,
+            expires_at: row.try_get("expires_at")?,
+            released_at: row.try_get("released_at")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+#[derive(InputObject)]
+pub struct AdjustStockInput {
+    pub sku: String,
+    /// Signed change to on-hand stock: positive for receipts and returns,
+    /// negative for sales, either for adjustments
+    pub quantity: i32,
+    #[graphql(default_with = "StockMovementType::Adjustment")]
+    pub movement_type: StockMovementType,
+    /// Defaults to the `DEFAULT` location
+    pub location_code: Option<String>,
+    pub reason: Option<String>,
+    pub reference: Option<String>,
+}
+
+#[derive(InputObject)]
+pub struct CreateLocationInput {
+    pub code: String,
+    pub name: String,
+}
+
+#[derive(InputObject)]
+pub struct ReserveStockInput {
+    pub sku: String,
+    pub quantity: i32,
+    pub reference: Option<String>,
+    /// Reservation stops counting against availability after this many seconds, at
+    /// least 1; never when omitted
+    pub expires_in_seconds: Option<i64>,
+}
+
+/// Variant row locked with `SELECT ... FOR UPDATE` for the rest of the transaction
+#[derive(Debug, Clone)]
+pub struct LockedVariant {
+    pub id: i32,
+    pub sku: String,
+    pub stock_quantity: i32,
+    pub reserved_quantity: i32,
+}
+
+impl LockedVariant {
+    pub fn available_quantity(&self) -> i32 {
+        self.stock_quantity - self.reserved_quantity
+    }
+}
+
+/// A ledger entry about to be written
+pub struct NewStockMovement<'a> {
+    pub movement_type: StockMovementType,
+    /// Required for movements that change on-hand stock
+    pub location_id: Option<i32>,
+    pub quantity: i32,
+    pub reason: Option<&'a str>,
+    pub reference: Option<&'a str>,
+    pub reservation_id: Option<i32>,
+    /// Order whose sale or cancellation moved the stock
+    pub order_id: Option<i32>,
+}
+
+/// Resolve a location code to its id, falling back to the default location
+pub async fn resolve_location(
+    conn: &mut PgConnection,
+    code: Option<&str>,
+) -> Result<i32, InventoryError> {
+    let code = code.unwrap_or(DEFAULT_LOCATION_CODE);
+
+    sqlx::query_scalar("SELECT id FROM locations WHERE code = $1 AND is_active")
+        .bind(code)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| InventoryError::UnknownLocation(code.to_string()))
+}
+
+/// Lock a variant row that is not deleted and compute its currently reserved units
+pub async fn lock_variant_by_sku(
+    conn: &mut PgConnection,
+    sku: &str,
+) -> Result<LockedVariant, InventoryError> {
+    lock_variant(conn, sku, false).await
+}
+
+/// Lock a variant row even if it was deleted, for returning units sold before the
+/// variant was deleted
+pub async fn lock_any_variant_by_sku(
+    conn: &mut PgConnection,
+    sku: &str,
+) -> Result<LockedVariant, InventoryError> {
+    lock_variant(conn, sku, true).await
+}
+
+async fn lock_variant(
+    conn: &mut PgConnection,
+    sku: &str,
+    include_deleted: bool,
+) -> Result<LockedVariant, InventoryError> {
+    let row = sqlx::query(
+        "SELECT id, sku, stock_quantity FROM product_variants
+         WHERE sku = $1 AND ($2 OR deleted_at IS NULL)
+         FOR UPDATE",
+    )
+    .bind(sku)
+    .bind(include_deleted)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| InventoryError::UnknownSku(sku.to_string()))?;
+
+    let id: i32 = row.get("id");
+    let reserved_quantity: i64 = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(quantity), 0)::int8 FROM stock_reservations
+         WHERE variant_id = $1 AND status = 'ACTIVE'
+           AND (expires_at IS NULL OR expires_at > NOW() AT TIME ZONE 'UTC')",
+    )
+    .bind(id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(LockedVariant {
+        id,
+        sku: row.get("sku"),
+        stock_quantity: row.get("stock_quantity"),
+        reserved_quantity: reserved_quantity as i32,
+    })
+}
+
+/// Apply a movement to a locked variant and append it to the ledger.
+///
+/// Movements that change on-hand stock update the location's `stock_levels` row and keep
+/// `product_variants.stock_quantity` equal to the sum across locations. Reservation and
+/// release movements leave on-hand stock untouched.
+pub async fn apply_stock_movement(
+    conn: &mut PgConnection,
+    variant: &mut LockedVariant,
+    movement: NewStockMovement<'_>,
+) -> Result<StockMovement, InventoryError> {
+    let changes_on_hand = !matches!(
+        movement.movement_type,
+        StockMovementType::Reservation | StockMovementType::Release
+    );
+
+    let mut location_id = movement.location_id;
+    if changes_on_hand {
+        let level_location_id = match location_id {
+            Some(id) => id,
+            None => resolve_location(&mut *conn, None).await?,
+        };
+        location_id = Some(level_location_id);
+
+        // The variant row lock serialises writers, so no separate lock on the level is needed
+        let level: i32 = sqlx::query_scalar(
+            "SELECT quantity FROM stock_levels WHERE variant_id = $1 AND location_id = $2",
+        )
+        .bind(variant.id)
+        .bind(level_location_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .unwrap_or(0);
+
+        if level + movement.quantity < 0 {
+            return Err(InventoryError::InsufficientStock {
+                sku: variant.sku.clone(),
+                requested: -movement.quantity,
+                available: level,
+            });
+        }
+
+        sqlx::query(
+            "INSERT INTO stock_levels (variant_id, location_id, quantity) VALUES ($1, $2, $3)
+             ON CONFLICT (variant_id, location_id)
+             DO UPDATE SET quantity = stock_levels.quantity + EXCLUDED.quantity",
+        )
+        .bind(variant.id)
+        .bind(level_location_id)
+        .bind(movement.quantity)
+        .execute(&mut *conn)
+        .await?;
+
+        let stock_after = variant.stock_quantity + movement.quantity;
+        sqlx::query("UPDATE product_variants SET stock_quantity = $1 WHERE id = $2")
+            .bind(stock_after)
+            .bind(variant.id)
+            .execute(&mut *conn)
+            .await?;
+        variant.stock_quantity = stock_after;
+    }
+
+    let recorded = sqlx::query_as::<_, StockMovement>(
+        "INSERT INTO stock_movements
+            (variant_id, location_id, movement_type, quantity, stock_after, reason, reference,
+             reservation_id, order_id)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+         RETURNING id, variant_id, location_id, movement_type, quantity, stock_after, reason,
+                   reference, reservation_id, created_at",
+    )
+    .bind(variant.id)
+    .bind(location_id)
+    .bind(movement.movement_type.as_str())
+    .bind(movement.quantity)
+    .bind(variant.stock_quantity)
+    .bind(movement.reason)
+    .bind(movement.reference)
+    .bind(movement.reservation_id)
+    .bind(movement.order_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(recorded)
+}
+
+/// Remove `quantity` units of a locked variant for `order`, drawing from the default
+/// location first and then from the locations holding the most stock
+pub async fn allocate_sale(
+    conn: &mut PgConnection,
+    variant: &mut LockedVariant,
+    quantity: i32,
+    order_id: i32,
+    reference: Option<&str>,
+) -> Result<Vec<StockMovement>, InventoryError> {
+    if variant.available_quantity() < quantity {
+        return Err(InventoryError::InsufficientStock {
+            sku: variant.sku.clone(),
+            requested: quantity,
+            available: variant.available_quantity(),
+        });
+    }
+
+    let levels: Vec<(i32, i32)> = sqlx::query_as(
+        "SELECT s.location_id, s.quantity FROM stock_levels s
+         JOIN locations l ON l.id = s.location_id
+         WHERE s.variant_id = $1 AND s.quantity > 0
+         ORDER BY (l.code = $2) DESC, s.quantity DESC, l.id",
+    )
+    .bind(variant.id)
+    .bind(DEFAULT_LOCATION_CODE)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut remaining = quantity;
+    let mut movements = Vec::new();
+    for (location_id, level) in levels {
+        if remaining == 0 {
+            break;
+        }
+
+        let taken = level.min(remaining);
+        let movement = apply_stock_movement(
+            &mut *conn,
+            variant,
+            NewStockMovement {
+                movement_type: StockMovementType::Sale,
+                location_id: Some(location_id),
+                quantity: -taken,
+                reason: None,
+                reference,
+                reservation_id: None,
+                order_id: Some(order_id),
+            },
+        )
+        .await?;
+        movements.push(movement);
+        remaining -= taken;
+    }
+
+    if remaining > 0 {
+        return Err(InventoryError::InsufficientStock {
+            sku: variant.sku.clone(),
+            requested: quantity,
+            available: quantity - remaining,
+        });
+    }
+
+    Ok(movements)
+}
+
+fn validate_adjustment(input: &AdjustStockInput) -> Result<(), InventoryError> {
+    let valid = match input.movement_type {
+        StockMovementType::Receipt | StockMovementType::Return => input.quantity > 0,
+        StockMovementType::Sale => input.quantity < 0,
+        StockMovementType::Adjustment => input.quantity != 0,
+        StockMovementType::Reservation | StockMovementType::Release => {
+            return Err(InventoryError::InvalidQuantity(
+                "use reserveStock and releaseReservation for reservations".to_string(),
+            ));
+        }
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(InventoryError::InvalidQuantity(format!(
+            "quantity {} is not valid for a {} movement",
+            input.quantity,
+            input.movement_type.as_str()
+        )))
+    }
+}
+
+#[derive(Default)]
+pub struct InventoryQuery;
+
+#[Object]
+impl InventoryQuery {
+    #[graphql(guard = "RoleGuard::new(Role::InventoryManager)")]
+    async fn locations(&self, ctx: &Context<'_>) -> Result<Vec<Location>> {
+        let db = ctx.data::<PgPool>()?;
+
+        let locations = sqlx::query_as::<_, Location>(
+            "SELECT id, code, name, is_active FROM locations ORDER BY code",
+        )
+        .fetch_all(db)
+        .await?;
+
+        Ok(locations)
+    }
+
+    /// Active variants below `threshold`, or below their own reorder point when omitted
+    #[graphql(guard = "RoleGuard::new(Role::InventoryManager)")]
+    async fn low_stock_variants(
+        &self,
+        ctx: &Context<'_>,
+        threshold: Option<i32>,
+    ) -> Result<Vec<VariantGQL>> {
+        let db = ctx.data::<PgPool>()?;
+
+        let sql = format!(
+            "SELECT id, product_id, sku, price_amount, price_currency, cost_amount, stock_quantity,
+                    {}, reorder_point, is_active, attributes
+             FROM product_variants
+             WHERE is_active AND deleted_at IS NULL
+               AND stock_quantity < COALESCE($1, reorder_point)
+             ORDER BY stock_quantity, sku",
+            AVAILABLE_QUANTITY_SQL
+        );
+
+        let variants = sqlx::query(&sql)
+            .bind(threshold)
+            .try_map(|row: PgRow| VariantGQL::from_row(&row))
+            .fetch_all(db)
+            .await?;
+
+        Ok(variants)
+    }
+}
+
+#[derive(Default)]
+pub struct InventoryMutation;
+
+#[Object]
+impl InventoryMutation {
+    #[graphql(guard = "RoleGuard::new(Role::InventoryManager)")]
+    async fn create_location(
+        &self,
+        ctx: &Context<'_>,
+        input: CreateLocationInput,
+    ) -> Result<Location> {
+        let db = ctx.data::<PgPool>()?;
+
+        let location = sqlx::query_as::<_, Location>(
+            "INSERT INTO locations (code, name) VALUES ($1, $2)
+             RETURNING id, code, name, is_active",
+        )
+        .bind(&input.code)
+        .bind(&input.name)
+        .fetch_one(db)
+        .await?;
+
+        Ok(location)
+    }
+
+    /// Set or clear (with `null`) the stock level that triggers low stock alerts
+    #[graphql(guard = "RoleGuard::new(Role::InventoryManager)")]
+    async fn set_reorder_point(
+        &self,
+        ctx: &Context<'_>,
+        sku: String,
+        reorder_point: Option<i32>,
+    ) -> Result<Option<i32>> {
+        if reorder_point.is_some_and(|point| point < 0) {
+            return Err(InventoryError::InvalidQuantity(
+                "reorder point cannot be negative".to_string(),
+            )
+            .into());
+        }
+        let db = ctx.data::<PgPool>()?;
+
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+        let updated: Option<Option<i32>> = sqlx::query_scalar(
+            "UPDATE product_variants SET reorder_point = $1 WHERE sku = $2 RETURNING reorder_point",
+        )
+        .bind(reorder_point)
+        .bind(&sku)
+        .fetch_optional(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(updated.ok_or(InventoryError::UnknownSku(sku))?)
+    }
+
+    /// Record a receipt, sale, return or manual adjustment against a variant. Sales and
+    /// negative adjustments cannot take units held by active reservations.
+    #[graphql(guard = "RoleGuard::new(Role::InventoryManager)")]
+    async fn adjust_stock(
+        &self,
+        ctx: &Context<'_>,
+        input: AdjustStockInput,
+    ) -> Result<StockMovement> {
+        validate_adjustment(&input)?;
+        let db = ctx.data::<PgPool>()?;
+
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+        let location_id = resolve_location(&mut tx, input.location_code.as_deref()).await?;
+        let mut variant = lock_variant_by_sku(&mut tx, &input.sku).await?;
+        // Units held by reservations are spoken for, so only available ones can leave
+        if input.quantity < 0 && variant.available_quantity() + input.quantity < 0 {
+            return Err(InventoryError::InsufficientStock {
+                requested: -input.quantity,
+                available: variant.available_quantity(),
+                sku: variant.sku,
+            }
+            .into());
+        }
+        let movement = apply_stock_movement(
+            &mut tx,
+            &mut variant,
+            NewStockMovement {
+                movement_type: input.movement_type,
+                location_id: Some(location_id),
+                quantity: input.quantity,
+                reason: input.reason.as_deref(),
+                reference: input.reference.as_deref(),
+                reservation_id: None,
+                order_id: None,
+            },
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(movement)
+    }
+
+    /// Hold units of a variant so they no longer count as available
+    #[graphql(guard = "RoleGuard::new(Role::InventoryManager)")]
+    async fn reserve_stock(
+        &self,
+        ctx: &Context<'_>,
+        input: ReserveStockInput,
+    ) -> Result<StockReservation> {
+        if input.quantity <= 0 {
+            return Err(InventoryError::InvalidQuantity(
+                "reservation quantity must be positive".to_string(),
+            )
+            .into());
+        }
+        if let Some(secs) = input.expires_in_seconds.filter(|secs| *secs < 1) {
+            return Err(InventoryError::InvalidExpiry(secs).into());
+        }
+        let db = ctx.data::<PgPool>()?;
+
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+        let mut variant = lock_variant_by_sku(&mut tx, &input.sku).await?;
+        if variant.available_quantity() < input.quantity {
+            return Err(InventoryError::InsufficientStock {
+                requested: input.quantity,
+                available: variant.available_quantity(),
+                sku: variant.sku,
+            }
+            .into());
+        }
+
+        let reservation = sqlx::query_as::<_, StockReservation>(
+            "INSERT INTO stock_reservations (variant_id, quantity, reference, expires_at)
+             VALUES ($1, $2, $3, NOW() AT TIME ZONE 'UTC' + make_interval(secs => $4))
+             RETURNING id, variant_id, quantity, status, reference, expires_at, released_at,
+                       created_at",
+        )
+        .bind(variant.id)
+        .bind(input.quantity)
+        .bind(&input.reference)
+        .bind(input.expires_in_seconds.map(|secs| secs as f64))
+        .fetch_one(&mut *tx)
+        .await?;
+
+        apply_stock_movement(
+            &mut tx,
+            &mut variant,
+            NewStockMovement {
+                movement_type: StockMovementType::Reservation,
+                location_id: None,
+                quantity: reservation.quantity,
+                reason: None,
+                reference: input.reference.as_deref(),
+                reservation_id: Some(reservation.id),
+                order_id: None,
+            },
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(reservation)
+    }
+
+    /// Give the units held by an active reservation back to available stock
+    #[graphql(guard = "RoleGuard::new(Role::InventoryManager)")]
+    async fn release_reservation(
+        &self,
+        ctx: &Context<'_>,
+        reservation_id: i32,
+        reason: Option<String>,
+    ) -> Result<StockReservation> {
+        let db = ctx.data::<PgPool>()?;
+
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+        let sku: String = sqlx::query_scalar(
+            "SELECT v.sku FROM stock_reservations r
+             JOIN product_variants v ON v.id = r.variant_id
+             WHERE r.id = $1",
+        )
+        .bind(reservation_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(InventoryError::UnknownReservation(reservation_id))?;
+
+        // Lock the variant before the reservation, in the same order as reserveStock.
+        // Reservations on variants deleted since are released all the same.
+        let mut variant = lock_any_variant_by_sku(&mut tx, &sku).await?;
+        let reservation = sqlx::query_as::<_, StockReservation>(
+            "UPDATE stock_reservations
+             SET status = 'RELEASED', released_at = NOW() AT TIME ZONE 'UTC'
+             WHERE id = $1 AND status = 'ACTIVE'
+             RETURNING id, variant_id, quantity, status, reference, expires_at, released_at,
+                       created_at",
+        )
+        .bind(reservation_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(InventoryError::ReservationNotActive(reservation_id))?;
+
+        apply_stock_movement(
+            &mut tx,
+            &mut variant,
+            NewStockMovement {
+                movement_type: StockMovementType::Release,
+                location_id: None,
+                quantity: reservation.quantity,
+                reason: reason.as_deref(),
+                reference: reservation.reference.as_deref(),
+                reservation_id: Some(reservation.id),
+                order_id: None,
+            },
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(reservation)
+    }
+}
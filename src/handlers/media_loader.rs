@@ -3,18 +3,34 @@ use std::{collections::HashMap, sync::Arc};
 use async_graphql::{SimpleObject, dataloader::Loader};
 use sqlx::{PgPool, Row, postgres::PgRow};
 
+use crate::metrics::{MEDIA_LOADER, metrics};
+use crate::slow_query_log::{Explain, take_arguments};
+use crate::telemetry::instrument_explainable_query;
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct ProductMediaLoadKey {
     pub product_id: i32,
     pub columns: Vec<String>,
+    /// Also load deleted media; only honoured for admins
+    pub include_deleted: bool,
 }
 
+/// An image, video or document of a product, in display order
 #[derive(Debug, Clone, sqlx::FromRow, SimpleObject)]
 pub struct Media {
     pub id: i32,
     pub product_id: i32,
-    pub category_id: i32,
+    pub url: String,
+    pub media_type: String,
+    pub sort_order: Option<i32>,
+    pub alt_text: Option<String>,
+    pub file_size: Option<i32>,
+    pub mime_type: Option<String>,
     pub is_primary: bool,
+    /// Whether the media item was deleted
+    #[graphql(skip)]
+    #[sqlx(default)]
+    pub deleted: bool,
 }
 
 pub struct ProductMediaLoader {
@@ -25,6 +41,11 @@ impl Loader<ProductMediaLoadKey> for ProductMediaLoader {
     type Value = Vec<Media>;
     type Error = Arc<sqlx::Error>;
 
+    #[tracing::instrument(
+        name = "dataloader.load",
+        skip_all,
+        fields(loader = MEDIA_LOADER, keys = keys.len())
+    )]
     async fn load(
         &self,
         keys: &[ProductMediaLoadKey],
@@ -43,33 +64,53 @@ impl Loader<ProductMediaLoadKey> for ProductMediaLoader {
         }
         safe_columns.dedup();
 
+        // Deleted entries are filtered per key below when only some keys want them
+        let include_deleted = keys.iter().any(|key| key.include_deleted);
         let sql = format!(
-            "SELECT {} FROM product_category_junction WHERE product_id = ANY($1)",
+            "SELECT {}, deleted_at IS NOT NULL AS deleted
+             FROM product_media
+             WHERE product_id = ANY($1) AND ($2 OR deleted_at IS NULL)
+             ORDER BY sort_order, id",
             safe_columns.join(", ")
         );
 
-        let rows = sqlx::query(&sql)
-            .bind(&product_ids)
-            .map(|row: PgRow| {
-                return Media {
+        metrics().record_batch(MEDIA_LOADER, keys.len());
+        let arguments = take_arguments(sqlx::query(&sql).bind(&product_ids).bind(include_deleted))?;
+        let explain = Explain {
+            pool: &self.pool,
+            arguments: &arguments,
+        };
+        let rows = instrument_explainable_query(
+            MEDIA_LOADER,
+            &sql,
+            explain,
+            sqlx::query_with(&sql, arguments.clone())
+                .map(|row: PgRow| Media {
                     id: row.get("id"),
                     product_id: row.try_get("product_id").unwrap_or_default(),
-                    category_id: row.try_get("category_id").unwrap_or_default(),
+                    url: row.try_get("url").unwrap_or_default(),
+                    media_type: row.try_get("media_type").unwrap_or_default(),
+                    sort_order: row.try_get("sort_order").unwrap_or_default(),
+                    alt_text: row.try_get("alt_text").unwrap_or_default(),
+                    file_size: row.try_get("file_size").unwrap_or_default(),
+                    mime_type: row.try_get("mime_type").unwrap_or_default(),
                     is_primary: row.try_get("is_primary").unwrap_or_default(),
-                };
-            })
-            .fetch_all(&self.pool)
-            .await?;
+                    deleted: row.try_get("deleted").unwrap_or_default(),
+                })
+                .fetch_all(&self.pool),
+        )
+        .await?;
 
         // Create result map for each key
         let mut result_map: HashMap<ProductMediaLoadKey, Vec<Media>> = HashMap::new();
         for key in keys {
-            let variants: Vec<Media> = rows
+            let media: Vec<Media> = rows
                 .iter()
                 .filter(|v| v.product_id == key.product_id)
+                .filter(|v| key.include_deleted || !v.deleted)
                 .cloned()
                 .collect();
-            result_map.insert(key.clone(), variants);
+            result_map.insert(key.clone(), media);
         }
 
         Ok(result_map)
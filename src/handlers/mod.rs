@@ -1,4 +1,20 @@
+pub mod api_keys;
+pub mod audit;
+pub mod cart;
+pub mod catalog;
+pub mod customers;
+pub mod events;
+pub mod inventory;
 pub mod media_loader;
+pub mod mutations;
+pub mod order_loader;
+pub mod orders;
+pub mod payments;
+pub mod persisted_queries;
 pub mod products;
+pub mod publishing;
 pub mod queries;
+pub mod revisions;
+pub mod stock_level_loader;
+pub mod subscriptions;
 pub mod variant_loader;
@@ -0,0 +1,20 @@
+use async_graphql::MergedObject;
+
+use crate::handlers::{
+    api_keys::ApiKeyMutation, cart::CartMutation, catalog::CatalogMutation,
+    customers::CustomerMutation, inventory::InventoryMutation, orders::OrderMutation,
+    persisted_queries::PersistedQueryMutation, revisions::RevisionMutation,
+};
+
+/// Root mutation type combining the mutations of each subsystem
+#[derive(MergedObject, Default)]
+pub struct MutationRoot(
+    CatalogMutation,
+    InventoryMutation,
+    CartMutation,
+    OrderMutation,
+    CustomerMutation,
+    ApiKeyMutation,
+    PersistedQueryMutation,
+    RevisionMutation,
+);
@@ -0,0 +1,131 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_graphql::dataloader::Loader;
+use sqlx::PgPool;
+
+use crate::handlers::orders::{OrderLine, OrderStatusChange};
+use crate::handlers::payments::{PAYMENT_ATTEMPT_COLUMNS, PaymentAttempt};
+use crate::telemetry::instrument_query;
+
+/// Loads order lines keyed by order id
+pub struct OrderLineLoader {
+    pub pool: PgPool,
+}
+
+impl Loader<i32> for OrderLineLoader {
+    type Value = Vec<OrderLine>;
+    type Error = Arc<sqlx::Error>;
+
+    #[tracing::instrument(
+        name = "dataloader.load",
+        skip_all,
+        fields(loader = "order_lines", keys = keys.len())
+    )]
+    async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+        if keys.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let sql = "SELECT id, order_id, variant_id, sku, product_name, unit_price_amount, currency,
+                  quantity, shipped_quantity, line_total_amount
+                  FROM order_lines WHERE order_id = ANY($1) ORDER BY id";
+        let rows = instrument_query(
+            "order_lines",
+            sql,
+            sqlx::query_as::<_, OrderLine>(sql)
+                .bind(keys)
+                .fetch_all(&self.pool),
+        )
+        .await?;
+
+        let mut result_map: HashMap<i32, Vec<OrderLine>> =
+            keys.iter().map(|key| (*key, Vec::new())).collect();
+        for row in rows {
+            result_map.entry(row.order_id).or_default().push(row);
+        }
+
+        Ok(result_map)
+    }
+}
+
+/// Loads order status history keyed by order id
+pub struct OrderStatusHistoryLoader {
+    pub pool: PgPool,
+}
+
+impl Loader<i32> for OrderStatusHistoryLoader {
+    type Value = Vec<OrderStatusChange>;
+    type Error = Arc<sqlx::Error>;
+
+    #[tracing::instrument(
+        name = "dataloader.load",
+        skip_all,
+        fields(loader = "order_status_history", keys = keys.len())
+    )]
+    async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+        if keys.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let sql = "SELECT order_id, from_status, to_status, note, created_at
+                  FROM order_status_history WHERE order_id = ANY($1) ORDER BY created_at, id";
+        let rows = instrument_query(
+            "order_status_history",
+            sql,
+            sqlx::query_as::<_, OrderStatusChange>(sql)
+                .bind(keys)
+                .fetch_all(&self.pool),
+        )
+        .await?;
+
+        let mut result_map: HashMap<i32, Vec<OrderStatusChange>> =
+            keys.iter().map(|key| (*key, Vec::new())).collect();
+        for row in rows {
+            result_map.entry(row.order_id).or_default().push(row);
+        }
+
+        Ok(result_map)
+    }
+}
+
+/// Loads payment attempts keyed by order id
+pub struct PaymentAttemptLoader {
+    pub pool: PgPool,
+}
+
+impl Loader<i32> for PaymentAttemptLoader {
+    type Value = Vec<PaymentAttempt>;
+    type Error = Arc<sqlx::Error>;
+
+    #[tracing::instrument(
+        name = "dataloader.load",
+        skip_all,
+        fields(loader = "payment_attempts", keys = keys.len())
+    )]
+    async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+        if keys.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let sql = format!(
+            "SELECT {} FROM payment_attempts WHERE order_id = ANY($1) ORDER BY created_at, id",
+            PAYMENT_ATTEMPT_COLUMNS
+        );
+        let rows = instrument_query(
+            "payment_attempts",
+            &sql,
+            sqlx::query_as::<_, PaymentAttempt>(&sql)
+                .bind(keys)
+                .fetch_all(&self.pool),
+        )
+        .await?;
+
+        let mut result_map: HashMap<i32, Vec<PaymentAttempt>> =
+            keys.iter().map(|key| (*key, Vec::new())).collect();
+        for row in rows {
+            result_map.entry(row.order_id).or_default().push(row);
+        }
+
+        Ok(result_map)
+    }
+}
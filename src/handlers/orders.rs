@@ -0,0 +1,927 @@
+use async_graphql::{
+    ComplexObject, Context, Enum, InputObject, Object, Result, SimpleObject,
+    connection::{Connection, Edge, EmptyFields},
+    dataloader::DataLoader,
+};
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection, PgPool, Row, postgres::PgRow, types::Json};
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::auth::{Principal, Role, RoleGuard};
+use crate::handlers::{
+    audit,
+    cart::{CartError, CartStatus, load_cart},
+    inventory::{
+        InventoryError, NewStockMovement, StockMovementType, allocate_sale, apply_stock_movement,
+        lock_any_variant_by_sku, lock_variant_by_sku,
+    },
+    order_loader::{OrderLineLoader, OrderStatusHistoryLoader, PaymentAttemptLoader},
+    payments::{PaymentAttempt, SharedPaymentProvider, collect_payment, release_payments},
+};
+use crate::payments::{PaymentError, PaymentProvider};
+
+/// Lifecycle state of an order
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OrderStatus {
+    /// Placed at checkout, awaiting payment
+    Pending,
+    /// Payment captured, ready to ship
+    Paid,
+    /// Some but not all units have shipped
+    PartiallyShipped,
+    /// Every unit has shipped
+    Fulfilled,
+    /// Cancelled before shipping; sold stock has been returned
+    Cancelled,
+    /// Money returned to the customer after shipping
+    Refunded,
+}
+
+impl OrderStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "PENDING",
+            Self::Paid => "PAID",
+            Self::PartiallyShipped => "PARTIALLY_SHIPPED",
+            Self::Fulfilled => "FULFILLED",
+            Self::Cancelled => "CANCELLED",
+            Self::Refunded => "REFUNDED",
+        }
+    }
+
+    /// Whether an order in this state may move to `next`
+    pub fn can_transition_to(self, next: Self) -> bool {
+        matches!(
+            (self, next),
+            (Self::Pending, Self::Paid | Self::Cancelled)
+                | (
+                    Self::Paid,
+                    Self::PartiallyShipped | Self::Fulfilled | Self::Cancelled
+                )
+                | (Self::PartiallyShipped, Self::Fulfilled | Self::Refunded)
+                | (Self::Fulfilled, Self::Refunded)
+        )
+    }
+
+    /// Column recording when the order first entered this state
+    fn timestamp_column(self) -> Option<&'static str> {
+        match self {
+            Self::Pending => None,
+            Self::Paid => Some("paid_at"),
+            Self::PartiallyShipped => Some("shipped_at"),
+            Self::Fulfilled => Some("fulfilled_at"),
+            Self::Cancelled => Some("cancelled_at"),
+            Self::Refunded => Some("refunded_at"),
+        }
+    }
+}
+
+impl FromStr for OrderStatus {
+    type Err = OrderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "PENDING" => Ok(Self::Pending),
+            "PAID" => Ok(Self::Paid),
+            "PARTIALLY_SHIPPED" => Ok(Self::PartiallyShipped),
+            "FULFILLED" => Ok(Self::Fulfilled),
+            "CANCELLED" => Ok(Self::Cancelled),
+            "REFUNDED" => Ok(Self::Refunded),
+            other => Err(OrderError::UnknownValue(other.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum OrderError {
+    #[error("cart {0} is empty")]
+    EmptyCart(Uuid),
+    #[error("variant '{0}' is no longer available for sale")]
+    VariantInactive(String),
+    #[error("unknown order '{0}'")]
+    UnknownOrder(String),
+    #[error("order line {0} does not belong to this order")]
+    UnknownOrderLine(i32),
+    #[error("cannot move order from {} to {}", from.as_str(), to.as_str())]
+    InvalidTransition { from: OrderStatus, to: OrderStatus },
+    #[error("cannot ship {requested} of '{sku}', only {remaining} left to ship")]
+    OverShipment {
+        sku: String,
+        requested: i32,
+        remaining: i32,
+    },
+    #[error("invalid quantity: {0}")]
+    InvalidQuantity(String),
+    #[error("unknown value '{0}'")]
+    UnknownValue(String),
+    #[error(transparent)]
+    Cart(#[from] CartError),
+    #[error(transparent)]
+    Inventory(#[from] InventoryError),
+    #[error(transparent)]
+    Payment(#[from] PaymentError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+fn decode_err(err: OrderError) -> sqlx::Error {
+    sqlx::Error::Decode(Box::new(err))
+}
+
+/// Postal address captured at checkout
+#[derive(Debug, Clone, SimpleObject, InputObject, Serialize, Deserialize)]
+#[graphql(input_name = "AddressInput")]
+pub struct Address {
+    pub name: String,
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: String,
+    pub region: Option<String>,
+    pub postal_code: String,
+    /// ISO 3166-1 alpha-2 country code
+    pub country_code: String,
+    pub phone: Option<String>,
+}
+
+/// Immutable snapshot of a purchased variant
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(complex)]
+pub struct OrderLine {
+    pub id: i32,
+    #[graphql(skip)]
+    pub order_id: i32,
+    /// `None` once the variant has been deleted
+    pub variant_id: Option<i32>,
+    pub sku: String,
+    pub product_name: String,
+    #[graphql(skip)]
+    pub unit_price_amount: Decimal,
+    pub currency: String,
+    pub quantity: i32,
+    /// Units handed to the carrier so far
+    pub shipped_quantity: i32,
+    #[graphql(skip)]
+    pub line_total_amount: Decimal,
+}
+
+#[ComplexObject]
+impl OrderLine {
+    async fn unit_price_amount(&self) -> String {
+        self.unit_price_amount.to_string()
+    }
+
+    async fn line_total_amount(&self) -> String {
+        self.line_total_amount.to_string()
+    }
+
+    async fn remaining_quantity(&self) -> i32 {
+        self.quantity - self.shipped_quantity
+    }
+}
+
+impl<'r> FromRow<'r, PgRow> for OrderLine {
+    fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            order_id: row.try_get("order_id")?,
+            variant_id: row.try_get("variant_id")?,
+            sku: row.try_get("sku")?,
+            product_name: row.try_get("product_name")?,
+            unit_price_amount: row.try_get("unit_price_amount")?,
+            currency: row.try_get("currency")?,
+            quantity: row.try_get("quantity")?,
+            shipped_quantity: row.try_get("shipped_quantity")?,
+            line_total_amount: row.try_get("line_total_amount")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+pub struct OrderStatusChange {
+    #[graphql(skip)]
+    pub order_id: i32,
+    /// `None` for the entry recorded when the order was placed
+    pub from_status: Option<OrderStatus>,
+    pub to_status: OrderStatus,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl<'r> FromRow<'r, PgRow> for OrderStatusChange {
+    fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            order_id: row.try_get("order_id")?,
+            from_status: row
+                .try_get::<Option<String>, _>("from_status")?
+                .map(|status| status.parse())
+                .transpose()
+                .map_err(decode_err)?,
+            to_status: row
+                .try_get::<String, _>("to_status")?
+                .parse()
+                .map_err(decode_err)?,
+            note: row.try_get("note")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+pub const ORDER_COLUMNS: &str = "id, order_number, cart_id, customer_id, status, currency, \
+     subtotal_amount, total_amount, shipping_address, billing_address, placed_at, updated_at, paid_at, shipped_at, \
+     fulfilled_at, cancelled_at, refunded_at";
+
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(complex)]
+pub struct Order {
+    pub id: i32,
+    pub order_number: String,
+    pub cart_id: Option<Uuid>,
+    pub customer_id: Option<i32>,
+    pub status: OrderStatus,
+    pub currency: String,
+    #[graphql(skip)]
+    pub subtotal_amount: Decimal,
+    #[graphql(skip)]
+    pub total_amount: Decimal,
+    pub shipping_address: Address,
+    pub billing_address: Address,
+    pub placed_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub paid_at: Option<NaiveDateTime>,
+    /// When the first shipment left
+    pub shipped_at: Option<NaiveDateTime>,
+    pub fulfilled_at: Option<NaiveDateTime>,
+    pub cancelled_at: Option<NaiveDateTime>,
+    pub refunded_at: Option<NaiveDateTime>,
+}
+
+impl<'r> FromRow<'r, PgRow> for Order {
+    fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            order_number: row.try_get("order_number")?,
+            cart_id: row.try_get("cart_id")?,
+            customer_id: row.try_get("customer_id")?,
+            status: row
+                .try_get::<String, _>("status")?
+                .parse()
+                .map_err(decode_err)?,
+            currency: row.try_get("currency")?,
+            subtotal_amount: row.try_get("subtotal_amount")?,
+            total_amount: row.try_get("total_amount")?,
+            shipping_address: row.try_get::<Json<Address>, _>("shipping_address")?.0,
+            billing_address: row.try_get::<Json<Address>, _>("billing_address")?.0,
+            placed_at: row.try_get("placed_at")?,
+            updated_at: row.try_get("updated_at")?,
+            paid_at: row.try_get("paid_at")?,
+            shipped_at: row.try_get("shipped_at")?,
+            fulfilled_at: row.try_get("fulfilled_at")?,
+            cancelled_at: row.try_get("cancelled_at")?,
+            refunded_at: row.try_get("refunded_at")?,
+        })
+    }
+}
+
+#[ComplexObject]
+impl Order {
+    async fn subtotal_amount(&self) -> String {
+        self.subtotal_amount.to_string()
+    }
+
+    async fn total_amount(&self) -> String {
+        self.total_amount.to_string()
+    }
+
+    async fn lines(&self, ctx: &Context<'_>) -> Result<Vec<OrderLine>> {
+        let loader = ctx.data_unchecked::<DataLoader<OrderLineLoader>>();
+
+        Ok(loader.load_one(self.id).await?.unwrap_or_default())
+    }
+
+    /// Every status the order has been in, oldest first
+    async fn status_history(&self, ctx: &Context<'_>) -> Result<Vec<OrderStatusChange>> {
+        let loader = ctx.data_unchecked::<DataLoader<OrderStatusHistoryLoader>>();
+
+        Ok(loader.load_one(self.id).await?.unwrap_or_default())
+    }
+
+    /// Every call made to the payment provider for this order, oldest first
+    async fn payments(&self, ctx: &Context<'_>) -> Result<Vec<PaymentAttempt>> {
+        let loader = ctx.data_unchecked::<DataLoader<PaymentAttemptLoader>>();
+
+        Ok(loader.load_one(self.id).await?.unwrap_or_default())
+    }
+}
+
+/// Append an entry to an order's status history
+pub async fn record_status_change(
+    conn: &mut PgConnection,
+    order_id: i32,
+    from_status: Option<OrderStatus>,
+    to_status: OrderStatus,
+    note: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO order_status_history (order_id, from_status, to_status, note)
+         VALUES ($1, $2, $3, $4)",
+    )
+    .bind(order_id)
+    .bind(from_status.map(OrderStatus::as_str))
+    .bind(to_status.as_str())
+    .bind(note)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Convert an open cart into an order, taking the sold units out of stock
+pub async fn place_order(
+    conn: &mut PgConnection,
+    cart_id: Uuid,
+    principal: &Principal,
+    shipping_address: Address,
+    billing_address: Address,
+) -> Result<Order, OrderError> {
+    // Lock the cart so concurrent checkouts of the same cart serialise
+    let (status, owner): (String, Option<i32>) =
+        sqlx::query_as("SELECT status, customer_id FROM carts WHERE id = $1 FOR UPDATE")
+            .bind(cart_id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .ok_or(CartError::UnknownCart(cart_id))?;
+    if !principal.can_access(owner) {
+        return Err(CartError::UnknownCart(cart_id).into());
+    }
+    if status != CartStatus::Open.as_str() {
+        return Err(CartError::CartNotOpen(cart_id).into());
+    }
+
+    let cart = load_cart(&mut *conn, cart_id)
+        .await?
+        .ok_or(CartError::UnknownCart(cart_id))?;
+    if cart.lines.is_empty() {
+        return Err(OrderError::EmptyCart(cart.id));
+    }
+
+    let subtotal = cart.subtotal();
+
+    let order = sqlx::query_as::<_, Order>(&format!(
+        "INSERT INTO orders
+            (cart_id, customer_id, currency, subtotal_amount, total_amount,
+             shipping_address, billing_address)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         RETURNING {}",
+        ORDER_COLUMNS
+    ))
+    .bind(cart.id)
+    // A guest cart checked out by a logged-in customer becomes their order
+    .bind(cart.customer_id.or(principal.customer_id()))
+    .bind(&cart.currency)
+    .bind(subtotal)
+    .bind(subtotal)
+    .bind(Json(&shipping_address))
+    .bind(Json(&billing_address))
+    .fetch_one(&mut *conn)
+    .await?;
+
+    // Lock variants in a stable order to avoid deadlocks between checkouts
+    let mut lines = cart.lines;
+    lines.sort_by_key(|line| line.variant_id);
+
+    for line in &lines {
+        let mut variant = lock_variant_by_sku(&mut *conn, &line.sku).await?;
+        if !line.is_active {
+            return Err(OrderError::VariantInactive(line.sku.clone()));
+        }
+        if line.price_currency != cart.currency {
+            return Err(CartError::CurrencyMismatch {
+                sku: line.sku.clone(),
+                variant_currency: line.price_currency.clone(),
+                cart_currency: cart.currency.clone(),
+            }
+            .into());
+        }
+
+        allocate_sale(
+            &mut *conn,
+            &mut variant,
+            line.quantity,
+            order.id,
+            Some(&order.order_number),
+        )
+        .await?;
+
+        sqlx::query(
+            "INSERT INTO order_lines
+                (order_id, variant_id, sku, product_name, unit_price_amount, currency, quantity,
+                 line_total_amount)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(order.id)
+        .bind(line.variant_id)
+        .bind(&line.sku)
+        .bind(&line.product_name)
+        .bind(line.unit_price_amount)
+        .bind(&line.price_currency)
+        .bind(line.quantity)
+        .bind(line.line_total())
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    record_status_change(&mut *conn, order.id, None, order.status, None).await?;
+
+    sqlx::query("UPDATE carts SET status = $1 WHERE id = $2")
+        .bind(CartStatus::CheckedOut.as_str())
+        .bind(cart.id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(order)
+}
+
+/// Load an order by number and lock it for the rest of the transaction.
+///
+/// `NO KEY UPDATE` still serialises writers but lets payment attempts referencing the
+/// order be inserted from other connections while the lock is held.
+pub async fn lock_order(conn: &mut PgConnection, order_number: &str) -> Result<Order, OrderError> {
+    sqlx::query_as::<_, Order>(&format!(
+        "SELECT {} FROM orders WHERE order_number = $1 FOR NO KEY UPDATE",
+        ORDER_COLUMNS
+    ))
+    .bind(order_number)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| OrderError::UnknownOrder(order_number.to_string()))
+}
+
+/// Move a locked order to a new state, stamping the state's timestamp and
+/// appending to the status history
+pub async fn transition_order(
+    conn: &mut PgConnection,
+    order: &Order,
+    to_status: OrderStatus,
+    note: Option<&str>,
+) -> Result<Order, OrderError> {
+    if !order.status.can_transition_to(to_status) {
+        return Err(OrderError::InvalidTransition {
+            from: order.status,
+            to: to_status,
+        });
+    }
+
+    let mut assignments = vec!["status = $1".to_string()];
+    if let Some(column) = to_status.timestamp_column() {
+        assignments.push(format!("{column} = COALESCE({column}, CURRENT_TIMESTAMP)"));
+    }
+    // Shipping everything at once skips PARTIALLY_SHIPPED but still counts as shipped
+    if to_status == OrderStatus::Fulfilled {
+        assignments.push("shipped_at = COALESCE(shipped_at, CURRENT_TIMESTAMP)".to_string());
+    }
+
+    let updated = sqlx::query_as::<_, Order>(&format!(
+        "UPDATE orders SET {} WHERE id = $2 RETURNING {}",
+        assignments.join(", "),
+        ORDER_COLUMNS
+    ))
+    .bind(to_status.as_str())
+    .bind(order.id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    record_status_change(&mut *conn, order.id, Some(order.status), to_status, note).await?;
+
+    Ok(updated)
+}
+
+/// Cancel an order and put the units it sold back where they were taken from
+pub async fn cancel_order(
+    conn: &mut PgConnection,
+    order_number: &str,
+    reason: Option<&str>,
+) -> Result<Order, OrderError> {
+    let order = lock_order(&mut *conn, order_number).await?;
+    let cancelled = transition_order(&mut *conn, &order, OrderStatus::Cancelled, reason).await?;
+
+    // Net of any earlier returns, in variant order so concurrent cancellations lock alike
+    let sold: Vec<(i32, String, Option<i32>, i64)> = sqlx::query_as(
+        "SELECT m.variant_id, v.sku, m.location_id, -SUM(m.quantity)::int8
+         FROM stock_movements m
+         JOIN product_variants v ON v.id = m.variant_id
+         WHERE m.order_id = $1 AND m.movement_type IN ($2, $3)
+         GROUP BY m.variant_id, v.sku, m.location_id
+         HAVING SUM(m.quantity) < 0
+         ORDER BY m.variant_id, m.location_id",
+    )
+    .bind(order.id)
+    .bind(StockMovementType::Sale.as_str())
+    .bind(StockMovementType::Return.as_str())
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let reason = format!("Order {} cancelled", order.order_number);
+    for (_, sku, location_id, quantity) in sold {
+        let mut variant = lock_any_variant_by_sku(&mut *conn, &sku).await?;
+        apply_stock_movement(
+            &mut *conn,
+            &mut variant,
+            NewStockMovement {
+                movement_type: StockMovementType::Return,
+                location_id,
+                quantity: quantity as i32,
+                reason: Some(&reason),
+                reference: Some(&order.order_number),
+                reservation_id: None,
+                order_id: Some(order.id),
+            },
+        )
+        .await?;
+    }
+
+    Ok(cancelled)
+}
+
+/// Give back what a committed cancellation or refund still holds. A failure is left to
+/// the payment release task rather than failing a change that already happened.
+async fn release_after_commit(pool: &PgPool, provider: &dyn PaymentProvider, order: &Order) {
+    if let Err(err) = release_payments(pool, provider, order).await {
+        tracing::warn!(
+            order_number = %order.order_number,
+            "Failed to release payments, leaving it to the retry task: {}",
+            err
+        );
+    }
+}
+
+/// Units of one order line handed to the carrier
+#[derive(InputObject)]
+pub struct ShipOrderLineInput {
+    pub line_id: i32,
+    pub quantity: i32,
+}
+
+/// Record a shipment against a paid order, moving it to PARTIALLY_SHIPPED
+/// or FULFILLED depending on what is left to ship
+pub async fn ship_order(
+    conn: &mut PgConnection,
+    order_number: &str,
+    lines: &[ShipOrderLineInput],
+    note: Option<&str>,
+) -> Result<Order, OrderError> {
+    let order = lock_order(&mut *conn, order_number).await?;
+    if !matches!(
+        order.status,
+        OrderStatus::Paid | OrderStatus::PartiallyShipped
+    ) {
+        return Err(OrderError::InvalidTransition {
+            from: order.status,
+            to: OrderStatus::PartiallyShipped,
+        });
+    }
+    if lines.is_empty() {
+        return Err(OrderError::InvalidQuantity(
+            "a shipment needs at least one line".to_string(),
+        ));
+    }
+
+    for line in lines {
+        if line.quantity <= 0 {
+            return Err(OrderError::InvalidQuantity(
+                "shipped quantity must be positive".to_string(),
+            ));
+        }
+
+        let (sku, remaining): (String, i32) = sqlx::query_as(
+            "SELECT sku, quantity - shipped_quantity FROM order_lines
+             WHERE id = $1 AND order_id = $2",
+        )
+        .bind(line.line_id)
+        .bind(order.id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(OrderError::UnknownOrderLine(line.line_id))?;
+
+        if line.quantity > remaining {
+            return Err(OrderError::OverShipment {
+                sku,
+                requested: line.quantity,
+                remaining,
+            });
+        }
+
+        sqlx::query(
+            "UPDATE order_lines SET shipped_quantity = shipped_quantity + $1 WHERE id = $2",
+        )
+        .bind(line.quantity)
+        .bind(line.line_id)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    let unshipped: i64 = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(quantity - shipped_quantity), 0)::int8 FROM order_lines
+         WHERE order_id = $1",
+    )
+    .bind(order.id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let next = if unshipped == 0 {
+        OrderStatus::Fulfilled
+    } else {
+        OrderStatus::PartiallyShipped
+    };
+    if next == order.status {
+        // Another partial shipment; nothing changes at the order level
+        return Ok(order);
+    }
+
+    transition_order(&mut *conn, &order, next, note).await
+}
+
+/// Fulfilment staff see every order; everyone else only their own
+fn can_view_order(principal: &Principal, owner: Option<i32>) -> bool {
+    principal.has_role(Role::InventoryManager) || principal.can_access(owner)
+}
+
+#[derive(Default)]
+pub struct OrderQuery;
+
+#[Object]
+impl OrderQuery {
+    /// An order by number; customers only see their own orders
+    async fn order(&self, ctx: &Context<'_>, number: String) -> Result<Option<Order>> {
+        let db = ctx.data::<PgPool>()?;
+        let principal = Principal::current(ctx);
+
+        let order = sqlx::query_as::<_, Order>(&format!(
+            "SELECT {} FROM orders WHERE order_number = $1",
+            ORDER_COLUMNS
+        ))
+        .bind(number)
+        .fetch_optional(db)
+        .await?;
+
+        Ok(order.filter(|order| can_view_order(principal, order.customer_id)))
+    }
+
+    /// The logged-in customer's orders, or every order for fulfilment staff,
+    /// most recently placed first
+    async fn orders(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        first: Option<i32>,
+        status: Option<OrderStatus>,
+    ) -> Result<Connection<String, Order, EmptyFields, EmptyFields>> {
+        let principal = Principal::current(ctx);
+        let customer_id = if principal.has_role(Role::InventoryManager) {
+            None
+        } else {
+            Some(principal.require_customer()?)
+        };
+        let db = ctx.data::<PgPool>()?;
+
+        let limit = first.unwrap_or(10).clamp(1, 100);
+        let offset = after
+            .and_then(|cursor| cursor.parse::<i32>().ok())
+            .map(|cursor| cursor + 1)
+            .unwrap_or(0);
+
+        // Fetch one extra row to know whether another page exists
+        let orders = sqlx::query_as::<_, Order>(&format!(
+            "SELECT {} FROM orders
+             WHERE ($1::int4 IS NULL OR customer_id = $1)
+               AND ($2::varchar IS NULL OR status = $2)
+             ORDER BY placed_at DESC, id DESC
+             LIMIT $3 OFFSET $4",
+            ORDER_COLUMNS
+        ))
+        .bind(customer_id)
+        .bind(status.map(OrderStatus::as_str))
+        .bind(limit + 1)
+        .bind(offset)
+        .fetch_all(db)
+        .await?;
+
+        let mut conn = Connection::new(offset > 0, orders.len() > limit as usize);
+        for (i, order) in orders.into_iter().take(limit as usize).enumerate() {
+            let cursor = (offset as usize + i).to_string();
+            conn.edges.push(Edge::new(cursor, order));
+        }
+
+        Ok(conn)
+    }
+}
+
+#[derive(Default)]
+pub struct OrderMutation;
+
+#[Object]
+impl OrderMutation {
+    /// Place an order for everything in an open cart and pay for it with the
+    /// client-side `paymentToken`. The billing address defaults to the shipping address.
+    ///
+    /// If payment fails the order is cancelled, its stock returned and the cart reopened.
+    async fn checkout(
+        &self,
+        ctx: &Context<'_>,
+        cart_id: Uuid,
+        shipping_address: Address,
+        billing_address: Option<Address>,
+        payment_token: String,
+    ) -> Result<Order> {
+        let db = ctx.data::<PgPool>()?;
+        let provider = ctx.data::<SharedPaymentProvider>()?;
+        let billing_address = billing_address.unwrap_or_else(|| shipping_address.clone());
+
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+        let order = place_order(
+            &mut tx,
+            cart_id,
+            Principal::current(ctx),
+            shipping_address,
+            billing_address,
+        )
+        .await?;
+        tx.commit().await?;
+
+        match collect_payment(db, provider.as_ref(), &order, &payment_token).await {
+            Ok(order) => Ok(order),
+            Err(err) => {
+                let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+                let cancelled =
+                    cancel_order(&mut tx, &order.order_number, Some(&err.to_string())).await?;
+                sqlx::query("UPDATE carts SET status = $1 WHERE id = $2")
+                    .bind(CartStatus::Open.as_str())
+                    .bind(cart_id)
+                    .execute(&mut *tx)
+                    .await?;
+                tx.commit().await?;
+
+                release_after_commit(db, provider.as_ref(), &cancelled).await;
+
+                Err(err.into())
+            }
+        }
+    }
+
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn mark_order_paid(
+        &self,
+        ctx: &Context<'_>,
+        order_number: String,
+        note: Option<String>,
+    ) -> Result<Order> {
+        let db = ctx.data::<PgPool>()?;
+
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+        let order = lock_order(&mut tx, &order_number).await?;
+        let order = transition_order(&mut tx, &order, OrderStatus::Paid, note.as_deref()).await?;
+        tx.commit().await?;
+
+        Ok(order)
+    }
+
+    /// Record units handed to the carrier
+    #[graphql(guard = "RoleGuard::new(Role::InventoryManager)")]
+    async fn ship_order(
+        &self,
+        ctx: &Context<'_>,
+        order_number: String,
+        lines: Vec<ShipOrderLineInput>,
+        note: Option<String>,
+    ) -> Result<Order> {
+        let db = ctx.data::<PgPool>()?;
+
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+        let order = ship_order(&mut tx, &order_number, &lines, note.as_deref()).await?;
+        tx.commit().await?;
+
+        Ok(order)
+    }
+
+    /// Cancel an unshipped order, return its stock and give back any payment.
+    /// Customers may cancel their own orders; admins may cancel any.
+    async fn cancel_order(
+        &self,
+        ctx: &Context<'_>,
+        order_number: String,
+        reason: Option<String>,
+    ) -> Result<Order> {
+        let db = ctx.data::<PgPool>()?;
+        let provider = ctx.data::<SharedPaymentProvider>()?;
+        let principal = Principal::current(ctx);
+
+        if !principal.has_role(Role::Admin) {
+            let customer_id = principal.require_customer()?;
+            let owner: Option<Option<i32>> =
+                sqlx::query_scalar("SELECT customer_id FROM orders WHERE order_number = $1")
+                    .bind(&order_number)
+                    .fetch_optional(db)
+                    .await?;
+            // Someone else's order looks like an unknown one
+            if owner.flatten() != Some(customer_id) {
+                return Err(OrderError::UnknownOrder(order_number).into());
+            }
+        }
+
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+        let order = cancel_order(&mut tx, &order_number, reason.as_deref()).await?;
+        tx.commit().await?;
+
+        release_after_commit(db, provider.as_ref(), &order).await;
+
+        Ok(order)
+    }
+
+    /// Refund everything captured for a shipped order
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn refund_order(
+        &self,
+        ctx: &Context<'_>,
+        order_number: String,
+        reason: Option<String>,
+    ) -> Result<Order> {
+        let db = ctx.data::<PgPool>()?;
+        let provider = ctx.data::<SharedPaymentProvider>()?;
+
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+        let order = lock_order(&mut tx, &order_number).await?;
+        let order =
+            transition_order(&mut tx, &order, OrderStatus::Refunded, reason.as_deref()).await?;
+        tx.commit().await?;
+
+        release_after_commit(db, provider.as_ref(), &order).await;
+
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [OrderStatus; 6] = [
+        OrderStatus::Pending,
+        OrderStatus::Paid,
+        OrderStatus::PartiallyShipped,
+        OrderStatus::Fulfilled,
+        OrderStatus::Cancelled,
+        OrderStatus::Refunded,
+    ];
+
+    #[test]
+    fn allowed_transitions() {
+        use OrderStatus::*;
+
+        let allowed = [
+            (Pending, Paid),
+            (Pending, Cancelled),
+            (Paid, PartiallyShipped),
+            (Paid, Fulfilled),
+            (Paid, Cancelled),
+            (PartiallyShipped, Fulfilled),
+            (PartiallyShipped, Refunded),
+            (Fulfilled, Refunded),
+        ];
+
+        for from in ALL {
+            for to in ALL {
+                assert_eq!(
+                    from.can_transition_to(to),
+                    allowed.contains(&(from, to)),
+                    "{} -> {}",
+                    from.as_str(),
+                    to.as_str()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn shipped_orders_cannot_be_cancelled() {
+        assert!(!OrderStatus::PartiallyShipped.can_transition_to(OrderStatus::Cancelled));
+        assert!(!OrderStatus::Fulfilled.can_transition_to(OrderStatus::Cancelled));
+    }
+
+    #[test]
+    fn final_states_stay_put() {
+        for to in ALL {
+            assert!(!OrderStatus::Cancelled.can_transition_to(to));
+            assert!(!OrderStatus::Refunded.can_transition_to(to));
+        }
+    }
+
+    #[test]
+    fn status_round_trips_through_its_column_value() {
+        for status in ALL {
+            assert_eq!(status.as_str().parse::<OrderStatus>().unwrap(), status);
+        }
+        assert!("SHIPPED".parse::<OrderStatus>().is_err());
+    }
+}
@@ -0,0 +1,579 @@
+use async_graphql::{ComplexObject, Enum, SimpleObject};
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use sqlx::{FromRow, PgPool, Row, postgres::PgRow};
+use std::{str::FromStr, sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::handlers::orders::{
+    ORDER_COLUMNS, Order, OrderError, OrderStatus, lock_order, transition_order,
+};
+use crate::payments::{Money, PaymentError, PaymentProvider, PaymentReceipt};
+use crate::shutdown::Shutdown;
+
+/// Payment provider shared by every request
+pub type SharedPaymentProvider = Arc<dyn PaymentProvider>;
+
+/// Advisory lock namespace; the second key is the order id, so payments of one order
+/// are released by one caller at a time
+const RELEASE_LOCK_NAMESPACE: i32 = 0x7061_7973;
+
+/// Most orders one run of the release task gives money back for
+const MAX_RELEASES_PER_RUN: i64 = 100;
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PaymentOperation {
+    Authorize,
+    Capture,
+    Void,
+    Refund,
+}
+
+impl PaymentOperation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Authorize => "AUTHORIZE",
+            Self::Capture => "CAPTURE",
+            Self::Void => "VOID",
+            Self::Refund => "REFUND",
+        }
+    }
+}
+
+impl FromStr for PaymentOperation {
+    type Err = OrderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "AUTHORIZE" => Ok(Self::Authorize),
+            "CAPTURE" => Ok(Self::Capture),
+            "VOID" => Ok(Self::Void),
+            "REFUND" => Ok(Self::Refund),
+            other => Err(OrderError::UnknownValue(other.to_string())),
+        }
+    }
+}
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PaymentAttemptStatus {
+    /// Sent to the provider without a recorded answer
+    Pending,
+    Succeeded,
+    /// Refused by the provider
+    Declined,
+    /// The provider could not be reached or errored
+    Failed,
+}
+
+impl PaymentAttemptStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "PENDING",
+            Self::Succeeded => "SUCCEEDED",
+            Self::Declined => "DECLINED",
+            Self::Failed => "FAILED",
+        }
+    }
+}
+
+impl FromStr for PaymentAttemptStatus {
+    type Err = OrderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "PENDING" => Ok(Self::Pending),
+            "SUCCEEDED" => Ok(Self::Succeeded),
+            "DECLINED" => Ok(Self::Declined),
+            "FAILED" => Ok(Self::Failed),
+            other => Err(OrderError::UnknownValue(other.to_string())),
+        }
+    }
+}
+
+fn decode_err(err: OrderError) -> sqlx::Error {
+    sqlx::Error::Decode(Box::new(err))
+}
+
+pub const PAYMENT_ATTEMPT_COLUMNS: &str = "id, order_id, idempotency_key, provider, operation, \
+     status, amount, currency, parent_attempt_id, provider_reference, failure_reason, \
+     created_at, completed_at";
+
+/// One call to the payment provider
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(complex)]
+pub struct PaymentAttempt {
+    pub id: i32,
+    #[graphql(skip)]
+    pub order_id: i32,
+    pub idempotency_key: Uuid,
+    pub provider: String,
+    pub operation: PaymentOperation,
+    pub status: PaymentAttemptStatus,
+    #[graphql(skip)]
+    pub amount: Decimal,
+    /// Always the order currency
+    pub currency: String,
+    /// Authorization a capture or void acted on, or capture a refund returned
+    pub parent_attempt_id: Option<i32>,
+    pub provider_reference: Option<String>,
+    pub failure_reason: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub completed_at: Option<NaiveDateTime>,
+}
+
+#[ComplexObject]
+impl PaymentAttempt {
+    async fn amount(&self) -> String {
+        self.amount.to_string()
+    }
+}
+
+impl PaymentAttempt {
+    fn reference(&self) -> &str {
+        self.provider_reference.as_deref().unwrap_or_default()
+    }
+}
+
+impl<'r> FromRow<'r, PgRow> for PaymentAttempt {
+    fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            order_id: row.try_get("order_id")?,
+            idempotency_key: row.try_get("idempotency_key")?,
+            provider: row.try_get("provider")?,
+            operation: row
+                .try_get::<String, _>("operation")?
+                .parse()
+                .map_err(decode_err)?,
+            status: row
+                .try_get::<String, _>("status")?
+                .parse()
+                .map_err(decode_err)?,
+            amount: row.try_get("amount")?,
+            currency: row.try_get("currency")?,
+            parent_attempt_id: row.try_get("parent_attempt_id")?,
+            provider_reference: row.try_get("provider_reference")?,
+            failure_reason: row.try_get("failure_reason")?,
+            created_at: row.try_get("created_at")?,
+            completed_at: row.try_get("completed_at")?,
+        })
+    }
+}
+
+/// The provider call behind an attempt
+enum ProviderCall<'a> {
+    Authorize { token: &'a str },
+    Capture { authorization: &'a PaymentAttempt },
+    Void { authorization: &'a PaymentAttempt },
+    Refund { capture: &'a PaymentAttempt },
+}
+
+impl ProviderCall<'_> {
+    fn operation(&self) -> PaymentOperation {
+        match self {
+            Self::Authorize { .. } => PaymentOperation::Authorize,
+            Self::Capture { .. } => PaymentOperation::Capture,
+            Self::Void { .. } => PaymentOperation::Void,
+            Self::Refund { .. } => PaymentOperation::Refund,
+        }
+    }
+
+    fn parent(&self) -> Option<&PaymentAttempt> {
+        match self {
+            Self::Authorize { .. } => None,
+            Self::Capture { authorization } | Self::Void { authorization } => Some(authorization),
+            Self::Refund { capture } => Some(capture),
+        }
+    }
+}
+
+/// The latest attempt at `operation` on `parent`, if it never got a final answer from
+/// the provider. Resending it under its idempotency key cannot move money twice.
+async fn unfinished_attempt(
+    pool: &PgPool,
+    provider: &dyn PaymentProvider,
+    parent: &PaymentAttempt,
+    operation: PaymentOperation,
+) -> Result<Option<PaymentAttempt>, OrderError> {
+    let latest = sqlx::query_as::<_, PaymentAttempt>(&format!(
+        "SELECT {} FROM payment_attempts
+         WHERE parent_attempt_id = $1 AND operation = $2 AND provider = $3
+         ORDER BY id DESC
+         LIMIT 1",
+        PAYMENT_ATTEMPT_COLUMNS
+    ))
+    .bind(parent.id)
+    .bind(operation.as_str())
+    .bind(provider.name())
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(latest.filter(|attempt| {
+        matches!(
+            attempt.status,
+            PaymentAttemptStatus::Pending | PaymentAttemptStatus::Failed
+        )
+    }))
+}
+
+/// Record a new attempt before its provider call is made
+async fn insert_attempt(
+    pool: &PgPool,
+    provider: &dyn PaymentProvider,
+    order_id: i32,
+    call: &ProviderCall<'_>,
+    amount: &Money,
+) -> Result<PaymentAttempt, OrderError> {
+    let attempt = sqlx::query_as::<_, PaymentAttempt>(&format!(
+        "INSERT INTO payment_attempts
+            (order_id, provider, operation, amount, currency, parent_attempt_id)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         RETURNING {}",
+        PAYMENT_ATTEMPT_COLUMNS
+    ))
+    .bind(order_id)
+    .bind(provider.name())
+    .bind(call.operation().as_str())
+    .bind(amount.amount)
+    .bind(&amount.currency)
+    .bind(call.parent().map(|parent| parent.id))
+    .fetch_one(pool)
+    .await?;
+
+    Ok(attempt)
+}
+
+/// Record an attempt, make the provider call and store its outcome.
+///
+/// Attempts are written straight to the pool and never inside an order transaction:
+/// callers commit the order change first, so money only moves for changes that stuck,
+/// and the record of a provider call survives whatever happens afterwards. A capture,
+/// void or refund whose last try got no final answer is sent again with the same
+/// idempotency key and amount instead of as a new attempt.
+async fn attempt_payment(
+    pool: &PgPool,
+    provider: &dyn PaymentProvider,
+    order_id: i32,
+    call: ProviderCall<'_>,
+    amount: &Money,
+) -> Result<PaymentAttempt, OrderError> {
+    let unfinished = match call.parent() {
+        Some(parent) => unfinished_attempt(pool, provider, parent, call.operation()).await?,
+        None => None,
+    };
+    let attempt = match unfinished {
+        Some(attempt) => attempt,
+        None => insert_attempt(pool, provider, order_id, &call, amount).await?,
+    };
+    let amount = &Money::new(attempt.amount, &attempt.currency);
+
+    let key = attempt.idempotency_key;
+    let result = match &call {
+        ProviderCall::Authorize { token } => provider.authorize(token, amount, key).await,
+        ProviderCall::Capture { authorization } => {
+            provider
+                .capture(authorization.reference(), amount, key)
+                .await
+        }
+        ProviderCall::Void { authorization } => provider.void(authorization.reference(), key).await,
+        ProviderCall::Refund { capture } => provider.refund(capture.reference(), amount, key).await,
+    };
+
+    let (status, reference, failure_reason) = match &result {
+        Ok(PaymentReceipt { reference }) => (
+            PaymentAttemptStatus::Succeeded,
+            Some(reference.as_str()),
+            None,
+        ),
+        Err(PaymentError::Declined(reason)) => {
+            (PaymentAttemptStatus::Declined, None, Some(reason.as_str()))
+        }
+        Err(PaymentError::Provider(reason)) => {
+            (PaymentAttemptStatus::Failed, None, Some(reason.as_str()))
+        }
+    };
+
+    let attempt = sqlx::query_as::<_, PaymentAttempt>(&format!(
+        "UPDATE payment_attempts
+         SET status = $1, provider_reference = $2, failure_reason = $3,
+             completed_at = CURRENT_TIMESTAMP
+         WHERE id = $4
+         RETURNING {}",
+        PAYMENT_ATTEMPT_COLUMNS
+    ))
+    .bind(status.as_str())
+    .bind(reference)
+    .bind(failure_reason)
+    .bind(attempt.id)
+    .fetch_one(pool)
+    .await?;
+
+    result?;
+    Ok(attempt)
+}
+
+/// Authorize and capture the order total, then mark the order as paid.
+///
+/// A failed capture voids the authorization so the customer is not left with a hold.
+pub async fn collect_payment(
+    pool: &PgPool,
+    provider: &dyn PaymentProvider,
+    order: &Order,
+    token: &str,
+) -> Result<Order, OrderError> {
+    let amount = Money::new(order.total_amount, &order.currency);
+
+    let authorization = attempt_payment(
+        pool,
+        provider,
+        order.id,
+        ProviderCall::Authorize { token },
+        &amount,
+    )
+    .await?;
+
+    let capture = match attempt_payment(
+        pool,
+        provider,
+        order.id,
+        ProviderCall::Capture {
+            authorization: &authorization,
+        },
+        &amount,
+    )
+    .await
+    {
+        Ok(capture) => capture,
+        Err(err) => {
+            let void = ProviderCall::Void {
+                authorization: &authorization,
+            };
+            if let Err(void_err) = attempt_payment(pool, provider, order.id, void, &amount).await {
+                tracing::error!(
+                    order_number = %order.order_number,
+                    "Failed to void authorization after capture failure: {}",
+                    void_err
+                );
+            }
+            return Err(err);
+        }
+    };
+
+    let mut tx = pool.begin().await?;
+    let locked = lock_order(&mut tx, &order.order_number).await?;
+    let note = format!("Payment captured ({})", capture.reference());
+    let paid = transition_order(&mut tx, &locked, OrderStatus::Paid, Some(&note)).await?;
+    tx.commit().await?;
+
+    Ok(paid)
+}
+
+/// SQL for the status of the latest attempt at `operation` (a bind parameter) on the
+/// payment attempt `a`
+fn latest_status_sql(operation: &str) -> String {
+    format!(
+        "SELECT l.status FROM payment_attempts l
+         WHERE l.parent_attempt_id = a.id AND l.operation = {}
+         ORDER BY l.id DESC
+         LIMIT 1",
+        operation
+    )
+}
+
+/// A declined void or refund will not succeed by retrying it, so it is reported for
+/// manual handling and the rest of the release carries on
+fn report_declined(
+    order: &Order,
+    result: Result<PaymentAttempt, OrderError>,
+) -> Result<(), OrderError> {
+    match result {
+        Ok(_) => Ok(()),
+        Err(OrderError::Payment(PaymentError::Declined(reason))) => {
+            tracing::error!(
+                order_number = %order.order_number,
+                "Payment release declined, needs manual handling: {}",
+                reason
+            );
+            Ok(())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Give back everything the order still holds: void uncaptured authorizations and
+/// refund the unrefunded part of every capture. Payments whose last void or refund was
+/// declined are skipped.
+///
+/// Call it only once the cancellation or refund has been committed. Running it again
+/// releases nothing twice: voids and refunds left without an answer are resent under
+/// their original idempotency key, and concurrent calls for the same order wait for
+/// each other.
+pub async fn release_payments(
+    pool: &PgPool,
+    provider: &dyn PaymentProvider,
+    order: &Order,
+) -> Result<(), OrderError> {
+    // Released when the transaction ends, even if this replica dies mid-release
+    let mut lock = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1, $2)")
+        .bind(RELEASE_LOCK_NAMESPACE)
+        .bind(order.id)
+        .execute(&mut *lock)
+        .await?;
+
+    let open_authorizations = sqlx::query_as::<_, PaymentAttempt>(&format!(
+        "SELECT {} FROM payment_attempts a
+         WHERE a.order_id = $1 AND a.operation = $2 AND a.status = $3
+           AND NOT EXISTS (
+               SELECT 1 FROM payment_attempts c
+               WHERE c.parent_attempt_id = a.id AND c.operation IN ($4, $5) AND c.status = $3
+           )
+           AND ({}) IS DISTINCT FROM $6
+         ORDER BY a.id",
+        PAYMENT_ATTEMPT_COLUMNS,
+        latest_status_sql("$5")
+    ))
+    .bind(order.id)
+    .bind(PaymentOperation::Authorize.as_str())
+    .bind(PaymentAttemptStatus::Succeeded.as_str())
+    .bind(PaymentOperation::Capture.as_str())
+    .bind(PaymentOperation::Void.as_str())
+    .bind(PaymentAttemptStatus::Declined.as_str())
+    .fetch_all(pool)
+    .await?;
+
+    for authorization in &open_authorizations {
+        let amount = Money::new(authorization.amount, &authorization.currency);
+        let void = attempt_payment(
+            pool,
+            provider,
+            order.id,
+            ProviderCall::Void { authorization },
+            &amount,
+        )
+        .await;
+        report_declined(order, void)?;
+    }
+
+    let captures = sqlx::query_as::<_, PaymentAttempt>(&format!(
+        "SELECT {} FROM payment_attempts a
+         WHERE a.order_id = $1 AND a.operation = $2 AND a.status = $3
+           AND ({}) IS DISTINCT FROM $5
+         ORDER BY a.id",
+        PAYMENT_ATTEMPT_COLUMNS,
+        latest_status_sql("$4")
+    ))
+    .bind(order.id)
+    .bind(PaymentOperation::Capture.as_str())
+    .bind(PaymentAttemptStatus::Succeeded.as_str())
+    .bind(PaymentOperation::Refund.as_str())
+    .bind(PaymentAttemptStatus::Declined.as_str())
+    .fetch_all(pool)
+    .await?;
+
+    for capture in &captures {
+        let refunded: Decimal = sqlx::query_scalar(
+            "SELECT COALESCE(SUM(amount), 0) FROM payment_attempts
+             WHERE parent_attempt_id = $1 AND operation = $2 AND status = $3",
+        )
+        .bind(capture.id)
+        .bind(PaymentOperation::Refund.as_str())
+        .bind(PaymentAttemptStatus::Succeeded.as_str())
+        .fetch_one(pool)
+        .await?;
+
+        let refundable = capture.amount - refunded;
+        if refundable <= Decimal::ZERO {
+            continue;
+        }
+
+        let refund = attempt_payment(
+            pool,
+            provider,
+            order.id,
+            ProviderCall::Refund { capture },
+            &Money::new(refundable, &capture.currency),
+        )
+        .await;
+        report_declined(order, refund)?;
+    }
+
+    lock.commit().await?;
+    Ok(())
+}
+
+/// Cancelled and refunded orders still holding money: an authorization neither
+/// captured nor voided, or a capture not refunded in full. Payments whose last void or
+/// refund was declined are left to be handled by hand.
+async fn orders_holding_payments(pool: &PgPool) -> Result<Vec<Order>, OrderError> {
+    let orders = sqlx::query_as::<_, Order>(&format!(
+        "SELECT {} FROM orders
+         WHERE status IN ($1, $2) AND EXISTS (
+             SELECT 1 FROM payment_attempts a
+             WHERE a.order_id = orders.id AND a.status = $3 AND (
+                 (a.operation = $4 AND NOT EXISTS (
+                     SELECT 1 FROM payment_attempts c
+                     WHERE c.parent_attempt_id = a.id AND c.operation IN ($5, $6)
+                       AND c.status = $3
+                 ) AND ({}) IS DISTINCT FROM $9)
+                 OR (a.operation = $5 AND a.amount > (
+                     SELECT COALESCE(SUM(r.amount), 0) FROM payment_attempts r
+                     WHERE r.parent_attempt_id = a.id AND r.operation = $7 AND r.status = $3
+                 ) AND ({}) IS DISTINCT FROM $9)
+             )
+         )
+         ORDER BY id
+         LIMIT $8",
+        ORDER_COLUMNS,
+        latest_status_sql("$6"),
+        latest_status_sql("$7")
+    ))
+    .bind(OrderStatus::Cancelled.as_str())
+    .bind(OrderStatus::Refunded.as_str())
+    .bind(PaymentAttemptStatus::Succeeded.as_str())
+    .bind(PaymentOperation::Authorize.as_str())
+    .bind(PaymentOperation::Capture.as_str())
+    .bind(PaymentOperation::Void.as_str())
+    .bind(PaymentOperation::Refund.as_str())
+    .bind(MAX_RELEASES_PER_RUN)
+    .bind(PaymentAttemptStatus::Declined.as_str())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(orders)
+}
+
+/// Spawn a task that retries giving back the payments of cancelled and refunded orders
+/// every `interval` until shutdown, for releases that failed after the order change
+pub fn spawn_payment_release(
+    pool: PgPool,
+    provider: SharedPaymentProvider,
+    interval: Duration,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => match orders_holding_payments(&pool).await {
+                    Ok(orders) => {
+                        for order in &orders {
+                            if let Err(err) = release_payments(&pool, provider.as_ref(), order).await {
+                                tracing::error!(
+                                    order_number = %order.order_number,
+                                    "Failed to release payments: {}",
+                                    err
+                                );
+                            }
+                        }
+                    }
+                    Err(err) => tracing::error!("Failed to find payments to release: {}", err),
+                },
+                _ = shutdown.wait() => break,
+            }
+        }
+    })
+}
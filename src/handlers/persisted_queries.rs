@@ -0,0 +1,75 @@
+use async_graphql::{Context, Object, Result, SimpleObject};
+use chrono::NaiveDateTime;
+use sqlx::{FromRow, PgPool};
+
+use crate::auth::{Role, RoleGuard};
+use crate::persisted_queries::{PersistedQueryError, query_hash};
+
+const PERSISTED_QUERY_COLUMNS: &str = "hash, name, registered, created_at";
+
+/// A stored operation, addressed by the SHA-256 hash of its text
+#[derive(Debug, Clone, SimpleObject, FromRow)]
+pub struct PersistedQuery {
+    pub hash: String,
+    pub name: Option<String>,
+    /// Whether the operation is allowed when only registered operations are accepted
+    pub registered: bool,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Default)]
+pub struct PersistedQueryMutation;
+
+#[Object]
+impl PersistedQueryMutation {
+    /// Allow an operation in strict persisted query mode. Clients must send exactly
+    /// this text, or its hash.
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn register_persisted_query(
+        &self,
+        ctx: &Context<'_>,
+        query: String,
+        name: Option<String>,
+    ) -> Result<PersistedQuery> {
+        let db = ctx.data::<PgPool>()?;
+
+        async_graphql::parser::parse_query(&query)
+            .map_err(|err| PersistedQueryError::InvalidQuery(err.to_string()))?;
+
+        let persisted = sqlx::query_as::<_, PersistedQuery>(&format!(
+            "INSERT INTO persisted_queries (hash, query, name, registered)
+             VALUES ($1, $2, $3, TRUE)
+             ON CONFLICT (hash) DO UPDATE
+             SET name = COALESCE(EXCLUDED.name, persisted_queries.name), registered = TRUE
+             RETURNING {}",
+            PERSISTED_QUERY_COLUMNS
+        ))
+        .bind(query_hash(&query))
+        .bind(&query)
+        .bind(name)
+        .fetch_one(db)
+        .await?;
+
+        Ok(persisted)
+    }
+
+    /// Stop accepting an operation in strict mode
+    #[graphql(guard = "RoleGuard::new(Role::Admin)")]
+    async fn unregister_persisted_query(
+        &self,
+        ctx: &Context<'_>,
+        hash: String,
+    ) -> Result<Option<PersistedQuery>> {
+        let db = ctx.data::<PgPool>()?;
+
+        let persisted = sqlx::query_as::<_, PersistedQuery>(&format!(
+            "UPDATE persisted_queries SET registered = FALSE WHERE hash = $1 RETURNING {}",
+            PERSISTED_QUERY_COLUMNS
+        ))
+        .bind(hash.to_ascii_lowercase())
+        .fetch_optional(db)
+        .await?;
+
+        Ok(persisted)
+    }
+}
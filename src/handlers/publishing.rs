@@ -0,0 +1,91 @@
+use sqlx::{PgConnection, PgPool};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::handlers::audit::{SCHEDULER_ACTOR, set_actor};
+use crate::shutdown::Shutdown;
+
+/// Advisory lock held by the replica applying publishing schedules
+const PUBLISHING_LOCK_KEY: i64 = 0x0070_7562_6c69_7368;
+
+/// Product ids published and withdrawn by one run of the scheduler
+#[derive(Debug, Default)]
+pub struct PublishingRun {
+    pub published: Vec<i32>,
+    pub unpublished: Vec<i32>,
+}
+
+/// Activate scheduled products whose `publish_at` has passed and archive active ones
+/// whose `unpublish_at` has passed, leaving deleted products alone. Only the transition
+/// still pending is applied, so running it again, or on several replicas, changes
+/// nothing further.
+pub async fn apply_schedules(conn: &mut PgConnection) -> Result<PublishingRun, sqlx::Error> {
+    let published = sqlx::query_scalar(
+        "UPDATE products SET status = 'ACTIVE'
+         WHERE status = 'SCHEDULED' AND publish_at <= NOW() AT TIME ZONE 'UTC'
+           AND deleted_at IS NULL
+         RETURNING id",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let unpublished = sqlx::query_scalar(
+        "UPDATE products SET status = 'ARCHIVED', unpublish_at = NULL
+         WHERE status = 'ACTIVE' AND unpublish_at <= NOW() AT TIME ZONE 'UTC'
+           AND deleted_at IS NULL
+         RETURNING id",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(PublishingRun {
+        published,
+        unpublished,
+    })
+}
+
+/// Apply schedules unless another replica is already doing so; `None` when skipped
+async fn run_once(pool: &PgPool) -> Result<Option<PublishingRun>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    // Released when the transaction ends, even if this replica dies mid-run
+    let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_xact_lock($1)")
+        .bind(PUBLISHING_LOCK_KEY)
+        .fetch_one(&mut *tx)
+        .await?;
+    if !locked {
+        return Ok(None);
+    }
+
+    set_actor(&mut tx, Some(SCHEDULER_ACTOR)).await?;
+    let run = apply_schedules(&mut tx).await?;
+    tx.commit().await?;
+    Ok(Some(run))
+}
+
+/// Spawn a task applying product publishing schedules every `interval` until shutdown
+pub fn spawn_scheduler(pool: PgPool, interval: Duration, shutdown: Shutdown) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => match run_once(&pool).await {
+                    Ok(Some(run)) => {
+                        if !run.published.is_empty() || !run.unpublished.is_empty() {
+                            tracing::info!(
+                                "Published products {:?}, unpublished products {:?}",
+                                run.published,
+                                run.unpublished
+                            );
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(err) => tracing::error!("Failed to apply publishing schedules: {}", err),
+                },
+                _ = shutdown.wait() => break,
+            }
+        }
+    })
+}
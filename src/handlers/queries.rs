@@ -1,28 +1,150 @@
 use async_graphql::{
-    Context, InputObject, Object, Result,
+    Context, InputObject, MergedObject, Object, Result,
     connection::{Connection, Edge, EmptyFields},
     dataloader::DataLoader,
 };
-use sqlx::{PgPool, Row, postgres::PgRow, prelude::FromRow};
+use chrono::NaiveDateTime;
+use sqlx::{PgPool, Postgres, QueryBuilder, Row, postgres::PgRow, prelude::FromRow};
 
+use crate::auth::Principal;
 use crate::handlers::{
+    api_keys::ApiKeyQuery,
+    audit::AuditQuery,
+    cart::CartQuery,
+    catalog::{ProductStatus, check_include_deleted, decode_err},
+    customers::CustomerQuery,
+    inventory::InventoryQuery,
     media_loader::{Media, ProductMediaLoadKey, ProductMediaLoader},
+    orders::OrderQuery,
+    revisions::RevisionQuery,
     variant_loader::{VariantGQL, VariantLoadKey, VariantLoader},
 };
+use crate::metrics::{MEDIA_LOADER, VARIANT_LOADER, metrics};
+use crate::response_cache::{CacheTags, PRODUCTS_TAG, category_tag, product_tag};
+use crate::slow_query_log::{Explain, take_arguments};
+use crate::telemetry::instrument_explainable_query;
 
-#[derive(InputObject)]
+#[derive(InputObject, Default)]
 pub struct ProductFilter {
     pub category_slug: Option<String>,
     pub in_stock: Option<bool>,
+    /// Evaluate `in_stock` against a single location instead of total stock
+    pub location_code: Option<String>,
+    /// Only list products in this state; ignored for the storefront, which only ever
+    /// lists ACTIVE products
+    pub status: Option<ProductStatus>,
+    /// Also list deleted products; admins only
+    pub include_deleted: Option<bool>,
 }
 
-#[derive(Debug, FromRow)]
+impl ProductFilter {
+    /// Append the filter's WHERE clause; `active_only` hides products that are not
+    /// yet (or no longer) on sale, as the public storefront should, and deleted
+    /// products and categories are hidden unless `include_deleted` is set
+    fn push_conditions(
+        &self,
+        query: &mut QueryBuilder<'_, Postgres>,
+        active_only: bool,
+        include_deleted: bool,
+    ) {
+        let mut separator = " WHERE ";
+
+        if !include_deleted {
+            query.push(separator).push("deleted_at IS NULL");
+            separator = " AND ";
+        }
+
+        let status = if active_only {
+            Some(ProductStatus::Active)
+        } else {
+            self.status
+        };
+        if let Some(status) = status {
+            query
+                .push(separator)
+                .push("status = ")
+                .push_bind(status.as_str());
+            separator = " AND ";
+        }
+
+        if let Some(category_slug) = &self.category_slug {
+            query.push(separator).push(
+                "EXISTS (SELECT 1 FROM product_category_junction pc \
+                 JOIN categories c ON c.id = pc.category_id \
+                 WHERE pc.product_id = products.id AND c.slug = ",
+            );
+            query.push_bind(category_slug.clone());
+            if !include_deleted {
+                query.push(" AND c.deleted_at IS NULL");
+            }
+            query.push(")");
+            separator = " AND ";
+        }
+
+        if let Some(in_stock) = self.in_stock {
+            query
+                .push(separator)
+                .push(if in_stock { "EXISTS" } else { "NOT EXISTS" });
+
+            match &self.location_code {
+                Some(location_code) => {
+                    query.push(
+                        " (SELECT 1 FROM product_variants v \
+                         JOIN stock_levels s ON s.variant_id = v.id \
+                         JOIN locations l ON l.id = s.location_id \
+                         WHERE v.product_id = products.id AND v.is_active \
+                         AND v.deleted_at IS NULL AND s.quantity > 0 AND l.code = ",
+                    );
+                    query.push_bind(location_code.clone()).push(")");
+                }
+                None => {
+                    query.push(
+                        " (SELECT 1 FROM product_variants v \
+                         WHERE v.product_id = products.id AND v.is_active \
+                         AND v.deleted_at IS NULL AND v.stock_quantity > 0)",
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Every column of a product, for queries that load whole products
+pub const PRODUCT_COLUMNS: &str =
+    "id, name, slug, description, status, publish_at, unpublish_at, deleted_at";
+
+#[derive(Debug)]
 pub struct ProductGQL {
     pub id: i32,
     pub name: Option<String>,
     pub slug: Option<String>,
     pub description: Option<String>,
-    pub status: Option<String>,
+    pub status: Option<ProductStatus>,
+    pub publish_at: Option<NaiveDateTime>,
+    pub unpublish_at: Option<NaiveDateTime>,
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+/// Catalogue queries only select the columns a request asks for, so every column but
+/// `id` may be missing from the row
+impl<'r> FromRow<'r, PgRow> for ProductGQL {
+    fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            name: row.try_get("name").ok(),
+            slug: row.try_get("slug").ok(),
+            description: row.try_get("description").ok(),
+            status: row
+                .try_get::<String, _>("status")
+                .ok()
+                .map(|status| status.parse())
+                .transpose()
+                .map_err(decode_err)?,
+            publish_at: row.try_get("publish_at").ok().flatten(),
+            unpublish_at: row.try_get("unpublish_at").ok().flatten(),
+            deleted_at: row.try_get("deleted_at").ok().flatten(),
+        })
+    }
 }
 
 #[Object]
@@ -43,11 +165,33 @@ impl ProductGQL {
         self.description.clone()
     }
 
-    async fn status(&self) -> Option<String> {
-        self.status.clone()
+    async fn status(&self) -> Option<ProductStatus> {
+        self.status
+    }
+
+    /// When a SCHEDULED product goes on sale (UTC)
+    async fn publish_at(&self) -> Option<NaiveDateTime> {
+        self.publish_at
+    }
+
+    /// When the product is withdrawn from sale and archived (UTC)
+    async fn unpublish_at(&self) -> Option<NaiveDateTime> {
+        self.unpublish_at
+    }
+
+    /// When the product was deleted; only admins can see deleted products
+    async fn deleted_at(&self) -> Option<NaiveDateTime> {
+        self.deleted_at
     }
 
-    async fn variants(&self, ctx: &Context<'_>, sku: Option<String>) -> Result<Vec<VariantGQL>> {
+    /// Variants of the product; `includeDeleted` (admins only) also lists deleted ones
+    async fn variants(
+        &self,
+        ctx: &Context<'_>,
+        sku: Option<String>,
+        include_deleted: Option<bool>,
+    ) -> Result<Vec<VariantGQL>> {
+        let include_deleted = check_include_deleted(ctx, include_deleted)?;
         let loader = ctx.data_unchecked::<DataLoader<VariantLoader>>();
 
         let mut cols = vec![];
@@ -67,13 +211,18 @@ impl ProductGQL {
         let key = VariantLoadKey {
             product_id: self.id,
             columns: cols,
-            sku: sku,
+            sku,
+            include_deleted,
         };
 
+        metrics().record_load(VARIANT_LOADER);
         Ok(loader.load_one(key).await?.unwrap_or_default())
     }
 
-    async fn media(&self, ctx: &Context<'_>) -> Result<Vec<Media>> {
+    /// Media of the product in display order; `includeDeleted` (admins only) also lists
+    /// deleted media
+    async fn media(&self, ctx: &Context<'_>, include_deleted: Option<bool>) -> Result<Vec<Media>> {
+        let include_deleted = check_include_deleted(ctx, include_deleted)?;
         let loader = ctx.data_unchecked::<DataLoader<ProductMediaLoader>>();
 
         let mut cols = vec!["id".to_string()];
@@ -93,29 +242,47 @@ impl ProductGQL {
         let key = ProductMediaLoadKey {
             product_id: self.id,
             columns: cols,
+            include_deleted,
         };
 
+        metrics().record_load(MEDIA_LOADER);
         Ok(loader.load_one(key).await?.unwrap_or_default())
     }
 }
 
-pub struct QueryRoot;
+/// Root query type combining the queries of each subsystem
+#[derive(MergedObject, Default)]
+pub struct QueryRoot(
+    CatalogQuery,
+    InventoryQuery,
+    CartQuery,
+    OrderQuery,
+    CustomerQuery,
+    ApiKeyQuery,
+    AuditQuery,
+    RevisionQuery,
+);
+
+#[derive(Default)]
+pub struct CatalogQuery;
 
 #[Object]
-impl QueryRoot {
+impl CatalogQuery {
+    #[graphql(cache_control(max_age = 60))]
     async fn products(
         &self,
         ctx: &Context<'_>,
         after: Option<String>,
-        before: Option<String>,
+        #[graphql(name = "before")] _before: Option<String>,
         first: Option<i32>,
-        last: Option<i32>,
-        // filter: Option<ProductFilter>,
+        #[graphql(name = "last")] _last: Option<i32>,
+        filter: Option<ProductFilter>,
     ) -> Result<Connection<String, ProductGQL, EmptyFields, EmptyFields>, String> {
         let db = ctx
             .data::<PgPool>()
             .expect("Db Connection is not available");
         let selection = ctx.look_ahead();
+        let active_only = !Principal::current(ctx).is_staff();
 
         let limit = first.unwrap_or(10);
         let offset = after
@@ -159,25 +326,33 @@ impl QueryRoot {
         //     }
         // }
 
-        let sql = format!(
-            "SELECT {} FROM products LIMIT $1 OFFSET $2",
-            cols.join(", ")
-        );
+        let filter = filter.unwrap_or_default();
+        let include_deleted =
+            check_include_deleted(ctx, filter.include_deleted).map_err(|err| err.to_string())?;
+        CacheTags::add(ctx, PRODUCTS_TAG);
+        if let Some(category_slug) = &filter.category_slug {
+            CacheTags::add(ctx, category_tag(category_slug));
+        }
 
-        println!("{}", sql);
-
-        let products = match sqlx::query(&sql)
-            .bind(limit)
-            .bind(offset)
-            .map(|row: PgRow| ProductGQL {
-                id: row.get("id"),
-                name: row.try_get("name").ok(),
-                slug: row.try_get("slug").ok(),
-                description: row.try_get("description").ok(),
-                status: row.try_get("status").ok(),
-            })
-            .fetch_all(db)
-            .await
+        let mut query =
+            QueryBuilder::<Postgres>::new(format!("SELECT {} FROM products", cols.join(", ")));
+        filter.push_conditions(&mut query, active_only, include_deleted);
+        query.push(" LIMIT ").push_bind(limit);
+        query.push(" OFFSET ").push_bind(offset);
+
+        let sql = query.sql().to_string();
+        let arguments = take_arguments(query.build()).map_err(|err| err.to_string())?;
+        let explain = Explain {
+            pool: db,
+            arguments: &arguments,
+        };
+        let products = match instrument_explainable_query(
+            "products",
+            &sql,
+            explain,
+            sqlx::query_as_with::<_, ProductGQL, _>(&sql, arguments.clone()).fetch_all(db),
+        )
+        .await
         {
             Ok(res) => res,
             Err(err) => return Err(err.to_string()),
@@ -193,11 +368,16 @@ impl QueryRoot {
         Ok(conn)
     }
 
+    #[graphql(cache_control(max_age = 60))]
     async fn product(
         &self,
         ctx: &Context<'_>,
         product_id: i32, // filter: Option<ProductFilter>,
+        // Also find the product when it was deleted; admins only
+        include_deleted: Option<bool>,
     ) -> Result<Option<ProductGQL>, String> {
+        let include_deleted =
+            check_include_deleted(ctx, include_deleted).map_err(|err| err.to_string())?;
         let db = ctx
             .data::<PgPool>()
             .expect("Db Connection is not available");
@@ -220,23 +400,34 @@ impl QueryRoot {
             }
         }
 
+        CacheTags::add(ctx, product_tag(product_id));
+
+        // Drafts and archived products are only visible to staff
         let sql = format!(
-            "SELECT {} FROM products WHERE id=$1 LIMIT $2",
+            "SELECT {} FROM products
+             WHERE id=$1 AND ($2 OR status = 'ACTIVE') AND ($3 OR deleted_at IS NULL) LIMIT $4",
             cols.join(", ")
         );
 
-        let product = match sqlx::query(&sql)
-            .bind(product_id)
-            .bind(1)
-            .map(|row: PgRow| ProductGQL {
-                id: row.get("id"),
-                name: row.try_get("name").ok(),
-                slug: row.try_get("slug").ok(),
-                description: row.try_get("description").ok(),
-                status: row.try_get("status").ok(),
-            })
-            .fetch_optional(db)
-            .await
+        let arguments = take_arguments(
+            sqlx::query(&sql)
+                .bind(product_id)
+                .bind(Principal::current(ctx).is_staff())
+                .bind(include_deleted)
+                .bind(1),
+        )
+        .map_err(|err| err.to_string())?;
+        let explain = Explain {
+            pool: db,
+            arguments: &arguments,
+        };
+        let product = match instrument_explainable_query(
+            "product",
+            &sql,
+            explain,
+            sqlx::query_as_with::<_, ProductGQL, _>(&sql, arguments.clone()).fetch_optional(db),
+        )
+        .await
         {
             Ok(res) => res,
             Err(err) => return Err(err.to_string()),
@@ -0,0 +1,240 @@
+use async_graphql::{Context, Object, Result, SimpleObject};
+use chrono::NaiveDateTime;
+use sqlx::{FromRow, PgConnection, PgPool};
+
+use crate::auth::{Principal, Role, RoleGuard};
+use crate::handlers::{
+    audit,
+    catalog::{CatalogError, lock_product},
+    queries::{PRODUCT_COLUMNS, ProductGQL},
+};
+
+/// A product as it was after a change, numbered from 1 per product
+#[derive(Debug, Clone, FromRow, SimpleObject)]
+pub struct ProductRevision {
+    pub product_id: i32,
+    pub revision: i32,
+    /// Who made the change, as in the audit log
+    pub actor: Option<String>,
+    /// Core fields, variants (without stock), attributes and category links
+    pub snapshot: serde_json::Value,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Default)]
+pub struct RevisionQuery;
+
+#[Object]
+impl RevisionQuery {
+    /// Revisions of a product, newest first
+    #[graphql(
+        guard = "RoleGuard::new(Role::CatalogueEditor)",
+        cache_control(private)
+    )]
+    async fn product_revisions(
+        &self,
+        ctx: &Context<'_>,
+        product_id: i32,
+    ) -> Result<Vec<ProductRevision>> {
+        let db = ctx.data::<PgPool>()?;
+
+        let revisions = sqlx::query_as::<_, ProductRevision>(
+            "SELECT product_id, revision, actor, snapshot, created_at
+             FROM product_revisions
+             WHERE product_id = $1
+             ORDER BY revision DESC",
+        )
+        .bind(product_id)
+        .fetch_all(db)
+        .await?;
+
+        Ok(revisions)
+    }
+}
+
+#[derive(Default)]
+pub struct RevisionMutation;
+
+#[Object]
+impl RevisionMutation {
+    /// Bring a product's core fields, variants, attributes and category links back to
+    /// an earlier revision. The revert is recorded as a new revision.
+    #[graphql(guard = "RoleGuard::new(Role::CatalogueEditor)")]
+    async fn revert_product(
+        &self,
+        ctx: &Context<'_>,
+        product_id: i32,
+        revision: i32,
+    ) -> Result<ProductGQL> {
+        let db = ctx.data::<PgPool>()?;
+        let mut tx = audit::begin(db, Principal::current(ctx)).await?;
+
+        let reverted = revert_product(&mut tx, product_id, revision).await?;
+
+        tx.commit().await?;
+        Ok(reverted)
+    }
+}
+
+/// Apply a revision's snapshot to a product.
+///
+/// The lifecycle (status, publishing schedule and deletion) is left as it is, since it
+/// only changes through its own mutations. Stock is never reverted. Variants missing
+/// from the snapshot are soft-deleted; variants deleted since are recreated without
+/// stock. Links to categories that no longer exist are skipped. Fails without changing
+/// anything if another product now has the snapshot's slug, or another variant one of
+/// its SKUs.
+pub async fn revert_product(
+    conn: &mut PgConnection,
+    product_id: i32,
+    revision: i32,
+) -> Result<ProductGQL, CatalogError> {
+    let product = lock_product(conn, product_id).await?;
+
+    let snapshot: serde_json::Value = sqlx::query_scalar(
+        "SELECT snapshot FROM product_revisions WHERE product_id = $1 AND revision = $2",
+    )
+    .bind(product.id)
+    .bind(revision)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(CatalogError::UnknownRevision {
+        product_id,
+        revision,
+    })?;
+
+    // Slugs and SKUs may have been taken by other rows since; name them rather than
+    // failing on the unique constraint
+    let taken_slug: Option<String> =
+        sqlx::query_scalar("SELECT slug FROM products WHERE slug = $2 ->> 'slug' AND id <> $1")
+            .bind(product.id)
+            .bind(&snapshot["product"])
+            .fetch_optional(&mut *conn)
+            .await?;
+    if let Some(slug) = taken_slug {
+        return Err(CatalogError::SlugTaken(slug));
+    }
+
+    let taken_sku: Option<String> = sqlx::query_scalar(
+        "SELECT v.sku
+         FROM jsonb_to_recordset($1) AS v(id integer, sku varchar)
+         JOIN product_variants taken ON taken.sku = v.sku AND taken.id <> v.id
+         ORDER BY v.sku
+         LIMIT 1",
+    )
+    .bind(&snapshot["variants"])
+    .fetch_optional(&mut *conn)
+    .await?;
+    if let Some(sku) = taken_sku {
+        return Err(CatalogError::SkuTaken(sku));
+    }
+
+    sqlx::query(
+        "UPDATE products SET name = s.name, slug = s.slug, description = s.description
+         FROM jsonb_to_record($2) AS s(name varchar, slug varchar, description text)
+         WHERE products.id = $1",
+    )
+    .bind(product.id)
+    .bind(&snapshot["product"])
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO product_variants
+            (id, product_id, sku, price_amount, price_currency, cost_amount, attributes,
+             is_active, reorder_point, deleted_at)
+         SELECT v.id, $1, v.sku, v.price_amount, v.price_currency, v.cost_amount, v.attributes,
+                v.is_active, v.reorder_point, v.deleted_at
+         FROM jsonb_to_recordset($2) AS v(
+             id integer, sku varchar, price_amount numeric, price_currency varchar,
+             cost_amount numeric, attributes jsonb, is_active boolean, reorder_point integer,
+             deleted_at timestamp
+         )
+         ON CONFLICT (id) DO UPDATE SET
+             sku = EXCLUDED.sku,
+             price_amount = EXCLUDED.price_amount,
+             price_currency = EXCLUDED.price_currency,
+             cost_amount = EXCLUDED.cost_amount,
+             attributes = EXCLUDED.attributes,
+             is_active = EXCLUDED.is_active,
+             reorder_point = EXCLUDED.reorder_point,
+             deleted_at = EXCLUDED.deleted_at
+         WHERE product_variants.product_id = $1",
+    )
+    .bind(product.id)
+    .bind(&snapshot["variants"])
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "UPDATE product_variants SET deleted_at = NOW() AT TIME ZONE 'UTC'
+         WHERE product_id = $1 AND deleted_at IS NULL
+           AND id NOT IN (SELECT (v ->> 'id')::integer FROM jsonb_array_elements($2) v)",
+    )
+    .bind(product.id)
+    .bind(&snapshot["variants"])
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO product_attributes
+            (product_id, namespace, attribute_key, attribute_value, value_type, is_searchable)
+         SELECT $1, a.namespace, a.attribute_key, a.attribute_value, a.value_type, a.is_searchable
+         FROM jsonb_to_recordset($2) AS a(
+             namespace varchar, attribute_key varchar, attribute_value text, value_type varchar,
+             is_searchable boolean
+         )
+         ON CONFLICT (product_id, namespace, attribute_key) DO UPDATE SET
+             attribute_value = EXCLUDED.attribute_value,
+             value_type = EXCLUDED.value_type,
+             is_searchable = EXCLUDED.is_searchable",
+    )
+    .bind(product.id)
+    .bind(&snapshot["attributes"])
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "DELETE FROM product_attributes
+         WHERE product_id = $1 AND (namespace, attribute_key) NOT IN (
+             SELECT a ->> 'namespace', a ->> 'attribute_key' FROM jsonb_array_elements($2) a
+         )",
+    )
+    .bind(product.id)
+    .bind(&snapshot["attributes"])
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO product_category_junction (product_id, category_id, is_primary)
+         SELECT $1, c.category_id, c.is_primary
+         FROM jsonb_to_recordset($2) AS c(category_id integer, is_primary boolean)
+         WHERE EXISTS (SELECT 1 FROM categories WHERE categories.id = c.category_id)
+         ON CONFLICT (product_id, category_id) DO UPDATE SET is_primary = EXCLUDED.is_primary",
+    )
+    .bind(product.id)
+    .bind(&snapshot["categories"])
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "DELETE FROM product_category_junction
+         WHERE product_id = $1 AND category_id NOT IN (
+             SELECT (c ->> 'category_id')::integer FROM jsonb_array_elements($2) c
+         )",
+    )
+    .bind(product.id)
+    .bind(&snapshot["categories"])
+    .execute(&mut *conn)
+    .await?;
+
+    let reverted = sqlx::query_as::<_, ProductGQL>(&format!(
+        "SELECT {} FROM products WHERE id = $1",
+        PRODUCT_COLUMNS
+    ))
+    .bind(product.id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(reverted)
+}
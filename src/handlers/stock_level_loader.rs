@@ -0,0 +1,54 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_graphql::dataloader::Loader;
+use sqlx::PgPool;
+
+use crate::handlers::inventory::LocationStock;
+use crate::telemetry::instrument_query;
+
+/// Loads per-location stock levels keyed by variant id
+pub struct StockLevelLoader {
+    pub pool: PgPool,
+}
+
+impl Loader<i32> for StockLevelLoader {
+    type Value = Vec<LocationStock>;
+    type Error = Arc<sqlx::Error>;
+
+    #[tracing::instrument(
+        name = "dataloader.load",
+        skip_all,
+        fields(loader = "stock_levels", keys = keys.len())
+    )]
+    async fn load(&self, keys: &[i32]) -> Result<HashMap<i32, Self::Value>, Self::Error> {
+        if keys.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let sql = "SELECT s.variant_id, l.id AS location_id, l.code AS location_code,
+                  l.name AS location_name, s.quantity
+                  FROM stock_levels s
+                  JOIN locations l ON l.id = s.location_id
+                  WHERE s.variant_id = ANY($1)
+                  ORDER BY l.code";
+        let rows = instrument_query(
+            "stock_levels",
+            sql,
+            sqlx::query_as::<_, LocationStock>(sql)
+                .bind(keys)
+                .fetch_all(&self.pool),
+        )
+        .await?;
+
+        // Create result map for each key
+        let mut result_map: HashMap<i32, Vec<LocationStock>> = HashMap::new();
+        for key in keys {
+            result_map.insert(*key, Vec::new());
+        }
+        for row in rows {
+            result_map.entry(row.variant_id).or_default().push(row);
+        }
+
+        Ok(result_map)
+    }
+}
@@ -0,0 +1,125 @@
+use async_graphql::{
+    Context, Result, Subscription,
+    futures_util::{Stream, StreamExt, future},
+};
+use sqlx::PgPool;
+
+use crate::auth::{Principal, Role, RoleGuard};
+use crate::handlers::{
+    events::{CatalogEvent, CatalogEvents, LowStockAlert, PriceChange, StockChange},
+    queries::{PRODUCT_COLUMNS, ProductGQL},
+};
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Emits the product every time its row is written; non-staff only see it while ACTIVE,
+    /// and nobody sees it once deleted
+    async fn product_updated(
+        &self,
+        ctx: &Context<'_>,
+        id: i32,
+    ) -> impl Stream<Item = Result<ProductGQL>> {
+        let events = ctx.data_unchecked::<CatalogEvents>();
+        let db = ctx.data_unchecked::<PgPool>().clone();
+        let include_inactive = Principal::current(ctx).is_staff();
+
+        events
+            .stream()
+            .filter(move |event| {
+                future::ready(
+                    matches!(event, CatalogEvent::ProductUpdated(product) if product.id == id),
+                )
+            })
+            .filter_map(move |_| {
+                let db = db.clone();
+                async move {
+                    sqlx::query_as::<_, ProductGQL>(&format!(
+                        "SELECT {} FROM products
+                         WHERE id = $1 AND ($2 OR status = 'ACTIVE') AND deleted_at IS NULL",
+                        PRODUCT_COLUMNS
+                    ))
+                    .bind(id)
+                    .bind(include_inactive)
+                    .fetch_optional(&db)
+                    .await
+                    .map_err(Into::into)
+                    .transpose()
+                }
+            })
+    }
+
+    #[graphql(guard = "RoleGuard::staff()")]
+    async fn variant_stock_changed(
+        &self,
+        ctx: &Context<'_>,
+        sku: String,
+    ) -> impl Stream<Item = StockChange> {
+        let events = ctx.data_unchecked::<CatalogEvents>();
+
+        events.stream().filter_map(move |event| {
+            future::ready(match event {
+                CatalogEvent::StockChanged(change) if change.sku == sku => Some(change),
+                _ => None,
+            })
+        })
+    }
+
+    /// Emits every price change of the product's variants; like `productUpdated`,
+    /// non-staff only see them while the product is ACTIVE, and nobody once it is deleted
+    async fn price_changed(
+        &self,
+        ctx: &Context<'_>,
+        product_id: i32,
+    ) -> impl Stream<Item = Result<PriceChange>> {
+        let events = ctx.data_unchecked::<CatalogEvents>();
+        let db = ctx.data_unchecked::<PgPool>().clone();
+        let include_inactive = Principal::current(ctx).is_staff();
+
+        events
+            .stream()
+            .filter_map(move |event| {
+                future::ready(match event {
+                    CatalogEvent::PriceChanged(change) if change.product_id == product_id => {
+                        Some(change)
+                    }
+                    _ => None,
+                })
+            })
+            .filter_map(move |change| {
+                let db = db.clone();
+                async move {
+                    let visible: bool = match sqlx::query_scalar(
+                        "SELECT EXISTS (
+                             SELECT 1 FROM products
+                             WHERE id = $1 AND ($2 OR status = 'ACTIVE') AND deleted_at IS NULL
+                         )",
+                    )
+                    .bind(product_id)
+                    .bind(include_inactive)
+                    .fetch_one(&db)
+                    .await
+                    {
+                        Ok(visible) => visible,
+                        Err(err) => return Some(Err(err.into())),
+                    };
+
+                    visible.then_some(Ok(change))
+                }
+            })
+    }
+
+    /// Emits when any variant's stock drops below its reorder point
+    #[graphql(guard = "RoleGuard::new(Role::InventoryManager)")]
+    async fn low_stock(&self, ctx: &Context<'_>) -> impl Stream<Item = LowStockAlert> {
+        let events = ctx.data_unchecked::<CatalogEvents>();
+
+        events.stream().filter_map(|event| {
+            future::ready(match event {
+                CatalogEvent::LowStock(alert) => Some(alert),
+                _ => None,
+            })
+        })
+    }
+}
@@ -1,25 +1,64 @@
-use async_graphql::{Object, dataloader::*};
+use async_graphql::{Context, Object, Result, dataloader::*};
+use chrono::NaiveDateTime;
 use rust_decimal::Decimal;
-use sqlx::{PgPool, Row, postgres::PgRow};
+use sqlx::{FromRow, PgPool, Row, postgres::PgRow};
 use std::{collections::HashMap, sync::Arc};
 
+use crate::auth::RoleGuard;
+use crate::handlers::{
+    inventory::{AVAILABLE_QUANTITY_SQL, LocationStock},
+    stock_level_loader::StockLevelLoader,
+};
+use crate::metrics::{VARIANT_LOADER, metrics};
+use crate::slow_query_log::{Explain, take_arguments};
+use crate::telemetry::instrument_explainable_query;
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct VariantLoadKey {
     pub product_id: i32,
     pub columns: Vec<String>,
     pub sku: Option<String>,
+    /// Also load deleted variants; only honoured for admins
+    pub include_deleted: bool,
 }
 
-#[derive(Debug, Clone, sqlx::FromRow)]
+#[derive(Debug, Clone)]
 pub struct VariantGQL {
     pub id: i32,
     pub product_id: i32,
     pub sku: String,
     pub price_amount: Decimal,
     pub price_currency: String,
+    /// Supplier unit cost, staff only
+    pub cost_amount: Option<Decimal>,
     pub stock_quantity: i32,
+    /// Stock minus active reservations
+    pub available_quantity: i32,
+    pub reorder_point: Option<i32>,
     pub is_active: bool,
     pub attributes: serde_json::Value,
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+/// Variant queries only select the columns a request asks for, so every column but
+/// `id` and `product_id` may be missing from the row
+impl<'r> FromRow<'r, PgRow> for VariantGQL {
+    fn from_row(row: &'r PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            product_id: row.try_get("product_id")?,
+            sku: row.try_get("sku").unwrap_or_default(),
+            price_amount: row.try_get("price_amount").unwrap_or_default(),
+            price_currency: row.try_get("price_currency").unwrap_or_default(),
+            cost_amount: row.try_get("cost_amount").unwrap_or_default(),
+            attributes: row.try_get("attributes").unwrap_or_default(),
+            is_active: row.try_get("is_active").unwrap_or_default(),
+            stock_quantity: row.try_get("stock_quantity").unwrap_or_default(),
+            available_quantity: row.try_get("available_quantity").unwrap_or_default(),
+            reorder_point: row.try_get("reorder_point").unwrap_or_default(),
+            deleted_at: row.try_get("deleted_at").ok().flatten(),
+        })
+    }
 }
 
 #[Object]
@@ -44,17 +83,46 @@ impl VariantGQL {
         &self.price_currency
     }
 
+    #[graphql(guard = "RoleGuard::staff()", cache_control(private))]
+    async fn cost_amount(&self) -> Option<String> {
+        self.cost_amount.map(|amount| amount.to_string())
+    }
+
     async fn attributes(&self) -> &serde_json::Value {
         &self.attributes
     }
 
+    /// Total on-hand stock across all locations
+    #[graphql(guard = "RoleGuard::staff()", cache_control(private))]
     async fn stock_quantity(&self) -> i32 {
         self.stock_quantity
     }
 
+    #[graphql(guard = "RoleGuard::staff()", cache_control(private))]
+    async fn stock_by_location(&self, ctx: &Context<'_>) -> Result<Vec<LocationStock>> {
+        let loader = ctx.data_unchecked::<DataLoader<StockLevelLoader>>();
+
+        Ok(loader.load_one(self.id).await?.unwrap_or_default())
+    }
+
+    async fn available_quantity(&self) -> i32 {
+        self.available_quantity
+    }
+
+    /// Stock level below which a low stock alert is raised
+    #[graphql(guard = "RoleGuard::staff()", cache_control(private))]
+    async fn reorder_point(&self) -> Option<i32> {
+        self.reorder_point
+    }
+
     async fn is_active(&self) -> bool {
         self.is_active
     }
+
+    /// When the variant was deleted; only admins can list deleted variants
+    async fn deleted_at(&self) -> Option<NaiveDateTime> {
+        self.deleted_at
+    }
 }
 
 pub struct VariantLoader {
@@ -65,6 +133,11 @@ impl Loader<VariantLoadKey> for VariantLoader {
     type Value = Vec<VariantGQL>;
     type Error = Arc<sqlx::Error>;
 
+    #[tracing::instrument(
+        name = "dataloader.load",
+        skip_all,
+        fields(loader = VARIANT_LOADER, keys = keys.len())
+    )]
     async fn load(
         &self,
         keys: &[VariantLoadKey],
@@ -78,42 +151,48 @@ impl Loader<VariantLoadKey> for VariantLoader {
         let product_ids: Vec<i32> = keys.iter().map(|k| k.product_id).collect();
 
         // Ensure required columns are included
-        let mut safe_columns = vec!["id", "sku", "product_id"];
+        let mut safe_columns = vec!["id", "sku", "product_id", "deleted_at"];
         for col in columns {
-            safe_columns.push(col)
+            match col.as_str() {
+                // Computed from active reservations rather than stored
+                "available_quantity" => safe_columns.push(AVAILABLE_QUANTITY_SQL),
+                // Resolved through the StockLevelLoader, or always selected
+                "stock_by_location" | "deleted_at" => {}
+                col => safe_columns.push(col),
+            }
         }
         safe_columns.dedup();
 
-        let mut sql = format!(
-            "SELECT {} FROM product_variants WHERE product_id = ANY($1)",
+        // Deleted variants are filtered per key below when only some keys want them
+        let include_deleted = keys.iter().any(|key| key.include_deleted);
+        // Only narrow the query by SKU when every key asks for one; each key's own SKU is
+        // applied below
+        let skus: Option<Vec<&str>> = keys.iter().map(|key| key.sku.as_deref()).collect();
+        let sql = format!(
+            "SELECT {} FROM product_variants
+             WHERE product_id = ANY($1) AND ($2 OR deleted_at IS NULL)
+               AND ($3::varchar[] IS NULL OR sku = ANY($3))",
             safe_columns.join(", ")
         );
 
-        let sku = &keys[0].sku;
-
-        match sku {
-            Some(sku) => {
-                sql = format!("{} AND sku = '{}'", sql, sku);
-            }
-            None => {}
+        metrics().record_batch(VARIANT_LOADER, keys.len());
+        let arguments = take_arguments(
+            sqlx::query(&sql)
+                .bind(&product_ids)
+                .bind(include_deleted)
+                .bind(skus),
+        )?;
+        let explain = Explain {
+            pool: &self.pool,
+            arguments: &arguments,
         };
-
-        println!("{}", sql);
-
-        let rows = sqlx::query(&sql)
-            .bind(&product_ids)
-            .map(|row: PgRow| VariantGQL {
-                id: row.get("id"),
-                product_id: row.get("product_id"),
-                sku: row.try_get("sku").unwrap_or_default(),
-                price_amount: row.try_get("price_amount").unwrap_or_default(),
-                price_currency: row.try_get("price_currency").unwrap_or_default(),
-                attributes: row.try_get("attributes").unwrap_or_default(),
-                is_active: row.try_get("is_active").unwrap_or_default(),
-                stock_quantity: row.try_get("stock_quantity").unwrap_or_default(),
-            })
-            .fetch_all(&self.pool)
-            .await?;
+        let rows = instrument_explainable_query(
+            VARIANT_LOADER,
+            &sql,
+            explain,
+            sqlx::query_as_with::<_, VariantGQL, _>(&sql, arguments.clone()).fetch_all(&self.pool),
+        )
+        .await?;
 
         // Create result map for each key
         let mut result_map: HashMap<VariantLoadKey, Vec<VariantGQL>> = HashMap::new();
@@ -121,6 +200,8 @@ impl Loader<VariantLoadKey> for VariantLoader {
             let variants: Vec<VariantGQL> = rows
                 .iter()
                 .filter(|v| v.product_id == key.product_id)
+                .filter(|v| key.include_deleted || v.deleted_at.is_none())
+                .filter(|v| key.sku.as_ref().is_none_or(|sku| *sku == v.sku))
                 .cloned()
                 .collect();
             result_map.insert(key.clone(), variants);
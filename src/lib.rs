@@ -3,13 +3,26 @@
 //! This crate provides a complete GraphQL API for managing products, categories,
 //! and product variants in an e-commerce application.
 
+pub mod auth;
+pub mod config;
 pub mod domain;
 pub mod handlers;
+pub mod metrics;
 pub mod models;
+pub mod payments;
+pub mod persisted_queries;
+pub mod rate_limit;
+pub mod response_cache;
 pub mod routes;
 pub mod schema;
+pub mod shutdown;
+pub mod slow_query_log;
+pub mod telemetry;
 
 // Re-export commonly used types for convenience
+pub use config::AppConfig;
 pub use domain::*;
 pub use models::*;
+pub use payments::{FakePaymentProvider, PaymentProvider};
 pub use routes::{ApiSchema, create_router, create_schema, print_server_info};
+pub use shutdown::Shutdown;
@@ -1,21 +1,33 @@
 use dotenv::dotenv;
-use rust_store::{create_router, print_server_info};
+use rust_store::auth::{ApiKeyStore, Authenticator, SessionStore};
+use rust_store::handlers::{
+    events::CatalogEvents,
+    payments::{self, SharedPaymentProvider},
+    publishing,
+};
+use rust_store::persisted_queries::{
+    MemoryPersistedQueryStore, PersistedQueries, PersistedQueryBackend, PersistedQueryMode,
+    PersistedQueryStore, PgPersistedQueryStore,
+};
+use rust_store::response_cache::{self, InMemoryResponseCache, ResponseCache};
+use rust_store::telemetry::Telemetry;
+use rust_store::{
+    AppConfig, FakePaymentProvider, Shutdown, create_router, create_schema, print_server_info,
+    shutdown,
+};
 use sqlx::postgres::PgPoolOptions;
+use std::net::SocketAddr;
+use std::sync::Arc;
 use tokio::net::TcpListener;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| format!("{}=trace", env!("CARGO_CRATE_NAME")).into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    let config = AppConfig::from_env();
 
+    // Initialize tracing, exporting spans when a collector is configured
+    let telemetry = Telemetry::init(config.log_format, config.otlp_endpoint.as_deref());
+    config.slow_query_log().install();
     let db_connection_str = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
 
     // Create the application router with all routes and middleware
@@ -23,7 +35,65 @@ async fn main() {
         .connect(&db_connection_str)
         .await
         .expect("can't connect to database");
-    let app = create_router(pool.clone());
+
+    rust_store::metrics::metrics().register_pool(&pool);
+
+    // Relay catalogue NOTIFY events to GraphQL subscriptions until shutdown
+    let shutdown = Shutdown::new();
+    let events = CatalogEvents::default();
+    events.spawn_listener(pool.clone(), shutdown.clone());
+
+    // Put scheduled products on sale and withdraw them on time; one replica at a time
+    publishing::spawn_scheduler(pool.clone(), config.publishing_interval, shutdown.clone());
+
+    // Only the in-memory gateway exists so far; swap in a real provider here
+    let payments: SharedPaymentProvider = Arc::new(FakePaymentProvider::new());
+
+    // Retry refunds and voids that failed after an order was cancelled or refunded
+    payments::spawn_payment_release(
+        pool.clone(),
+        payments.clone(),
+        config.payment_release_interval,
+        shutdown.clone(),
+    );
+
+    let sessions = SessionStore::new(pool.clone(), config.session_ttl);
+
+    // Registered operations only live in Postgres, so strict mode always reads from there
+    let persisted_queries = match config.persisted_query_mode {
+        PersistedQueryMode::Off => None,
+        mode => {
+            let store: Arc<dyn PersistedQueryStore> = match (mode, config.persisted_query_backend) {
+                (PersistedQueryMode::Strict, _) | (_, PersistedQueryBackend::Postgres) => {
+                    Arc::new(PgPersistedQueryStore::new(pool.clone()))
+                }
+                (_, PersistedQueryBackend::Memory) => Arc::new(MemoryPersistedQueryStore::new(
+                    config.persisted_query_cache_size,
+                )),
+            };
+            Some(PersistedQueries::new(mode, store))
+        }
+    };
+
+    // Cached catalogue responses are dropped as catalogue change notifications arrive
+    let response_cache = (config.response_cache_capacity > 0).then(|| {
+        let cache: Arc<dyn ResponseCache> =
+            Arc::new(InMemoryResponseCache::new(config.response_cache_capacity));
+        response_cache::spawn_invalidator(cache.clone(), events.clone(), shutdown.clone());
+        cache
+    });
+
+    let auth = Authenticator::new(sessions.clone(), ApiKeyStore::new(pool.clone()));
+    let schema = create_schema(
+        pool.clone(),
+        events,
+        payments,
+        sessions,
+        config.budgets(),
+        persisted_queries,
+        response_cache,
+    );
+    let app = create_router(schema, auth, &config.cors, shutdown.clone());
 
     // Create TCP listener
     let listener = TcpListener::bind("0.0.0.0:3000")
@@ -33,8 +103,46 @@ async fn main() {
     // Print server startup information
     print_server_info();
 
-    // Start the server
-    axum::serve(listener, app)
+    // Start the server; it stops accepting connections once shutdown is triggered
+    let server_shutdown = shutdown.clone();
+    let mut server = tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(async move { server_shutdown.wait().await })
         .await
-        .expect("Failed to start server");
+    });
+
+    tokio::select! {
+        result = &mut server => {
+            result
+                .expect("Server task panicked")
+                .expect("Failed to start server");
+        }
+        _ = shutdown::wait_for_signal() => {
+            tracing::info!(
+                "Shutdown signal received, draining in-flight requests for up to {:?}",
+                config.shutdown_timeout
+            );
+            shutdown.trigger();
+
+            match tokio::time::timeout(config.shutdown_timeout, &mut server).await {
+                Ok(result) => {
+                    if let Err(err) = result.expect("Server task panicked") {
+                        tracing::error!("Server error during shutdown: {}", err);
+                    }
+                }
+                Err(_) => {
+                    tracing::warn!("Shutdown deadline elapsed, dropping remaining connections");
+                    server.abort();
+                }
+            }
+        }
+    }
+
+    // Let in-use connections finish and close the pool before exiting
+    pool.close().await;
+    tracing::info!("Database pool closed, server stopped");
+    telemetry.shutdown();
 }
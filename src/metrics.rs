@@ -0,0 +1,311 @@
+//! Prometheus metrics for GraphQL operations, resolvers, DataLoaders, SQL and the pool
+
+use async_graphql::{
+    Response, ServerResult, Value,
+    extensions::{
+        Extension, ExtensionContext, ExtensionFactory, NextExecute, NextRequest, NextResolve,
+        ResolveInfo,
+    },
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, PullingGauge, Registry, TextEncoder,
+};
+use sqlx::PgPool;
+use std::{
+    collections::HashSet,
+    sync::{Arc, LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Label used for operations sent without a name
+const ANONYMOUS_OPERATION: &str = "anonymous";
+
+/// Label used for requests that failed before an operation was validated
+const INVALID_OPERATION: &str = "invalid";
+
+/// Label used for operation names beyond [`MAX_OPERATION_LABELS`]
+const OTHER_OPERATION: &str = "other";
+
+/// Distinct operation names given a label of their own; clients choose the names, so
+/// the number of series has to be bounded
+const MAX_OPERATION_LABELS: usize = 200;
+
+/// Loader label of the [`VariantLoader`](crate::handlers::variant_loader::VariantLoader)
+pub const VARIANT_LOADER: &str = "variants";
+
+/// Loader label of the [`ProductMediaLoader`](crate::handlers::media_loader::ProductMediaLoader)
+pub const MEDIA_LOADER: &str = "media";
+
+/// Reads one statistic of the connection pool
+type PoolReading = fn(&PgPool) -> f64;
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// The process-wide metrics
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// Every metric the server exports, on a registry of its own
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    resolver_duration: HistogramVec,
+    loader_batch_size: HistogramVec,
+    loader_keys_requested: IntCounterVec,
+    loader_keys_loaded: IntCounterVec,
+    sql_duration: HistogramVec,
+    operation_labels: Mutex<HashSet<String>>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let requests = IntCounterVec::new(
+            Opts::new(
+                "graphql_requests_total",
+                "GraphQL requests by operation name and outcome",
+            ),
+            &["operation", "status"],
+        )
+        .expect("valid metric");
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "graphql_request_duration_seconds",
+                "Time to answer a GraphQL request, by operation name",
+            ),
+            &["operation"],
+        )
+        .expect("valid metric");
+        let resolver_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "graphql_resolver_duration_seconds",
+                "Time spent in each field resolver, including its children",
+            )
+            .buckets(vec![
+                0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+            ]),
+            &["parent_type", "field"],
+        )
+        .expect("valid metric");
+        let loader_batch_size = HistogramVec::new(
+            HistogramOpts::new(
+                "dataloader_batch_size",
+                "Keys fetched by a single DataLoader batch",
+            )
+            .buckets(vec![1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0]),
+            &["loader"],
+        )
+        .expect("valid metric");
+        let loader_keys_requested = IntCounterVec::new(
+            Opts::new(
+                "dataloader_keys_requested_total",
+                "Keys requested from a DataLoader by resolvers",
+            ),
+            &["loader"],
+        )
+        .expect("valid metric");
+        let loader_keys_loaded = IntCounterVec::new(
+            Opts::new(
+                "dataloader_keys_loaded_total",
+                "Keys a DataLoader fetched from the database; requested keys not loaded were \
+                 answered by another lookup in the same batch",
+            ),
+            &["loader"],
+        )
+        .expect("valid metric");
+        let sql_duration = HistogramVec::new(
+            HistogramOpts::new("sql_query_duration_seconds", "Time to run a SQL query").buckets(
+                vec![0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5],
+            ),
+            &["query"],
+        )
+        .expect("valid metric");
+
+        for collector in [
+            Box::new(requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(request_duration.clone()),
+            Box::new(resolver_duration.clone()),
+            Box::new(loader_batch_size.clone()),
+            Box::new(loader_keys_requested.clone()),
+            Box::new(loader_keys_loaded.clone()),
+            Box::new(sql_duration.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("metric registered once");
+        }
+
+        Self {
+            registry,
+            requests,
+            request_duration,
+            resolver_duration,
+            loader_batch_size,
+            loader_keys_requested,
+            loader_keys_loaded,
+            sql_duration,
+            operation_labels: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// The `operation` label for a validated operation named `name`
+    fn operation_label(&self, name: &str) -> String {
+        let mut labels = self
+            .operation_labels
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        if labels.contains(name) {
+            return name.to_string();
+        }
+        if labels.len() >= MAX_OPERATION_LABELS {
+            return OTHER_OPERATION.to_string();
+        }
+        labels.insert(name.to_string());
+        name.to_string()
+    }
+
+    /// Export the size of the connection pool, read on every scrape
+    pub fn register_pool(&self, pool: &PgPool) {
+        let gauges: [(&str, &str, PoolReading); 3] = [
+            ("db_pool_connections", "Open database connections", |pool| {
+                pool.size() as f64
+            }),
+            (
+                "db_pool_idle_connections",
+                "Idle database connections",
+                |pool| pool.num_idle() as f64,
+            ),
+            (
+                "db_pool_max_connections",
+                "Most database connections the pool will open",
+                |pool| pool.options().get_max_connections() as f64,
+            ),
+        ];
+
+        for (name, help, read) in gauges {
+            let pool = pool.clone();
+            let gauge =
+                PullingGauge::new(name, help, Box::new(move || read(&pool))).expect("valid metric");
+            if let Err(err) = self.registry.register(Box::new(gauge)) {
+                tracing::warn!("Failed to register {}: {}", name, err);
+            }
+        }
+    }
+
+    /// Count a key requested from a DataLoader
+    pub fn record_load(&self, loader: &str) {
+        self.loader_keys_requested
+            .with_label_values(&[loader])
+            .inc();
+    }
+
+    /// Record a DataLoader batch fetching `keys` keys
+    pub fn record_batch(&self, loader: &str, keys: usize) {
+        self.loader_batch_size
+            .with_label_values(&[loader])
+            .observe(keys as f64);
+        self.loader_keys_loaded
+            .with_label_values(&[loader])
+            .inc_by(keys as u64);
+    }
+
+    /// Record how long a SQL query took
+    pub fn observe_query(&self, query: &str, elapsed: Duration) {
+        self.sql_duration
+            .with_label_values(&[query])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Every metric in the Prometheus text format
+    pub fn render(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+/// Schema extension recording request counts and latencies per operation name, and
+/// the time spent in each field resolver. Requests failing to parse or validate are
+/// counted under a single `invalid` label.
+pub struct RequestMetrics;
+
+impl ExtensionFactory for RequestMetrics {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(RequestMetricsExtension {
+            operation: Mutex::new(None),
+        })
+    }
+}
+
+struct RequestMetricsExtension {
+    operation: Mutex<Option<String>>,
+}
+
+#[async_trait::async_trait]
+impl Extension for RequestMetricsExtension {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        let started = Instant::now();
+        let response = next.run(ctx).await;
+
+        let operation = self
+            .operation
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .take()
+            .unwrap_or_else(|| INVALID_OPERATION.to_string());
+        let status = if response.is_ok() { "ok" } else { "error" };
+
+        let metrics = metrics();
+        metrics
+            .requests
+            .with_label_values(&[operation.as_str(), status])
+            .inc();
+        metrics
+            .request_duration
+            .with_label_values(&[operation.as_str()])
+            .observe(started.elapsed().as_secs_f64());
+
+        response
+    }
+
+    /// Only reached once the document has parsed and validated
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let label = operation_name.map_or_else(
+            || ANONYMOUS_OPERATION.to_string(),
+            |name| metrics().operation_label(name),
+        );
+        *self.operation.lock().unwrap_or_else(|err| err.into_inner()) = Some(label);
+        next.run(ctx, operation_name).await
+    }
+
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        // Introspection is answered from the schema and is not worth a series
+        if info.is_for_introspection {
+            return next.run(ctx, info).await;
+        }
+
+        let parent_type = info.parent_type;
+        let field = info.name;
+        let started = Instant::now();
+        let value = next.run(ctx, info).await;
+        metrics()
+            .resolver_duration
+            .with_label_values(&[parent_type, field])
+            .observe(started.elapsed().as_secs_f64());
+
+        value
+    }
+}
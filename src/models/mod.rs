@@ -3,6 +3,7 @@ use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
+use uuid::Uuid;
 
 /// Database model for products table
 #[derive(Queryable, Selectable, Identifiable, Serialize, Deserialize, Debug, Clone)]
@@ -15,6 +16,9 @@ pub struct DbProduct {
     pub status: String,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub publish_at: Option<NaiveDateTime>,
+    pub unpublish_at: Option<NaiveDateTime>,
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
 /// Insert struct for products
@@ -38,6 +42,7 @@ pub struct DbCategory {
     pub sort_order: Option<i32>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
 /// Insert struct for categories
@@ -67,6 +72,9 @@ pub struct DbProductVariant {
     pub is_active: bool,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub reorder_point: Option<i32>,
+    pub cost_amount: Option<bigdecimal::BigDecimal>,
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
 /// Insert struct for product_variants
@@ -80,6 +88,8 @@ pub struct NewDbProductVariant {
     pub stock_quantity: i32,
     pub attributes: Option<JsonValue>,
     pub is_active: bool,
+    pub reorder_point: Option<i32>,
+    pub cost_amount: Option<bigdecimal::BigDecimal>,
 }
 
 /// Database model for product_media table
@@ -100,6 +110,7 @@ pub struct DbProductMedia {
     pub is_primary: bool,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
 /// Insert struct for product_media
@@ -169,3 +180,329 @@ pub struct NewDbProductCategoryJunction {
     pub category_id: i32,
     pub is_primary: bool,
 }
+
+/// Database model for locations table
+#[derive(Queryable, Selectable, Identifiable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = locations)]
+pub struct DbLocation {
+    pub id: i32,
+    pub code: String,
+    pub name: String,
+    pub is_active: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Insert struct for locations
+#[derive(Insertable, Serialize, Deserialize)]
+#[diesel(table_name = locations)]
+pub struct NewDbLocation {
+    pub code: String,
+    pub name: String,
+    pub is_active: bool,
+}
+
+/// Database model for stock_levels table
+#[derive(
+    Queryable, Selectable, Identifiable, Associations, Serialize, Deserialize, Debug, Clone,
+)]
+#[diesel(belongs_to(DbProductVariant, foreign_key = variant_id))]
+#[diesel(belongs_to(DbLocation, foreign_key = location_id))]
+#[diesel(table_name = stock_levels)]
+pub struct DbStockLevel {
+    pub id: i32,
+    pub variant_id: i32,
+    pub location_id: i32,
+    pub quantity: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Insert struct for stock_levels
+#[derive(Insertable, Serialize, Deserialize)]
+#[diesel(table_name = stock_levels)]
+pub struct NewDbStockLevel {
+    pub variant_id: i32,
+    pub location_id: i32,
+    pub quantity: i32,
+}
+
+/// Database model for stock_reservations table
+#[derive(
+    Queryable, Selectable, Identifiable, Associations, Serialize, Deserialize, Debug, Clone,
+)]
+#[diesel(belongs_to(DbProductVariant, foreign_key = variant_id))]
+#[diesel(table_name = stock_reservations)]
+pub struct DbStockReservation {
+    pub id: i32,
+    pub variant_id: i32,
+    pub quantity: i32,
+    pub status: String,
+    pub reference: Option<String>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub released_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Insert struct for stock_reservations
+#[derive(Insertable, Serialize, Deserialize)]
+#[diesel(table_name = stock_reservations)]
+pub struct NewDbStockReservation {
+    pub variant_id: i32,
+    pub quantity: i32,
+    pub reference: Option<String>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// Database model for stock_movements table
+#[derive(
+    Queryable, Selectable, Identifiable, Associations, Serialize, Deserialize, Debug, Clone,
+)]
+#[diesel(belongs_to(DbProductVariant, foreign_key = variant_id))]
+#[diesel(table_name = stock_movements)]
+pub struct DbStockMovement {
+    pub id: i32,
+    pub variant_id: i32,
+    pub movement_type: String,
+    pub quantity: i32,
+    pub stock_after: i32,
+    pub reason: Option<String>,
+    pub reference: Option<String>,
+    pub reservation_id: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub location_id: Option<i32>,
+    pub order_id: Option<i32>,
+}
+
+/// Insert struct for stock_movements
+#[derive(Insertable, Serialize, Deserialize)]
+#[diesel(table_name = stock_movements)]
+pub struct NewDbStockMovement {
+    pub variant_id: i32,
+    pub movement_type: String,
+    pub quantity: i32,
+    pub stock_after: i32,
+    pub reason: Option<String>,
+    pub reference: Option<String>,
+    pub reservation_id: Option<i32>,
+    pub location_id: Option<i32>,
+    pub order_id: Option<i32>,
+}
+
+/// Database model for carts table
+#[derive(Queryable, Selectable, Identifiable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = carts)]
+pub struct DbCart {
+    pub id: Uuid,
+    pub customer_id: Option<i32>,
+    pub currency: String,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Insert struct for carts
+#[derive(Insertable, Serialize, Deserialize)]
+#[diesel(table_name = carts)]
+pub struct NewDbCart {
+    pub customer_id: Option<i32>,
+    pub currency: String,
+}
+
+/// Database model for cart_lines table
+#[derive(
+    Queryable, Selectable, Identifiable, Associations, Serialize, Deserialize, Debug, Clone,
+)]
+#[diesel(belongs_to(DbCart, foreign_key = cart_id))]
+#[diesel(belongs_to(DbProductVariant, foreign_key = variant_id))]
+#[diesel(table_name = cart_lines)]
+pub struct DbCartLine {
+    pub id: i32,
+    pub cart_id: Uuid,
+    pub variant_id: i32,
+    pub quantity: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Insert struct for cart_lines
+#[derive(Insertable, Serialize, Deserialize)]
+#[diesel(table_name = cart_lines)]
+pub struct NewDbCartLine {
+    pub cart_id: Uuid,
+    pub variant_id: i32,
+    pub quantity: i32,
+}
+
+/// Database model for orders table
+#[derive(Queryable, Selectable, Identifiable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = orders)]
+pub struct DbOrder {
+    pub id: i32,
+    pub order_number: String,
+    pub cart_id: Option<Uuid>,
+    pub customer_id: Option<i32>,
+    pub status: String,
+    pub currency: String,
+    pub subtotal_amount: bigdecimal::BigDecimal,
+    pub total_amount: bigdecimal::BigDecimal,
+    pub shipping_address: JsonValue,
+    pub billing_address: JsonValue,
+    pub placed_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub paid_at: Option<NaiveDateTime>,
+    pub shipped_at: Option<NaiveDateTime>,
+    pub fulfilled_at: Option<NaiveDateTime>,
+    pub cancelled_at: Option<NaiveDateTime>,
+    pub refunded_at: Option<NaiveDateTime>,
+}
+
+/// Database model for order_lines table
+#[derive(
+    Queryable, Selectable, Identifiable, Associations, Serialize, Deserialize, Debug, Clone,
+)]
+#[diesel(belongs_to(DbOrder, foreign_key = order_id))]
+#[diesel(table_name = order_lines)]
+pub struct DbOrderLine {
+    pub id: i32,
+    pub order_id: i32,
+    pub variant_id: Option<i32>,
+    pub sku: String,
+    pub product_name: String,
+    pub unit_price_amount: bigdecimal::BigDecimal,
+    pub currency: String,
+    pub quantity: i32,
+    pub line_total_amount: bigdecimal::BigDecimal,
+    pub created_at: NaiveDateTime,
+    pub shipped_quantity: i32,
+}
+
+/// Database model for order_status_history table
+#[derive(
+    Queryable, Selectable, Identifiable, Associations, Serialize, Deserialize, Debug, Clone,
+)]
+#[diesel(belongs_to(DbOrder, foreign_key = order_id))]
+#[diesel(table_name = order_status_history)]
+pub struct DbOrderStatusHistory {
+    pub id: i32,
+    pub order_id: i32,
+    pub from_status: Option<String>,
+    pub to_status: String,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Database model for payment_attempts table
+#[derive(
+    Queryable, Selectable, Identifiable, Associations, Serialize, Deserialize, Debug, Clone,
+)]
+#[diesel(belongs_to(DbOrder, foreign_key = order_id))]
+#[diesel(table_name = payment_attempts)]
+pub struct DbPaymentAttempt {
+    pub id: i32,
+    pub order_id: i32,
+    pub idempotency_key: Uuid,
+    pub provider: String,
+    pub operation: String,
+    pub status: String,
+    pub amount: bigdecimal::BigDecimal,
+    pub currency: String,
+    pub parent_attempt_id: Option<i32>,
+    pub provider_reference: Option<String>,
+    pub failure_reason: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub completed_at: Option<NaiveDateTime>,
+}
+
+/// Database model for customers table
+#[derive(Queryable, Selectable, Identifiable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = customers)]
+pub struct DbCustomer {
+    pub id: i32,
+    pub email: String,
+    #[serde(skip_serializing)]
+    pub password_hash: String,
+    pub name: Option<String>,
+    pub last_login_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub role: String,
+}
+
+/// Database model for customer_sessions table
+#[derive(
+    Queryable, Selectable, Identifiable, Associations, Serialize, Deserialize, Debug, Clone,
+)]
+#[diesel(belongs_to(DbCustomer, foreign_key = customer_id))]
+#[diesel(table_name = customer_sessions)]
+pub struct DbCustomerSession {
+    pub id: i32,
+    pub customer_id: i32,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Database model for api_keys table
+#[derive(
+    Queryable, Selectable, Identifiable, Associations, Serialize, Deserialize, Debug, Clone,
+)]
+#[diesel(belongs_to(DbCustomer, foreign_key = created_by))]
+#[diesel(table_name = api_keys)]
+pub struct DbApiKey {
+    pub id: i32,
+    pub name: String,
+    pub key_prefix: String,
+    #[serde(skip_serializing)]
+    pub key_hash: String,
+    pub scopes: Vec<Option<String>>,
+    pub rate_limit_per_minute: i32,
+    pub created_by: Option<i32>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Database model for persisted_queries table
+#[derive(Queryable, Selectable, Identifiable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = persisted_queries)]
+#[diesel(primary_key(hash))]
+pub struct DbPersistedQuery {
+    pub hash: String,
+    pub query: String,
+    pub name: Option<String>,
+    pub registered: bool,
+    pub created_at: NaiveDateTime,
+}
+
+/// Database model for audit_log table
+#[derive(Queryable, Selectable, Identifiable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = audit_log)]
+pub struct DbAuditLog {
+    pub id: i64,
+    pub entity_type: String,
+    pub entity_id: i32,
+    pub action: String,
+    pub actor: Option<String>,
+    pub diff: JsonValue,
+    pub changed_at: NaiveDateTime,
+}
+
+/// Database model for product_revisions table
+#[derive(
+    Queryable, Selectable, Identifiable, Associations, Serialize, Deserialize, Debug, Clone,
+)]
+#[diesel(belongs_to(DbProduct, foreign_key = product_id))]
+#[diesel(table_name = product_revisions)]
+pub struct DbProductRevision {
+    pub id: i64,
+    pub product_id: i32,
+    pub revision: i32,
+    pub snapshot: JsonValue,
+    pub actor: Option<String>,
+    pub transaction_id: i64,
+    pub created_at: NaiveDateTime,
+}
@@ -0,0 +1,375 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use super::{Money, PaymentError, PaymentProvider, PaymentReceipt};
+
+/// Token that is always declined by [`FakePaymentProvider`]
+pub const DECLINED_TOKEN: &str = "tok_declined";
+/// Token that is declined for lack of funds by [`FakePaymentProvider`]
+pub const INSUFFICIENT_FUNDS_TOKEN: &str = "tok_insufficient_funds";
+/// Token for which [`FakePaymentProvider`] behaves as if it were unreachable
+pub const PROVIDER_ERROR_TOKEN: &str = "tok_provider_error";
+
+#[derive(Debug)]
+struct Authorization {
+    amount: Money,
+    captured: Decimal,
+    voided: bool,
+}
+
+#[derive(Debug)]
+struct Capture {
+    amount: Money,
+    refunded: Decimal,
+}
+
+#[derive(Debug, Default)]
+struct Ledger {
+    /// Completed calls by idempotency key, replayed on retries
+    responses: HashMap<Uuid, Result<PaymentReceipt, PaymentError>>,
+    authorizations: HashMap<String, Authorization>,
+    captures: HashMap<String, Capture>,
+}
+
+/// In-memory payment provider for development and tests.
+///
+/// Every token authorizes except [`DECLINED_TOKEN`], [`INSUFFICIENT_FUNDS_TOKEN`] and
+/// [`PROVIDER_ERROR_TOKEN`]. References are derived from the idempotency key, so the same
+/// sequence of calls always produces the same results.
+#[derive(Debug, Default)]
+pub struct FakePaymentProvider {
+    ledger: Mutex<Ledger>,
+}
+
+impl FakePaymentProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `call` once per idempotency key, replaying the stored result afterwards
+    fn idempotent(
+        &self,
+        idempotency_key: Uuid,
+        call: impl FnOnce(&mut Ledger) -> Result<PaymentReceipt, PaymentError>,
+    ) -> Result<PaymentReceipt, PaymentError> {
+        let mut ledger = self.ledger.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(response) = ledger.responses.get(&idempotency_key) {
+            return response.clone();
+        }
+
+        let response = call(&mut ledger);
+        // Provider errors mean nothing happened, so a retry gets a fresh attempt
+        if !matches!(response, Err(PaymentError::Provider(_))) {
+            ledger.responses.insert(idempotency_key, response.clone());
+        }
+
+        response
+    }
+}
+
+fn reference(prefix: &str, idempotency_key: Uuid) -> String {
+    format!("{}_{}", prefix, idempotency_key.simple())
+}
+
+fn check_amount(amount: &Money) -> Result<(), PaymentError> {
+    if amount.amount <= Decimal::ZERO {
+        return Err(PaymentError::Declined(format!(
+            "invalid amount {} {}",
+            amount.amount, amount.currency
+        )));
+    }
+
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl PaymentProvider for FakePaymentProvider {
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    async fn authorize(
+        &self,
+        token: &str,
+        amount: &Money,
+        idempotency_key: Uuid,
+    ) -> Result<PaymentReceipt, PaymentError> {
+        self.idempotent(idempotency_key, |ledger| {
+            match token {
+                DECLINED_TOKEN => return Err(PaymentError::Declined("card declined".to_string())),
+                INSUFFICIENT_FUNDS_TOKEN => {
+                    return Err(PaymentError::Declined("insufficient funds".to_string()));
+                }
+                PROVIDER_ERROR_TOKEN => {
+                    return Err(PaymentError::Provider("gateway unavailable".to_string()));
+                }
+                _ => {}
+            }
+            check_amount(amount)?;
+
+            let reference = reference("fake_auth", idempotency_key);
+            ledger.authorizations.insert(
+                reference.clone(),
+                Authorization {
+                    amount: amount.clone(),
+                    captured: Decimal::ZERO,
+                    voided: false,
+                },
+            );
+
+            Ok(PaymentReceipt { reference })
+        })
+    }
+
+    async fn capture(
+        &self,
+        authorization: &str,
+        amount: &Money,
+        idempotency_key: Uuid,
+    ) -> Result<PaymentReceipt, PaymentError> {
+        self.idempotent(idempotency_key, |ledger| {
+            check_amount(amount)?;
+            let auth = ledger
+                .authorizations
+                .get_mut(authorization)
+                .ok_or_else(|| PaymentError::Declined("unknown authorization".to_string()))?;
+            if auth.voided {
+                return Err(PaymentError::Declined(
+                    "authorization was voided".to_string(),
+                ));
+            }
+            if auth.amount.currency != amount.currency {
+                return Err(PaymentError::Declined("currency mismatch".to_string()));
+            }
+            if auth.captured + amount.amount > auth.amount.amount {
+                return Err(PaymentError::Declined(
+                    "capture exceeds authorized amount".to_string(),
+                ));
+            }
+            auth.captured += amount.amount;
+
+            let reference = reference("fake_capture", idempotency_key);
+            ledger.captures.insert(
+                reference.clone(),
+                Capture {
+                    amount: amount.clone(),
+                    refunded: Decimal::ZERO,
+                },
+            );
+
+            Ok(PaymentReceipt { reference })
+        })
+    }
+
+    async fn void(
+        &self,
+        authorization: &str,
+        idempotency_key: Uuid,
+    ) -> Result<PaymentReceipt, PaymentError> {
+        self.idempotent(idempotency_key, |ledger| {
+            let auth = ledger
+                .authorizations
+                .get_mut(authorization)
+                .ok_or_else(|| PaymentError::Declined("unknown authorization".to_string()))?;
+            if auth.captured > Decimal::ZERO {
+                return Err(PaymentError::Declined(
+                    "authorization has already been captured".to_string(),
+                ));
+            }
+            auth.voided = true;
+
+            Ok(PaymentReceipt {
+                reference: reference("fake_void", idempotency_key),
+            })
+        })
+    }
+
+    async fn refund(
+        &self,
+        capture: &str,
+        amount: &Money,
+        idempotency_key: Uuid,
+    ) -> Result<PaymentReceipt, PaymentError> {
+        self.idempotent(idempotency_key, |ledger| {
+            check_amount(amount)?;
+            let capture = ledger
+                .captures
+                .get_mut(capture)
+                .ok_or_else(|| PaymentError::Declined("unknown capture".to_string()))?;
+            if capture.amount.currency != amount.currency {
+                return Err(PaymentError::Declined("currency mismatch".to_string()));
+            }
+            if capture.refunded + amount.amount > capture.amount.amount {
+                return Err(PaymentError::Declined(
+                    "refund exceeds captured amount".to_string(),
+                ));
+            }
+            capture.refunded += amount.amount;
+
+            Ok(PaymentReceipt {
+                reference: reference("fake_refund", idempotency_key),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usd(amount: i64) -> Money {
+        Money::new(Decimal::from(amount), "USD")
+    }
+
+    async fn authorize(provider: &FakePaymentProvider, amount: i64) -> String {
+        provider
+            .authorize("tok_visa", &usd(amount), Uuid::new_v4())
+            .await
+            .unwrap()
+            .reference
+    }
+
+    #[tokio::test]
+    async fn special_tokens_are_declined_or_fail() {
+        let provider = FakePaymentProvider::new();
+
+        for token in [DECLINED_TOKEN, INSUFFICIENT_FUNDS_TOKEN] {
+            let result = provider.authorize(token, &usd(10), Uuid::new_v4()).await;
+            assert!(
+                matches!(result, Err(PaymentError::Declined(_))),
+                "{}",
+                token
+            );
+        }
+
+        let result = provider
+            .authorize(PROVIDER_ERROR_TOKEN, &usd(10), Uuid::new_v4())
+            .await;
+        assert!(matches!(result, Err(PaymentError::Provider(_))));
+    }
+
+    #[tokio::test]
+    async fn non_positive_amounts_are_declined() {
+        let provider = FakePaymentProvider::new();
+
+        for amount in [0, -5] {
+            let result = provider
+                .authorize("tok_visa", &usd(amount), Uuid::new_v4())
+                .await;
+            assert!(matches!(result, Err(PaymentError::Declined(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_with_the_same_key_replay_the_result() {
+        let provider = FakePaymentProvider::new();
+        let auth = authorize(&provider, 10).await;
+        let key = Uuid::new_v4();
+
+        let first = provider.capture(&auth, &usd(10), key).await.unwrap();
+        let retry = provider.capture(&auth, &usd(10), key).await.unwrap();
+        assert_eq!(first, retry);
+
+        // A new key is a new capture, which would exceed the authorization
+        let result = provider.capture(&auth, &usd(10), Uuid::new_v4()).await;
+        assert!(matches!(result, Err(PaymentError::Declined(_))));
+    }
+
+    #[tokio::test]
+    async fn provider_errors_are_not_replayed() {
+        let provider = FakePaymentProvider::new();
+        let key = Uuid::new_v4();
+
+        let failed = provider
+            .authorize(PROVIDER_ERROR_TOKEN, &usd(10), key)
+            .await;
+        assert!(matches!(failed, Err(PaymentError::Provider(_))));
+
+        let retry = provider.authorize("tok_visa", &usd(10), key).await;
+        assert!(retry.is_ok());
+    }
+
+    #[tokio::test]
+    async fn captures_are_limited_to_the_authorized_amount() {
+        let provider = FakePaymentProvider::new();
+        let auth = authorize(&provider, 10).await;
+
+        assert!(
+            provider
+                .capture(&auth, &usd(6), Uuid::new_v4())
+                .await
+                .is_ok()
+        );
+        let result = provider.capture(&auth, &usd(5), Uuid::new_v4()).await;
+        assert!(matches!(result, Err(PaymentError::Declined(_))));
+        assert!(
+            provider
+                .capture(&auth, &usd(4), Uuid::new_v4())
+                .await
+                .is_ok()
+        );
+
+        let other_currency = Money::new(Decimal::ONE, "EUR");
+        let auth = authorize(&provider, 10).await;
+        let result = provider
+            .capture(&auth, &other_currency, Uuid::new_v4())
+            .await;
+        assert!(matches!(result, Err(PaymentError::Declined(_))));
+    }
+
+    #[tokio::test]
+    async fn voided_authorizations_cannot_be_captured() {
+        let provider = FakePaymentProvider::new();
+        let auth = authorize(&provider, 10).await;
+
+        assert!(provider.void(&auth, Uuid::new_v4()).await.is_ok());
+        let result = provider.capture(&auth, &usd(10), Uuid::new_v4()).await;
+        assert!(matches!(result, Err(PaymentError::Declined(_))));
+    }
+
+    #[tokio::test]
+    async fn captured_authorizations_cannot_be_voided() {
+        let provider = FakePaymentProvider::new();
+        let auth = authorize(&provider, 10).await;
+
+        provider
+            .capture(&auth, &usd(10), Uuid::new_v4())
+            .await
+            .unwrap();
+        let result = provider.void(&auth, Uuid::new_v4()).await;
+        assert!(matches!(result, Err(PaymentError::Declined(_))));
+    }
+
+    #[tokio::test]
+    async fn refunds_are_limited_to_the_captured_amount() {
+        let provider = FakePaymentProvider::new();
+        let auth = authorize(&provider, 10).await;
+        let capture = provider
+            .capture(&auth, &usd(10), Uuid::new_v4())
+            .await
+            .unwrap()
+            .reference;
+
+        assert!(
+            provider
+                .refund(&capture, &usd(7), Uuid::new_v4())
+                .await
+                .is_ok()
+        );
+        let result = provider.refund(&capture, &usd(4), Uuid::new_v4()).await;
+        assert!(matches!(result, Err(PaymentError::Declined(_))));
+        assert!(
+            provider
+                .refund(&capture, &usd(3), Uuid::new_v4())
+                .await
+                .is_ok()
+        );
+
+        let result = provider
+            .refund("fake_capture_unknown", &usd(1), Uuid::new_v4())
+            .await;
+        assert!(matches!(result, Err(PaymentError::Declined(_))));
+    }
+}
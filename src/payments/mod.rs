@@ -0,0 +1,86 @@
+//! Payment provider abstraction used by checkout, cancellation and refunds
+
+mod fake;
+
+pub use fake::{
+    DECLINED_TOKEN, FakePaymentProvider, INSUFFICIENT_FUNDS_TOKEN, PROVIDER_ERROR_TOKEN,
+};
+
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+/// A sum of money in a single currency
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Money {
+    pub amount: Decimal,
+    /// ISO 4217 currency code
+    pub currency: String,
+}
+
+impl Money {
+    pub fn new(amount: Decimal, currency: impl Into<String>) -> Self {
+        Self {
+            amount,
+            currency: currency.into(),
+        }
+    }
+}
+
+/// Successful provider call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentReceipt {
+    /// Provider-side id of the authorization, capture, void or refund
+    pub reference: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PaymentError {
+    /// The provider refused the operation; retrying will not help
+    #[error("payment declined: {0}")]
+    Declined(String),
+    /// The provider could not be reached or failed; the operation may be retried
+    /// with the same idempotency key
+    #[error("payment provider error: {0}")]
+    Provider(String),
+}
+
+/// A payment gateway.
+///
+/// Every call carries an idempotency key; repeating a call with the same key must
+/// return the original result without moving money again.
+#[async_trait::async_trait]
+pub trait PaymentProvider: Send + Sync {
+    /// Short name stored with each payment attempt
+    fn name(&self) -> &'static str;
+
+    /// Reserve funds using a client-side payment token
+    async fn authorize(
+        &self,
+        token: &str,
+        amount: &Money,
+        idempotency_key: Uuid,
+    ) -> Result<PaymentReceipt, PaymentError>;
+
+    /// Collect some or all of an authorization
+    async fn capture(
+        &self,
+        authorization: &str,
+        amount: &Money,
+        idempotency_key: Uuid,
+    ) -> Result<PaymentReceipt, PaymentError>;
+
+    /// Release an authorization that will not be captured
+    async fn void(
+        &self,
+        authorization: &str,
+        idempotency_key: Uuid,
+    ) -> Result<PaymentReceipt, PaymentError>;
+
+    /// Return some or all of a capture to the customer
+    async fn refund(
+        &self,
+        capture: &str,
+        amount: &Money,
+        idempotency_key: Uuid,
+    ) -> Result<PaymentReceipt, PaymentError>;
+}
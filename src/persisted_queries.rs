@@ -0,0 +1,507 @@
+//! Apollo-style automatic persisted queries and a strict allow-list of registered operations
+
+use async_graphql::{
+    ErrorExtensionValues, Request, ServerError, ServerResult,
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest},
+};
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::{
+    num::NonZeroUsize,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use crate::auth::{Principal, Role};
+
+#[derive(Debug, thiserror::Error)]
+pub enum PersistedQueryError {
+    #[error("unknown value '{0}'")]
+    UnknownValue(String),
+    #[error("invalid query: {0}")]
+    InvalidQuery(String),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// How requests may supply their query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PersistedQueryMode {
+    /// Any query; persisted query hashes are not understood
+    Off,
+    /// Any query, and clients may send a hash in place of a query they sent before
+    Automatic,
+    /// Only operations registered ahead of time, by hash or in full; admins are exempt
+    Strict,
+}
+
+impl FromStr for PersistedQueryMode {
+    type Err = PersistedQueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "automatic" => Ok(Self::Automatic),
+            "strict" => Ok(Self::Strict),
+            other => Err(PersistedQueryError::UnknownValue(other.to_string())),
+        }
+    }
+}
+
+/// Where persisted queries are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PersistedQueryBackend {
+    /// A per-process LRU cache; cannot hold registered operations
+    Memory,
+    /// The `persisted_queries` table, shared by every instance
+    Postgres,
+}
+
+impl FromStr for PersistedQueryBackend {
+    type Err = PersistedQueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "memory" => Ok(Self::Memory),
+            "postgres" => Ok(Self::Postgres),
+            other => Err(PersistedQueryError::UnknownValue(other.to_string())),
+        }
+    }
+}
+
+/// Hex SHA-256 of a query's text, as sent by Apollo clients
+pub fn query_hash(query: &str) -> String {
+    format!("{:x}", Sha256::digest(query.as_bytes()))
+}
+
+/// Query text by hash
+#[async_trait::async_trait]
+pub trait PersistedQueryStore: Send + Sync {
+    /// The query stored under `hash`; with `registered_only`, only operations
+    /// registered ahead of time are returned
+    async fn get(
+        &self,
+        hash: &str,
+        registered_only: bool,
+    ) -> Result<Option<String>, PersistedQueryError>;
+
+    /// Remember a query sent by a client
+    async fn put(&self, hash: &str, query: &str) -> Result<(), PersistedQueryError>;
+}
+
+/// Keeps the most recently used queries in process memory
+pub struct MemoryPersistedQueryStore {
+    queries: Mutex<LruCache<String, String>>,
+}
+
+impl MemoryPersistedQueryStore {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            queries: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl PersistedQueryStore for MemoryPersistedQueryStore {
+    async fn get(
+        &self,
+        hash: &str,
+        registered_only: bool,
+    ) -> Result<Option<String>, PersistedQueryError> {
+        if registered_only {
+            return Ok(None);
+        }
+
+        let mut queries = self.queries.lock().unwrap_or_else(|err| err.into_inner());
+        Ok(queries.get(hash).cloned())
+    }
+
+    async fn put(&self, hash: &str, query: &str) -> Result<(), PersistedQueryError> {
+        let mut queries = self.queries.lock().unwrap_or_else(|err| err.into_inner());
+        queries.put(hash.to_string(), query.to_string());
+        Ok(())
+    }
+}
+
+/// Keeps queries in the `persisted_queries` table
+pub struct PgPersistedQueryStore {
+    pool: PgPool,
+}
+
+impl PgPersistedQueryStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl PersistedQueryStore for PgPersistedQueryStore {
+    async fn get(
+        &self,
+        hash: &str,
+        registered_only: bool,
+    ) -> Result<Option<String>, PersistedQueryError> {
+        let query = sqlx::query_scalar(
+            "SELECT query FROM persisted_queries WHERE hash = $1 AND (registered OR NOT $2)",
+        )
+        .bind(hash)
+        .bind(registered_only)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(query)
+    }
+
+    async fn put(&self, hash: &str, query: &str) -> Result<(), PersistedQueryError> {
+        sqlx::query(
+            "INSERT INTO persisted_queries (hash, query) VALUES ($1, $2)
+             ON CONFLICT (hash) DO NOTHING",
+        )
+        .bind(hash)
+        .bind(query)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Schema extension resolving `extensions.persistedQuery` hashes and, in strict
+/// mode, rejecting operations that were not registered
+pub struct PersistedQueries {
+    mode: PersistedQueryMode,
+    store: Arc<dyn PersistedQueryStore>,
+}
+
+impl PersistedQueries {
+    pub fn new(mode: PersistedQueryMode, store: Arc<dyn PersistedQueryStore>) -> Self {
+        Self { mode, store }
+    }
+}
+
+impl ExtensionFactory for PersistedQueries {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(PersistedQueriesExtension {
+            mode: self.mode,
+            store: self.store.clone(),
+        })
+    }
+}
+
+struct PersistedQueriesExtension {
+    mode: PersistedQueryMode,
+    store: Arc<dyn PersistedQueryStore>,
+}
+
+/// An error with the `code` extension Apollo clients look for
+fn coded_error(message: &str, code: &str) -> ServerError {
+    let mut extensions = ErrorExtensionValues::default();
+    extensions.set("code", code);
+
+    let mut err = ServerError::new(message, None);
+    err.extensions = Some(extensions);
+    err
+}
+
+fn store_error(err: PersistedQueryError) -> ServerError {
+    tracing::error!("Persisted query store failed: {}", err);
+    ServerError::new("failed to load persisted query", None)
+}
+
+/// The `sha256Hash` of a version 1 `persistedQuery` request extension
+fn requested_hash(request: &mut Request) -> ServerResult<Option<String>> {
+    let Some(value) = request.extensions.remove("persistedQuery") else {
+        return Ok(None);
+    };
+    let value = value
+        .into_json()
+        .map_err(|_| ServerError::new("invalid persistedQuery extension", None))?;
+
+    if value.get("version").and_then(|version| version.as_i64()) != Some(1) {
+        return Err(ServerError::new(
+            "only version 1 of the persistedQuery extension is supported",
+            None,
+        ));
+    }
+
+    value
+        .get("sha256Hash")
+        .and_then(|hash| hash.as_str())
+        .map(|hash| Some(hash.to_ascii_lowercase()))
+        .ok_or_else(|| ServerError::new("persistedQuery extension needs a sha256Hash", None))
+}
+
+#[async_trait::async_trait]
+impl Extension for PersistedQueriesExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        mut request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        if self.mode == PersistedQueryMode::Off {
+            return next.run(ctx, request).await;
+        }
+
+        let hash = requested_hash(&mut request)?;
+        let strict = self.mode == PersistedQueryMode::Strict
+            && !Principal::from_request(&request).has_role(Role::Admin);
+
+        if request.query.is_empty() {
+            if let Some(hash) = hash {
+                request.query = self
+                    .store
+                    .get(&hash, strict)
+                    .await
+                    .map_err(store_error)?
+                    .ok_or_else(|| {
+                        if strict {
+                            coded_error(
+                                "only registered operations are accepted",
+                                "PERSISTED_QUERY_NOT_ALLOWED",
+                            )
+                        } else {
+                            coded_error("PersistedQueryNotFound", "PERSISTED_QUERY_NOT_FOUND")
+                        }
+                    })?;
+            }
+            return next.run(ctx, request).await;
+        }
+
+        let actual = query_hash(&request.query);
+        if hash.as_ref().is_some_and(|hash| *hash != actual) {
+            return Err(ServerError::new(
+                "provided sha256Hash does not match query",
+                None,
+            ));
+        }
+
+        if strict {
+            let registered = self.store.get(&actual, true).await.map_err(store_error)?;
+            if registered.is_none() {
+                return Err(coded_error(
+                    "only registered operations are accepted",
+                    "PERSISTED_QUERY_NOT_ALLOWED",
+                ));
+            }
+        } else if hash.is_some() {
+            self.store
+                .put(&actual, &request.query)
+                .await
+                .map_err(store_error)?;
+        }
+
+        next.run(ctx, request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_graphql::{EmptyMutation, EmptySubscription, Object, Response, Schema, Value};
+    use serde_json::json;
+
+    const QUERY: &str = "{ ping }";
+
+    struct Query;
+
+    #[Object]
+    impl Query {
+        async fn ping(&self) -> &str {
+            "pong"
+        }
+    }
+
+    fn schema(
+        mode: PersistedQueryMode,
+        store: Arc<MemoryPersistedQueryStore>,
+    ) -> Schema<Query, EmptyMutation, EmptySubscription> {
+        Schema::build(Query, EmptyMutation, EmptySubscription)
+            .extension(PersistedQueries::new(mode, store))
+            .finish()
+    }
+
+    fn request(query: &str, persisted_query: Option<serde_json::Value>) -> Request {
+        let mut request = Request::new(query);
+        if let Some(persisted_query) = persisted_query {
+            request.extensions.insert(
+                "persistedQuery".to_string(),
+                Value::from_json(persisted_query).unwrap(),
+            );
+        }
+        request
+    }
+
+    fn by_hash(hash: &str) -> Option<serde_json::Value> {
+        Some(json!({ "version": 1, "sha256Hash": hash }))
+    }
+
+    fn error_code(response: &Response) -> Option<String> {
+        let extensions = response.errors.first()?.extensions.as_ref()?;
+        match extensions.get("code")? {
+            Value::String(code) => Some(code.clone()),
+            _ => None,
+        }
+    }
+
+    fn admin() -> Principal {
+        Principal::Customer {
+            customer_id: 1,
+            session_id: 1,
+            role: Role::Admin,
+        }
+    }
+
+    #[test]
+    fn requests_without_the_extension_have_no_hash() {
+        assert_eq!(requested_hash(&mut request(QUERY, None)).unwrap(), None);
+    }
+
+    #[test]
+    fn requested_hash_is_lowercased() {
+        let mut request = request("", by_hash("ABC123"));
+        assert_eq!(
+            requested_hash(&mut request).unwrap().as_deref(),
+            Some("abc123")
+        );
+        assert!(!request.extensions.contains_key("persistedQuery"));
+    }
+
+    #[test]
+    fn only_version_1_is_supported() {
+        for persisted_query in [
+            json!({ "version": 2, "sha256Hash": "abc" }),
+            json!({ "version": "1", "sha256Hash": "abc" }),
+            json!({ "sha256Hash": "abc" }),
+        ] {
+            assert!(requested_hash(&mut request("", Some(persisted_query))).is_err());
+        }
+    }
+
+    #[test]
+    fn a_hash_is_required() {
+        assert!(requested_hash(&mut request("", Some(json!({ "version": 1 })))).is_err());
+    }
+
+    #[tokio::test]
+    async fn unknown_hashes_ask_for_the_query() {
+        let store = Arc::new(MemoryPersistedQueryStore::new(10));
+        let schema = schema(PersistedQueryMode::Automatic, store);
+
+        let response = schema
+            .execute(request("", by_hash(&query_hash(QUERY))))
+            .await;
+        assert_eq!(
+            error_code(&response).as_deref(),
+            Some("PERSISTED_QUERY_NOT_FOUND")
+        );
+    }
+
+    #[tokio::test]
+    async fn queries_sent_with_their_hash_can_be_replayed() {
+        let store = Arc::new(MemoryPersistedQueryStore::new(10));
+        let schema = schema(PersistedQueryMode::Automatic, store.clone());
+        let hash = query_hash(QUERY);
+
+        let response = schema.execute(request(QUERY, by_hash(&hash))).await;
+        assert!(response.errors.is_empty());
+        assert_eq!(
+            store.get(&hash, false).await.unwrap().as_deref(),
+            Some(QUERY)
+        );
+
+        let response = schema
+            .execute(request("", by_hash(&hash.to_ascii_uppercase())))
+            .await;
+        assert!(response.errors.is_empty());
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            json!({ "ping": "pong" })
+        );
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_queries_are_evicted() {
+        let store = Arc::new(MemoryPersistedQueryStore::new(1));
+        let schema = schema(PersistedQueryMode::Automatic, store);
+        let other = "{ other: ping }";
+
+        for query in [QUERY, other] {
+            let response = schema
+                .execute(request(query, by_hash(&query_hash(query))))
+                .await;
+            assert!(response.errors.is_empty());
+        }
+
+        let response = schema
+            .execute(request("", by_hash(&query_hash(other))))
+            .await;
+        assert!(response.errors.is_empty());
+        let response = schema
+            .execute(request("", by_hash(&query_hash(QUERY))))
+            .await;
+        assert_eq!(
+            error_code(&response).as_deref(),
+            Some("PERSISTED_QUERY_NOT_FOUND")
+        );
+    }
+
+    #[tokio::test]
+    async fn mismatched_hashes_are_rejected_and_not_stored() {
+        let store = Arc::new(MemoryPersistedQueryStore::new(10));
+        let schema = schema(PersistedQueryMode::Automatic, store.clone());
+        let wrong = query_hash("{ other: ping }");
+
+        let response = schema.execute(request(QUERY, by_hash(&wrong))).await;
+        assert_eq!(
+            response.errors[0].message,
+            "provided sha256Hash does not match query"
+        );
+        assert_eq!(store.get(&wrong, false).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn strict_mode_rejects_unregistered_operations() {
+        let store = Arc::new(MemoryPersistedQueryStore::new(10));
+        let hash = query_hash(QUERY);
+        store.put(&hash, QUERY).await.unwrap();
+        let schema = schema(PersistedQueryMode::Strict, store);
+
+        for request in [request(QUERY, None), request("", by_hash(&hash))] {
+            let response = schema.execute(request).await;
+            assert_eq!(
+                error_code(&response).as_deref(),
+                Some("PERSISTED_QUERY_NOT_ALLOWED")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn strict_mode_exempts_admins() {
+        let store = Arc::new(MemoryPersistedQueryStore::new(10));
+        let hash = query_hash(QUERY);
+        store.put(&hash, QUERY).await.unwrap();
+        let schema = schema(PersistedQueryMode::Strict, store);
+
+        let response = schema.execute(request(QUERY, None).data(admin())).await;
+        assert!(response.errors.is_empty());
+        let response = schema
+            .execute(request("", by_hash(&hash)).data(admin()))
+            .await;
+        assert!(response.errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn off_mode_ignores_the_extension() {
+        let store = Arc::new(MemoryPersistedQueryStore::new(10));
+        let schema = schema(PersistedQueryMode::Off, store.clone());
+        let hash = query_hash(QUERY);
+
+        let response = schema.execute(request(QUERY, by_hash(&hash))).await;
+        assert!(response.errors.is_empty());
+        assert_eq!(store.get(&hash, false).await.unwrap(), None);
+    }
+}
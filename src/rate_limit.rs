@@ -0,0 +1,337 @@
+//! In-memory token bucket rate limiting, including complexity-weighted GraphQL budgets
+
+use async_graphql::{
+    ErrorExtensionValues, Request, Response, ServerError, ServerResult, ValidationResult,
+    Variables,
+    extensions::{
+        Extension, ExtensionContext, ExtensionFactory, NextParseQuery, NextPrepareRequest,
+        NextValidation,
+    },
+    parser::types::{ExecutableDocument, OperationType},
+};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::auth::Principal;
+
+/// Buckets idle for this long are full again and can be forgotten
+const IDLE_EVICTION: Duration = Duration::from_secs(10 * 60);
+/// Number of buckets above which idle ones are swept
+const SWEEP_THRESHOLD: usize = 10_000;
+/// Wait advertised by a quota that never grants anything
+const DENIED_RETRY: Duration = Duration::from_secs(60);
+
+/// Burst size and sustained rate of a bucket
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quota {
+    pub capacity: f64,
+    pub refill_per_second: f64,
+}
+
+impl Quota {
+    /// `limit` units per minute, all of which may be spent in a burst. A limit of 0
+    /// denies every request.
+    pub fn per_minute(limit: u32) -> Self {
+        Self {
+            capacity: limit as f64,
+            refill_per_second: limit as f64 / 60.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Token buckets keyed by caller, shared across requests
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take `cost` tokens from `key`'s bucket, or return how long to wait until
+    /// enough tokens are available
+    pub fn check(&self, key: &str, quota: Quota, cost: f64) -> Result<(), Duration> {
+        // An empty bucket that never refills would otherwise let zero-cost requests through
+        if quota.capacity <= 0.0 || quota.refill_per_second <= 0.0 {
+            return Err(DENIED_RETRY);
+        }
+
+        // A request costing more than the whole bucket needs a full bucket
+        let cost = cost.min(quota.capacity);
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|err| err.into_inner());
+
+        if buckets.len() > SWEEP_THRESHOLD {
+            buckets.retain(|_, bucket| now.duration_since(bucket.refilled_at) < IDLE_EVICTION);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: quota.capacity,
+            refilled_at: now,
+        });
+
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * quota.refill_per_second).min(quota.capacity);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= cost {
+            bucket.tokens -= cost;
+            return Ok(());
+        }
+
+        let missing = cost - bucket.tokens;
+        Err(Duration::from_secs_f64(missing / quota.refill_per_second))
+    }
+}
+
+/// Whole seconds to advertise in a `Retry-After` header
+pub fn retry_after_secs(wait: Duration) -> u64 {
+    wait.as_secs_f64().ceil().max(1.0) as u64
+}
+
+/// Who a request is charged to: the API key, else the customer, else the client address
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClientKey(String);
+
+impl ClientKey {
+    pub fn new(principal: &Principal, ip: Option<IpAddr>) -> Self {
+        Self(match (principal, ip) {
+            (Principal::ApiKey { key_id, .. }, _) => format!("key:{}", key_id),
+            (Principal::Customer { customer_id, .. }, _) => format!("user:{}", customer_id),
+            (Principal::Anonymous, Some(ip)) => format!("ip:{}", ip),
+            (Principal::Anonymous, None) => "anonymous".to_string(),
+        })
+    }
+}
+
+/// Per-client complexity budgets, spent separately by queries and mutations; `None`
+/// leaves that kind of operation unlimited
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Budgets {
+    pub queries: Option<Quota>,
+    pub mutations: Option<Quota>,
+}
+
+/// Error code attached to GraphQL errors for requests over budget
+pub const RATE_LIMITED: &str = "RATE_LIMITED";
+
+/// Seconds a rate limited GraphQL response asks the client to wait, if it was limited
+pub fn retry_after(response: &Response) -> Option<u64> {
+    response.errors.iter().find_map(|err| {
+        let extensions = err.extensions.as_ref()?;
+        match extensions.get("code")? {
+            async_graphql::Value::String(code) if code == RATE_LIMITED => extensions
+                .get("retryAfter")?
+                .clone()
+                .into_json()
+                .ok()?
+                .as_u64(),
+            _ => None,
+        }
+    })
+}
+
+/// Schema extension charging each query or mutation its complexity score against the
+/// caller's budget. The [`ClientKey`] must be attached as request data; requests
+/// without one, and subscriptions, are not limited.
+pub struct ComplexityRateLimit {
+    limiter: RateLimiter,
+    budgets: Budgets,
+}
+
+impl ComplexityRateLimit {
+    pub fn new(budgets: Budgets) -> Self {
+        Self {
+            limiter: RateLimiter::new(),
+            budgets,
+        }
+    }
+}
+
+impl ExtensionFactory for ComplexityRateLimit {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(ComplexityRateLimitExtension {
+            limiter: self.limiter.clone(),
+            budgets: self.budgets,
+            operation_name: Mutex::new(None),
+            operation_type: Mutex::new(None),
+        })
+    }
+}
+
+struct ComplexityRateLimitExtension {
+    limiter: RateLimiter,
+    budgets: Budgets,
+    operation_name: Mutex<Option<String>>,
+    operation_type: Mutex<Option<OperationType>>,
+}
+
+/// Type of the operation the request will run
+fn operation_type(document: &ExecutableDocument, name: Option<&str>) -> Option<OperationType> {
+    document
+        .operations
+        .iter()
+        .find(|(op_name, _)| name.is_none() || op_name.map(|n| n.as_str()) == name)
+        .map(|(_, operation)| operation.node.ty)
+}
+
+#[async_trait::async_trait]
+impl Extension for ComplexityRateLimitExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        *self
+            .operation_name
+            .lock()
+            .unwrap_or_else(|err| err.into_inner()) = request.operation_name.clone();
+        next.run(ctx, request).await
+    }
+
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        let name = self
+            .operation_name
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone();
+        *self
+            .operation_type
+            .lock()
+            .unwrap_or_else(|err| err.into_inner()) = operation_type(&document, name.as_deref());
+        Ok(document)
+    }
+
+    async fn validation(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        next: NextValidation<'_>,
+    ) -> Result<ValidationResult, Vec<ServerError>> {
+        let result = next.run(ctx).await?;
+
+        let operation_type = *self
+            .operation_type
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let (bucket, quota) = match operation_type {
+            Some(OperationType::Query) => ("query", self.budgets.queries),
+            Some(OperationType::Mutation) => ("mutation", self.budgets.mutations),
+            Some(OperationType::Subscription) | None => return Ok(result),
+        };
+        let Some(quota) = quota else {
+            return Ok(result);
+        };
+        let Some(ClientKey(client)) = ctx.data_opt::<ClientKey>() else {
+            return Ok(result);
+        };
+
+        let cost = result.complexity.max(1) as f64;
+        if let Err(wait) = self
+            .limiter
+            .check(&format!("{}:{}", bucket, client), quota, cost)
+        {
+            let mut extensions = ErrorExtensionValues::default();
+            extensions.set("code", RATE_LIMITED);
+            extensions.set("retryAfter", retry_after_secs(wait));
+            extensions.set("cost", result.complexity as u64);
+
+            let mut err = ServerError::new(format!("{} rate limit exceeded", bucket), None);
+            err.extensions = Some(extensions);
+            return Err(vec![err]);
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_empties_then_asks_to_wait() {
+        let limiter = RateLimiter::new();
+        let quota = Quota::per_minute(3);
+
+        for _ in 0..3 {
+            assert!(limiter.check("client", quota, 1.0).is_ok());
+        }
+
+        // One token comes back every 20 seconds
+        let wait = limiter.check("client", quota, 1.0).unwrap_err();
+        assert!(wait > Duration::from_secs(19) && wait <= Duration::from_secs(20));
+        assert_eq!(retry_after_secs(wait), 20);
+    }
+
+    #[test]
+    fn bucket_refills_over_time_up_to_capacity() {
+        let limiter = RateLimiter::new();
+        let quota = Quota {
+            capacity: 2.0,
+            refill_per_second: 100.0,
+        };
+
+        assert!(limiter.check("client", quota, 2.0).is_ok());
+        assert!(limiter.check("client", quota, 1.0).is_err());
+
+        std::thread::sleep(Duration::from_millis(50));
+
+        // Enough time passed for five tokens, but the bucket only holds two
+        assert!(limiter.check("client", quota, 2.0).is_ok());
+        assert!(limiter.check("client", quota, 1.0).is_err());
+    }
+
+    #[test]
+    fn clients_have_separate_buckets() {
+        let limiter = RateLimiter::new();
+        let quota = Quota::per_minute(1);
+
+        assert!(limiter.check("a", quota, 1.0).is_ok());
+        assert!(limiter.check("a", quota, 1.0).is_err());
+        assert!(limiter.check("b", quota, 1.0).is_ok());
+    }
+
+    #[test]
+    fn cost_above_capacity_needs_a_full_bucket() {
+        let limiter = RateLimiter::new();
+        let quota = Quota::per_minute(10);
+
+        assert!(limiter.check("client", quota, 50.0).is_ok());
+        assert!(limiter.check("client", quota, 1.0).is_err());
+    }
+
+    #[test]
+    fn zero_quota_denies_everything() {
+        let limiter = RateLimiter::new();
+        let quota = Quota::per_minute(0);
+
+        assert_eq!(limiter.check("client", quota, 1.0), Err(DENIED_RETRY));
+        assert_eq!(limiter.check("client", quota, 0.0), Err(DENIED_RETRY));
+    }
+
+    #[test]
+    fn retry_after_is_at_least_a_second() {
+        assert_eq!(retry_after_secs(Duration::from_millis(10)), 1);
+        assert_eq!(retry_after_secs(Duration::from_millis(1500)), 2);
+    }
+}
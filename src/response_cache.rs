@@ -0,0 +1,390 @@
+//! Whole-response caching for catalogue queries, invalidated by tag
+
+use async_graphql::{
+    CacheControl, Context, Request, Response, ServerError, ServerResult, ValidationResult, Value,
+    Variables,
+    extensions::{
+        Extension, ExtensionContext, ExtensionFactory, NextExecute, NextParseQuery,
+        NextPrepareRequest, NextValidation,
+    },
+    futures_util::StreamExt,
+    parser::types::{ExecutableDocument, OperationType, Selection},
+};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+
+use crate::auth::{Principal, Role};
+use crate::handlers::events::{CatalogEvent, CatalogEvents};
+use crate::shutdown::Shutdown;
+
+/// Root fields whose responses may be cached; an operation selecting anything else
+/// always executes
+const CACHEABLE_FIELDS: [&str; 3] = ["products", "product", "__typename"];
+
+/// Tag carried by every cached product listing
+pub const PRODUCTS_TAG: &str = "products";
+
+pub fn product_tag(product_id: i32) -> String {
+    format!("product:{}", product_id)
+}
+
+pub fn category_tag(slug: &str) -> String {
+    format!("category:{}", slug)
+}
+
+/// A successful response as stored in a cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub data: Value,
+}
+
+/// Storage for cached responses. Implementations may live in process or in a shared
+/// store such as Redis.
+#[async_trait::async_trait]
+pub trait ResponseCache: Send + Sync {
+    async fn get(&self, key: &str) -> Option<CachedResponse>;
+
+    /// Store a response for `ttl`, to be dropped early when any of `tags` is invalidated
+    async fn put(&self, key: &str, response: CachedResponse, tags: &[String], ttl: Duration);
+
+    /// Drop every response stored with any of `tags`
+    async fn invalidate(&self, tags: &[String]);
+}
+
+struct Entry {
+    response: CachedResponse,
+    tags: Vec<String>,
+    expires_at: Instant,
+}
+
+struct MemoryCacheState {
+    entries: LruCache<String, Entry>,
+    keys_by_tag: HashMap<String, HashSet<String>>,
+}
+
+impl MemoryCacheState {
+    fn forget(&mut self, key: &str, tags: &[String]) {
+        for tag in tags {
+            if let Some(keys) = self.keys_by_tag.get_mut(tag) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.keys_by_tag.remove(tag);
+                }
+            }
+        }
+    }
+}
+
+/// Least recently used responses kept in process memory
+pub struct InMemoryResponseCache {
+    state: Mutex<MemoryCacheState>,
+}
+
+impl InMemoryResponseCache {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            state: Mutex::new(MemoryCacheState {
+                entries: LruCache::new(capacity),
+                keys_by_tag: HashMap::new(),
+            }),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ResponseCache for InMemoryResponseCache {
+    async fn get(&self, key: &str) -> Option<CachedResponse> {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+
+        let entry = state.entries.get(key)?;
+        if entry.expires_at > Instant::now() {
+            return Some(entry.response.clone());
+        }
+
+        if let Some(expired) = state.entries.pop(key) {
+            state.forget(key, &expired.tags);
+        }
+        None
+    }
+
+    async fn put(&self, key: &str, response: CachedResponse, tags: &[String], ttl: Duration) {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+
+        let entry = Entry {
+            response,
+            tags: tags.to_vec(),
+            expires_at: Instant::now() + ttl,
+        };
+        if let Some((evicted_key, evicted)) = state.entries.push(key.to_string(), entry) {
+            state.forget(&evicted_key, &evicted.tags);
+        }
+        for tag in tags {
+            state
+                .keys_by_tag
+                .entry(tag.clone())
+                .or_default()
+                .insert(key.to_string());
+        }
+    }
+
+    async fn invalidate(&self, tags: &[String]) {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+
+        for tag in tags {
+            let Some(keys) = state.keys_by_tag.remove(tag) else {
+                continue;
+            };
+            for key in keys {
+                if let Some(entry) = state.entries.pop(&key) {
+                    state.forget(&key, &entry.tags);
+                }
+            }
+        }
+    }
+}
+
+/// Invalidation tags collected by resolvers while a request executes
+#[derive(Debug, Clone, Default)]
+pub struct CacheTags(Arc<Mutex<BTreeSet<String>>>);
+
+impl CacheTags {
+    /// Tag the current response; a no-op when response caching is disabled
+    pub fn add(ctx: &Context<'_>, tag: impl Into<String>) {
+        if let Some(tags) = ctx.data_opt::<CacheTags>() {
+            tags.0
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .insert(tag.into());
+        }
+    }
+
+    fn to_vec(&self) -> Vec<String> {
+        let tags = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        tags.iter().cloned().collect()
+    }
+}
+
+/// Tags to invalidate for a catalogue change
+fn invalidated_tags(event: &CatalogEvent) -> Vec<String> {
+    match event {
+        CatalogEvent::ProductUpdated(product) => {
+            vec![PRODUCTS_TAG.to_string(), product_tag(product.id)]
+        }
+        CatalogEvent::StockChanged(change) => {
+            vec![PRODUCTS_TAG.to_string(), product_tag(change.product_id)]
+        }
+        CatalogEvent::PriceChanged(change) => {
+            vec![PRODUCTS_TAG.to_string(), product_tag(change.product_id)]
+        }
+        CatalogEvent::CategoryUpdated(category) => std::iter::once(&category.slug)
+            .chain(&category.previous_slug)
+            .map(|slug| category_tag(slug))
+            .collect(),
+        CatalogEvent::LowStock(_) => Vec::new(),
+    }
+}
+
+/// Spawn a task invalidating cached responses as catalogue changes are committed.
+/// Mutations write through Postgres triggers that NOTIFY every instance, so each
+/// instance's cache is invalidated whichever one ran the mutation.
+pub fn spawn_invalidator(
+    cache: Arc<dyn ResponseCache>,
+    events: CatalogEvents,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut events = Box::pin(events.stream());
+        loop {
+            tokio::select! {
+                event = events.next() => match event {
+                    Some(event) => {
+                        let tags = invalidated_tags(&event);
+                        if !tags.is_empty() {
+                            cache.invalidate(&tags).await;
+                        }
+                    }
+                    None => break,
+                },
+                _ = shutdown.wait() => break,
+            }
+        }
+    })
+}
+
+/// Schema extension answering repeated catalogue queries from a [`ResponseCache`].
+/// Responses are keyed on the whitespace-normalised query, operation name, variables
+/// and whether the caller sees staff-only data, and live for the `max-age` of their
+/// cache control hint.
+pub struct ResponseCaching {
+    cache: Arc<dyn ResponseCache>,
+}
+
+impl ResponseCaching {
+    pub fn new(cache: Arc<dyn ResponseCache>) -> Self {
+        Self { cache }
+    }
+}
+
+impl ExtensionFactory for ResponseCaching {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(ResponseCachingExtension {
+            cache: self.cache.clone(),
+            tags: CacheTags::default(),
+            state: Mutex::new(RequestState::default()),
+        })
+    }
+}
+
+#[derive(Default)]
+struct RequestState {
+    /// Identity of the request, set once it is known to be cacheable
+    fingerprint: Option<serde_json::Value>,
+    key: Option<String>,
+    cache_control: CacheControl,
+}
+
+struct ResponseCachingExtension {
+    cache: Arc<dyn ResponseCache>,
+    tags: CacheTags,
+    state: Mutex<RequestState>,
+}
+
+impl ResponseCachingExtension {
+    fn state(&self) -> std::sync::MutexGuard<'_, RequestState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Whether the selected operation is a query touching only cacheable root fields
+fn is_cacheable(document: &ExecutableDocument, name: Option<&str>) -> bool {
+    let Some((_, operation)) = document
+        .operations
+        .iter()
+        .find(|(op_name, _)| name.is_none() || op_name.map(|n| n.as_str()) == name)
+    else {
+        return false;
+    };
+
+    operation.node.ty == OperationType::Query
+        && operation
+            .node
+            .selection_set
+            .node
+            .items
+            .iter()
+            .all(|selection| match &selection.node {
+                Selection::Field(field) => {
+                    CACHEABLE_FIELDS.contains(&field.node.name.node.as_str())
+                }
+                _ => false,
+            })
+}
+
+fn cache_key(fingerprint: &serde_json::Value, variables: &Variables) -> String {
+    let material = serde_json::json!({ "request": fingerprint, "variables": variables });
+    format!("{:x}", Sha256::digest(material.to_string().as_bytes()))
+}
+
+#[async_trait::async_trait]
+impl Extension for ResponseCachingExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        // Run the rest of the chain first so persisted query hashes are resolved
+        let request = next.run(ctx, request).await?;
+
+        // Admins may see deleted catalogue entries, which other staff may not
+        let principal = Principal::from_request(&request);
+        let scope = if principal.has_role(Role::Admin) {
+            "admin"
+        } else if principal.is_staff() {
+            "staff"
+        } else {
+            "public"
+        };
+        let query = request
+            .query
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.state().fingerprint = Some(serde_json::json!({
+            "query": query,
+            "operationName": request.operation_name,
+            "scope": scope,
+        }));
+
+        Ok(request.data(self.tags.clone()))
+    }
+
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+
+        let mut state = self.state();
+        if let Some(fingerprint) = state.fingerprint.take() {
+            let name = fingerprint["operationName"].as_str();
+            if is_cacheable(&document, name) {
+                state.key = Some(cache_key(&fingerprint, variables));
+            }
+        }
+
+        Ok(document)
+    }
+
+    async fn validation(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        next: NextValidation<'_>,
+    ) -> Result<ValidationResult, Vec<ServerError>> {
+        let result = next.run(ctx).await?;
+        self.state().cache_control = result.cache_control;
+        Ok(result)
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        let (key, cache_control) = {
+            let state = self.state();
+            (state.key.clone(), state.cache_control)
+        };
+        let Some(key) = key.filter(|_| cache_control.max_age > 0) else {
+            return next.run(ctx, operation_name).await;
+        };
+
+        if let Some(cached) = self.cache.get(&key).await {
+            return Response::new(cached.data);
+        }
+
+        let response = next.run(ctx, operation_name).await;
+        if response.is_ok() {
+            let cached = CachedResponse {
+                data: response.data.clone(),
+            };
+            let ttl = Duration::from_secs(cache_control.max_age as u64);
+            self.cache.put(&key, cached, &self.tags.to_vec(), ttl).await;
+        }
+
+        response
+    }
+}
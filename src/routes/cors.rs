@@ -0,0 +1,167 @@
+use axum::http::{HeaderName, HeaderValue, Method, header};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::config::CorsConfig;
+
+/// One entry of the allowed origins list
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum OriginPattern {
+    Exact(String),
+    /// `https://*.example.com`: any subdomain, at any depth, of `example.com`
+    Subdomain {
+        scheme: String,
+        suffix: String,
+    },
+}
+
+impl OriginPattern {
+    fn parse(pattern: &str) -> Self {
+        let pattern = pattern.trim_end_matches('/').to_ascii_lowercase();
+        match pattern.split_once("://*.") {
+            Some((scheme, domain)) => Self::Subdomain {
+                scheme: format!("{}://", scheme),
+                suffix: format!(".{}", domain),
+            },
+            None => Self::Exact(pattern),
+        }
+    }
+
+    fn matches(&self, origin: &str) -> bool {
+        match self {
+            Self::Exact(exact) => origin.eq_ignore_ascii_case(exact),
+            Self::Subdomain { scheme, suffix } => {
+                let origin = origin.to_ascii_lowercase();
+                let Some(subdomain) = origin
+                    .strip_prefix(scheme.as_str())
+                    .and_then(|host| host.strip_suffix(suffix.as_str()))
+                else {
+                    return false;
+                };
+
+                !subdomain.is_empty()
+                    && subdomain
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+            }
+        }
+    }
+}
+
+fn parse_list<T>(values: &[String], kind: &str, parse: impl Fn(&str) -> Option<T>) -> Vec<T> {
+    values
+        .iter()
+        .filter_map(|value| {
+            let parsed = parse(value);
+            if parsed.is_none() {
+                tracing::warn!("Ignoring invalid CORS {} '{}'", kind, value);
+            }
+            parsed
+        })
+        .collect()
+}
+
+/// Build the CORS layer for browser clients from configuration
+pub fn cors_layer(config: &CorsConfig) -> CorsLayer {
+    let any_origin = config.allowed_origins.iter().any(|origin| origin == "*");
+    // Browsers refuse credentialed responses for a wildcard origin, and tower-http
+    // rejects the combination outright
+    let allow_credentials = config.allow_credentials && !any_origin;
+    if config.allow_credentials && any_origin {
+        tracing::warn!("CORS credentials are disabled because any origin is allowed");
+    }
+
+    let allow_origin = if any_origin {
+        AllowOrigin::any()
+    } else {
+        let patterns: Vec<OriginPattern> = config
+            .allowed_origins
+            .iter()
+            .map(|origin| OriginPattern::parse(origin))
+            .collect();
+        AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+            origin
+                .to_str()
+                .is_ok_and(|origin| patterns.iter().any(|pattern| pattern.matches(origin)))
+        })
+    };
+
+    let methods = parse_list(&config.allowed_methods, "method", |method| {
+        Method::from_bytes(method.to_ascii_uppercase().as_bytes()).ok()
+    });
+    let headers = parse_list(&config.allowed_headers, "header", |name| {
+        HeaderName::try_from(name).ok()
+    });
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .allow_credentials(allow_credentials)
+        .expose_headers([header::RETRY_AFTER, header::ETAG])
+        .max_age(config.max_age)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcard_patterns_are_parsed() {
+        assert_eq!(
+            OriginPattern::parse("HTTPS://*.Example.com/"),
+            OriginPattern::Subdomain {
+                scheme: "https://".to_string(),
+                suffix: ".example.com".to_string(),
+            }
+        );
+        assert_eq!(
+            OriginPattern::parse("https://shop.example.com/"),
+            OriginPattern::Exact("https://shop.example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn wildcards_match_subdomains_at_any_depth() {
+        let pattern = OriginPattern::parse("https://*.example.com");
+        assert!(pattern.matches("https://a.example.com"));
+        assert!(pattern.matches("https://a-1.b.example.com"));
+        assert!(pattern.matches("HTTPS://A.Example.COM"));
+    }
+
+    #[test]
+    fn wildcards_do_not_match_the_bare_domain_or_lookalikes() {
+        let pattern = OriginPattern::parse("https://*.example.com");
+        assert!(!pattern.matches("https://example.com"));
+        assert!(!pattern.matches("https://.example.com"));
+        assert!(!pattern.matches("https://evilexample.com"));
+        assert!(!pattern.matches("https://a.example.com.evil.com"));
+    }
+
+    #[test]
+    fn wildcards_keep_the_scheme() {
+        let pattern = OriginPattern::parse("https://*.example.com");
+        assert!(!pattern.matches("http://a.example.com"));
+    }
+
+    #[test]
+    fn wildcards_reject_ports_and_other_characters() {
+        let pattern = OriginPattern::parse("https://*.example.com");
+        assert!(!pattern.matches("https://a.example.com:8443"));
+        assert!(!pattern.matches("https://a:8443.example.com"));
+        assert!(!pattern.matches("https://user@a.example.com"));
+        assert!(!pattern.matches("https://a/b.example.com"));
+    }
+
+    #[test]
+    fn exact_origins_match_case_insensitively() {
+        let pattern = OriginPattern::parse("https://Shop.Example.com");
+        assert!(pattern.matches("https://shop.example.com"));
+        assert!(pattern.matches("HTTPS://SHOP.EXAMPLE.COM"));
+        assert!(!pattern.matches("https://shop.example.com:8443"));
+        assert!(!pattern.matches("http://shop.example.com"));
+
+        let with_port = OriginPattern::parse("http://localhost:3000");
+        assert!(with_port.matches("http://localhost:3000"));
+        assert!(!with_port.matches("http://localhost:3001"));
+    }
+}
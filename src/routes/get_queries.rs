@@ -0,0 +1,174 @@
+use async_graphql::{
+    ErrorExtensionValues, ServerError, ServerResult, Variables,
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextParseQuery},
+    parser::types::{ExecutableDocument, OperationType},
+};
+use axum::{
+    body::{Body, to_bytes},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// Error code for operations that may not be sent over GET
+pub const METHOD_NOT_ALLOWED: &str = "METHOD_NOT_ALLOWED";
+
+/// Request data marking a request received over HTTP GET
+#[derive(Debug, Clone, Copy)]
+pub struct QueryOverGet;
+
+/// Schema extension rejecting mutations and subscriptions sent over GET, which
+/// caches and prefetchers assume to be safe
+pub struct GetQueriesOnly;
+
+impl ExtensionFactory for GetQueriesOnly {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(GetQueriesOnlyExtension)
+    }
+}
+
+struct GetQueriesOnlyExtension;
+
+#[async_trait::async_trait]
+impl Extension for GetQueriesOnlyExtension {
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+
+        let read_only = document
+            .operations
+            .iter()
+            .all(|(_, operation)| operation.node.ty == OperationType::Query);
+        if ctx.data_opt::<QueryOverGet>().is_some() && !read_only {
+            let mut extensions = ErrorExtensionValues::default();
+            extensions.set("code", METHOD_NOT_ALLOWED);
+
+            let mut err = ServerError::new("mutations and subscriptions must use POST", None);
+            err.extensions = Some(extensions);
+            return Err(err);
+        }
+
+        Ok(document)
+    }
+}
+
+/// Whether the response was refused because the operation needs POST
+pub fn is_method_not_allowed(response: &async_graphql::Response) -> bool {
+    response.errors.iter().any(|err| {
+        err.extensions
+            .as_ref()
+            .and_then(|extensions| extensions.get("code"))
+            .is_some_and(|code| *code == async_graphql::Value::from(METHOD_NOT_ALLOWED))
+    })
+}
+
+/// Whether an `If-None-Match` header value matches `etag`, using weak comparison
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    if_none_match
+        .split(',')
+        .any(|candidate| candidate.trim() == "*" || opaque(candidate) == opaque(etag))
+}
+
+/// Add an ETag and caching headers to a successful GET response, answering
+/// `304 Not Modified` when the client already holds the same body.
+///
+/// Responses depend on the `Authorization` header, so caches must vary on it, and
+/// responses to authenticated callers are only cacheable by the caller's browser.
+pub async fn conditional_response(
+    request_headers: &HeaderMap,
+    response: Response,
+    authenticated: bool,
+) -> Response {
+    let (mut parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => {
+            tracing::error!("Failed to buffer GraphQL response: {}", err);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let etag = format!("\"{:x}\"", Sha256::digest(&body));
+    let cache_control = match parts.headers.get(header::CACHE_CONTROL) {
+        Some(value) if authenticated => {
+            let value = value.to_str().unwrap_or_default();
+            if value.contains("private") {
+                value.to_string()
+            } else {
+                format!("private, {}", value)
+            }
+        }
+        Some(value) => value.to_str().unwrap_or_default().to_string(),
+        // Without a hint, clients may keep the body but must revalidate it
+        None => "no-cache".to_string(),
+    };
+
+    let headers = &mut parts.headers;
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        headers.insert(header::ETAG, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&cache_control) {
+        headers.insert(header::CACHE_CONTROL, value);
+    }
+    headers.append(header::VARY, HeaderValue::from_static("Authorization"));
+
+    let not_modified = request_headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| etag_matches(value, &etag));
+    if not_modified {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(header::CONTENT_TYPE);
+        parts.headers.remove(header::CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::empty());
+    }
+
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ETAG: &str = "\"abc123\"";
+
+    #[test]
+    fn strong_tag_matches_itself() {
+        assert!(etag_matches("\"abc123\"", ETAG));
+        assert!(!etag_matches("\"def456\"", ETAG));
+    }
+
+    #[test]
+    fn weak_tags_compare_by_opaque_value() {
+        assert!(etag_matches("W/\"abc123\"", ETAG));
+        assert!(etag_matches("\"abc123\"", "W/\"abc123\""));
+        assert!(!etag_matches("W/\"def456\"", ETAG));
+    }
+
+    #[test]
+    fn any_tag_in_a_list_matches() {
+        assert!(etag_matches("\"def456\", W/\"abc123\"", ETAG));
+        assert!(etag_matches(" \"abc123\" ,\"def456\"", ETAG));
+        assert!(!etag_matches("\"def456\", \"ghi789\"", ETAG));
+    }
+
+    #[test]
+    fn star_matches_any_tag() {
+        assert!(etag_matches("*", ETAG));
+        assert!(etag_matches(" * ", ETAG));
+        assert!(etag_matches("\"def456\", *", ETAG));
+    }
+
+    #[test]
+    fn quotes_are_part_of_the_tag() {
+        assert!(!etag_matches("abc123", ETAG));
+        assert!(!etag_matches("", ETAG));
+    }
+}
@@ -1,38 +1,264 @@
+mod cors;
+mod get_queries;
+
+use crate::auth::{Authenticator, Principal, SessionStore, authenticate, middleware::bearer_token};
+use crate::config::CorsConfig;
+use crate::handlers::events::CatalogEvents;
 use crate::handlers::media_loader::ProductMediaLoader;
+use crate::handlers::mutations::MutationRoot;
+use crate::handlers::order_loader::{
+    OrderLineLoader, OrderStatusHistoryLoader, PaymentAttemptLoader,
+};
+use crate::handlers::payments::SharedPaymentProvider;
 use crate::handlers::queries::QueryRoot;
+use crate::handlers::stock_level_loader::StockLevelLoader;
+use crate::handlers::subscriptions::SubscriptionRoot;
 use crate::handlers::variant_loader::VariantLoader;
+use crate::metrics::{RequestMetrics, metrics};
+use crate::persisted_queries::PersistedQueries;
+use crate::rate_limit::{Budgets, ClientKey, ComplexityRateLimit, retry_after};
+use crate::response_cache::{ResponseCache, ResponseCaching};
+use crate::shutdown::Shutdown;
+use crate::telemetry::{RequestTracing, spawn_in_current_span};
 use async_graphql::dataloader::DataLoader;
-use async_graphql::http::GraphiQLSource;
-use async_graphql::{EmptyMutation, EmptySubscription, Schema};
-use async_graphql_axum::{GraphQL, GraphQLRequest, GraphQLResponse};
-use axum::response::IntoResponse;
+use async_graphql::futures_util::SinkExt;
+use async_graphql::http::{ALL_WEBSOCKET_PROTOCOLS, GraphiQLSource};
+use async_graphql::{Data, Schema};
+use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
+use axum::extract::ConnectInfo;
+use axum::extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code};
+use axum::response::{IntoResponse, Response};
 use axum::{
     Router,
     extract::Extension,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware,
     response::{Html, Json},
-    routing::{get, post},
+    routing::get,
 };
+use get_queries::{GetQueriesOnly, QueryOverGet, conditional_response, is_method_not_allowed};
 use serde_json::Value;
 use sqlx::{Pool, Postgres};
-use tower_http::cors::CorsLayer;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 
 /// GraphQL Schema type alias
-pub type ApiSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+pub type ApiSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
 
 /// Create and configure the GraphQL schema
-pub fn create_schema(pool: Pool<Postgres>) -> ApiSchema {
+pub fn create_schema(
+    pool: Pool<Postgres>,
+    events: CatalogEvents,
+    payments: SharedPaymentProvider,
+    sessions: SessionStore,
+    budgets: Budgets,
+    persisted_queries: Option<PersistedQueries>,
+    response_cache: Option<Arc<dyn ResponseCache>>,
+) -> ApiSchema {
     let variant_loader = VariantLoader { pool: pool.clone() };
     let media_loader = ProductMediaLoader { pool: pool.clone() };
-    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
-        .data(DataLoader::new(variant_loader, tokio::spawn))
-        .data(DataLoader::new(media_loader, tokio::spawn))
-        .data(pool)
-        .finish()
+    let stock_level_loader = StockLevelLoader { pool: pool.clone() };
+    let order_line_loader = OrderLineLoader { pool: pool.clone() };
+    let order_history_loader = OrderStatusHistoryLoader { pool: pool.clone() };
+    let payment_attempt_loader = PaymentAttemptLoader { pool: pool.clone() };
+    let mut builder = Schema::build(
+        QueryRoot::default(),
+        MutationRoot::default(),
+        SubscriptionRoot,
+    )
+    .data(DataLoader::new(variant_loader, spawn_in_current_span))
+    .data(DataLoader::new(media_loader, spawn_in_current_span))
+    .data(DataLoader::new(stock_level_loader, spawn_in_current_span))
+    .data(DataLoader::new(order_line_loader, spawn_in_current_span))
+    .data(DataLoader::new(order_history_loader, spawn_in_current_span))
+    .data(DataLoader::new(
+        payment_attempt_loader,
+        spawn_in_current_span,
+    ))
+    .data(events)
+    .data(payments)
+    .data(sessions)
+    .data(pool)
+    .extension(RequestTracing)
+    .extension(RequestMetrics)
+    .extension(ComplexityRateLimit::new(budgets))
+    .extension(GetQueriesOnly);
+
+    if let Some(persisted_queries) = persisted_queries {
+        builder = builder.extension(persisted_queries);
+    }
+    if let Some(response_cache) = response_cache {
+        builder = builder.extension(ResponseCaching::new(response_cache));
+    }
+
+    builder.finish()
+}
+
+fn client_ip(connect_info: Option<Extension<ConnectInfo<SocketAddr>>>) -> Option<IpAddr> {
+    connect_info.map(|Extension(ConnectInfo(addr))| addr.ip())
+}
+
+/// Execute a request on behalf of the caller, charging it to their rate limit budget
+async fn execute_as_caller(
+    schema: &ApiSchema,
+    principal: Principal,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    request: async_graphql::Request,
+) -> async_graphql::Response {
+    let client = ClientKey::new(&principal, client_ip(connect_info));
+    schema.execute(request.data(principal).data(client)).await
 }
 
-/// GraphQL handler for processing GraphQL requests
-pub async fn graphql_handler(schema: Extension<ApiSchema>, req: GraphQLRequest) -> GraphQLResponse {
-    schema.execute(req.into_inner()).await.into()
+/// Requests over the caller's complexity budget get a `429` with `Retry-After`
+fn into_http_response(mut response: async_graphql::Response) -> Response {
+    match retry_after(&response) {
+        Some(seconds) => {
+            response
+                .http_headers
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                GraphQLResponse::from(response),
+            )
+                .into_response()
+        }
+        None => GraphQLResponse::from(response).into_response(),
+    }
+}
+
+/// GraphQL handler for processing GraphQL requests on behalf of the authenticated caller
+pub async fn graphql_handler(
+    Extension(schema): Extension<ApiSchema>,
+    principal: Option<Extension<Principal>>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    req: GraphQLRequest,
+) -> Response {
+    let principal = principal
+        .map(|Extension(principal)| principal)
+        .unwrap_or_default();
+
+    let response = execute_as_caller(&schema, principal, connect_info, req.into_inner()).await;
+    into_http_response(response)
+}
+
+/// GraphQL over HTTP GET for queries only, so CDNs and browsers can cache responses.
+/// Responses carry an ETag and `If-None-Match` revalidation is answered with `304`.
+pub async fn graphql_get_handler(
+    Extension(schema): Extension<ApiSchema>,
+    principal: Option<Extension<Principal>>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+    req: GraphQLRequest,
+) -> Response {
+    let principal = principal
+        .map(|Extension(principal)| principal)
+        .unwrap_or_default();
+    let authenticated = !principal.is_anonymous();
+
+    let request = req.into_inner().data(QueryOverGet);
+    let response = execute_as_caller(&schema, principal, connect_info, request).await;
+
+    if is_method_not_allowed(&response) {
+        let mut response = (
+            StatusCode::METHOD_NOT_ALLOWED,
+            GraphQLResponse::from(response),
+        )
+            .into_response();
+        response
+            .headers_mut()
+            .insert(header::ALLOW, HeaderValue::from_static("POST"));
+        return response;
+    }
+
+    let response = into_http_response(response);
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    conditional_response(&headers, response, authenticated).await
+}
+
+/// GraphQL subscription handler speaking the graphql-ws and graphql-transport-ws protocols
+pub async fn graphql_ws_handler(
+    Extension(schema): Extension<ApiSchema>,
+    Extension(shutdown): Extension<Shutdown>,
+    Extension(auth): Extension<Authenticator>,
+    principal: Option<Extension<Principal>>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    protocol: GraphQLProtocol,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let principal = principal
+        .map(|Extension(principal)| principal)
+        .unwrap_or_default();
+    let ip = client_ip(connect_info);
+
+    upgrade
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |socket| {
+            serve_graphql_ws(socket, schema, protocol, shutdown, auth, principal, ip)
+        })
+}
+
+/// Resolve the subscription principal. Browsers cannot set headers on WebSocket
+/// upgrades, so an `Authorization` entry in the `connection_init` payload takes
+/// precedence over the upgrade request's header.
+async fn ws_connection_data(
+    payload: Value,
+    auth: Authenticator,
+    principal: Principal,
+    ip: Option<IpAddr>,
+) -> async_graphql::Result<Data> {
+    let authorization = payload
+        .get("Authorization")
+        .or_else(|| payload.get("authorization"))
+        .and_then(Value::as_str);
+
+    let principal = match authorization {
+        None => principal,
+        Some(value) => {
+            let token = bearer_token(value).ok_or("expected 'Bearer <token>' authorization")?;
+            auth.resolve(token)
+                .await?
+                .ok_or("invalid, expired or revoked token")?
+        }
+    };
+
+    let mut data = Data::default();
+    data.insert(ClientKey::new(&principal, ip));
+    data.insert(principal);
+    Ok(data)
+}
+
+/// Serve subscriptions until the client leaves or the server starts shutting down,
+/// in which case the client gets a "going away" close frame
+async fn serve_graphql_ws(
+    socket: WebSocket,
+    schema: ApiSchema,
+    protocol: GraphQLProtocol,
+    shutdown: Shutdown,
+    auth: Authenticator,
+    principal: Principal,
+    ip: Option<IpAddr>,
+) {
+    let (mut sink, stream) = async_graphql::futures_util::StreamExt::split(socket);
+
+    let interrupted = {
+        let connection = GraphQLWebSocket::new_with_pair(&mut sink, stream, schema, protocol)
+            .on_connection_init(move |payload| ws_connection_data(payload, auth, principal, ip));
+        tokio::select! {
+            _ = connection.serve() => false,
+            _ = shutdown.wait() => true,
+        }
+    };
+
+    if interrupted {
+        let _ = sink
+            .send(Message::Close(Some(CloseFrame {
+                code: close_code::AWAY,
+                reason: "server shutting down".into(),
+            })))
+            .await;
+    }
 }
 
 /// GraphQL Playground handler for development
@@ -71,6 +297,17 @@ pub async fn health_check() -> Json<Value> {
     }))
 }
 
+/// Prometheus scrape endpoint
+pub async fn metrics_handler() -> Response {
+    match metrics().render() {
+        Ok(body) => ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], body).into_response(),
+        Err(err) => {
+            tracing::error!("Failed to encode metrics: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
 async fn graphiql() -> impl IntoResponse {
     Html(
         GraphiQLSource::build()
@@ -81,24 +318,32 @@ async fn graphiql() -> impl IntoResponse {
 }
 
 /// Build the complete application router with all routes and middleware
-pub fn create_router(pool: Pool<Postgres>) -> Router {
-    let schema = create_schema(pool);
-
+pub fn create_router(
+    schema: ApiSchema,
+    auth: Authenticator,
+    cors: &CorsConfig,
+    shutdown: Shutdown,
+) -> Router {
     Router::new()
+        .route("/", get(graphiql).post(graphql_handler))
+        // GraphQL endpoint; GET is accepted for queries only
+        .route("/graphql", get(graphql_get_handler).post(graphql_handler))
+        // GraphQL subscriptions over WebSocket
+        .route("/ws", get(graphql_ws_handler))
+        // Resolve the caller's session token or API key for the GraphQL routes above
+        .route_layer(middleware::from_fn_with_state(auth.clone(), authenticate))
         // Health check endpoint
         .route("/health", get(health_check))
-        .route(
-            "/",
-            get(graphiql).post_service(GraphQL::new(schema.clone())),
-        )
-        // GraphQL endpoint
-        .route("/graphql", post(graphql_handler))
+        // Prometheus metrics
+        .route("/metrics", get(metrics_handler))
         // GraphQL Playground for development
         .route("/playground", get(graphql_playground))
         // Add GraphQL schema as extension
         .layer(Extension(schema))
-        // Add CORS layer for web clients
-        .layer(CorsLayer::permissive())
+        .layer(Extension(shutdown))
+        .layer(Extension(auth))
+        // Only let configured browser origins call the API
+        .layer(cors::cors_layer(cors))
 }
 
 /// Print server startup information
@@ -106,13 +351,16 @@ pub fn print_server_info() {
     println!("🚀 GraphQL Server running on http://0.0.0.0:3000");
     println!("📚 GraphQL Documentation:");
     println!("  POST   /graphql           - GraphQL endpoint");
+    println!("  GET    /graphql           - GraphQL queries (cacheable, with ETag)");
+    println!("  GET    /ws                - GraphQL subscriptions (WebSocket)");
     println!("  GET    /playground        - GraphQL Playground (development)");
     println!("  GET    /health            - Health check");
-    println!("");
+    println!("  GET    /metrics           - Prometheus metrics");
+    println!();
     println!("🎯 Example GraphQL Queries:");
     println!("  # Get all products");
     println!("  query {{ products {{ id name slug status }} }}");
-    println!("");
+    println!();
     println!("  # Get product by ID");
     println!(
         "  query {{ product(id: \"1\") {{ id name description variants {{ sku price {{ amount currency }} }} }} }}"
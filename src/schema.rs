@@ -1,5 +1,56 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_keys (id) {
+        id -> Int4,
+        name -> Varchar,
+        key_prefix -> Varchar,
+        #[max_length = 64]
+        key_hash -> Varchar,
+        scopes -> Array<Nullable<Text>>,
+        rate_limit_per_minute -> Int4,
+        created_by -> Nullable<Int4>,
+        last_used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    audit_log (id) {
+        id -> Int8,
+        entity_type -> Varchar,
+        entity_id -> Int4,
+        action -> Varchar,
+        actor -> Nullable<Varchar>,
+        diff -> Jsonb,
+        changed_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    cart_lines (id) {
+        id -> Int4,
+        cart_id -> Uuid,
+        variant_id -> Int4,
+        quantity -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    carts (id) {
+        id -> Uuid,
+        customer_id -> Nullable<Int4>,
+        #[max_length = 3]
+        currency -> Varchar,
+        status -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     categories (id) {
         id -> Int4,
@@ -9,6 +60,125 @@ diesel::table! {
         sort_order -> Nullable<Int4>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        deleted_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    customer_sessions (id) {
+        id -> Int4,
+        customer_id -> Int4,
+        #[max_length = 64]
+        token_hash -> Varchar,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    customers (id) {
+        id -> Int4,
+        email -> Varchar,
+        password_hash -> Varchar,
+        name -> Nullable<Varchar>,
+        last_login_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        role -> Varchar,
+    }
+}
+
+diesel::table! {
+    locations (id) {
+        id -> Int4,
+        code -> Varchar,
+        name -> Varchar,
+        is_active -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    order_lines (id) {
+        id -> Int4,
+        order_id -> Int4,
+        variant_id -> Nullable<Int4>,
+        sku -> Varchar,
+        product_name -> Varchar,
+        unit_price_amount -> Numeric,
+        #[max_length = 3]
+        currency -> Varchar,
+        quantity -> Int4,
+        line_total_amount -> Numeric,
+        created_at -> Timestamp,
+        shipped_quantity -> Int4,
+    }
+}
+
+diesel::table! {
+    order_status_history (id) {
+        id -> Int4,
+        order_id -> Int4,
+        from_status -> Nullable<Varchar>,
+        to_status -> Varchar,
+        note -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    orders (id) {
+        id -> Int4,
+        order_number -> Varchar,
+        cart_id -> Nullable<Uuid>,
+        customer_id -> Nullable<Int4>,
+        status -> Varchar,
+        #[max_length = 3]
+        currency -> Varchar,
+        subtotal_amount -> Numeric,
+        total_amount -> Numeric,
+        shipping_address -> Jsonb,
+        billing_address -> Jsonb,
+        placed_at -> Timestamp,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        paid_at -> Nullable<Timestamp>,
+        shipped_at -> Nullable<Timestamp>,
+        fulfilled_at -> Nullable<Timestamp>,
+        cancelled_at -> Nullable<Timestamp>,
+        refunded_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    payment_attempts (id) {
+        id -> Int4,
+        order_id -> Int4,
+        idempotency_key -> Uuid,
+        provider -> Varchar,
+        operation -> Varchar,
+        status -> Varchar,
+        amount -> Numeric,
+        #[max_length = 3]
+        currency -> Varchar,
+        parent_attempt_id -> Nullable<Int4>,
+        provider_reference -> Nullable<Varchar>,
+        failure_reason -> Nullable<Text>,
+        created_at -> Timestamp,
+        completed_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    persisted_queries (hash) {
+        #[max_length = 64]
+        hash -> Varchar,
+        query -> Text,
+        name -> Nullable<Varchar>,
+        registered -> Bool,
+        created_at -> Timestamp,
     }
 }
 
@@ -49,6 +219,19 @@ diesel::table! {
         is_primary -> Bool,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        deleted_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    product_revisions (id) {
+        id -> Int8,
+        product_id -> Int4,
+        revision -> Int4,
+        snapshot -> Jsonb,
+        actor -> Nullable<Varchar>,
+        transaction_id -> Int8,
+        created_at -> Timestamp,
     }
 }
 
@@ -65,6 +248,9 @@ diesel::table! {
         is_active -> Bool,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        reorder_point -> Nullable<Int4>,
+        cost_amount -> Nullable<Numeric>,
+        deleted_at -> Nullable<Timestamp>,
     }
 }
 
@@ -77,20 +263,99 @@ diesel::table! {
         status -> Varchar,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        publish_at -> Nullable<Timestamp>,
+        unpublish_at -> Nullable<Timestamp>,
+        deleted_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    stock_levels (id) {
+        id -> Int4,
+        variant_id -> Int4,
+        location_id -> Int4,
+        quantity -> Int4,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    stock_movements (id) {
+        id -> Int4,
+        variant_id -> Int4,
+        movement_type -> Varchar,
+        quantity -> Int4,
+        stock_after -> Int4,
+        reason -> Nullable<Text>,
+        reference -> Nullable<Varchar>,
+        reservation_id -> Nullable<Int4>,
+        created_at -> Timestamp,
+        location_id -> Nullable<Int4>,
+        order_id -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    stock_reservations (id) {
+        id -> Int4,
+        variant_id -> Int4,
+        quantity -> Int4,
+        status -> Varchar,
+        reference -> Nullable<Varchar>,
+        expires_at -> Nullable<Timestamp>,
+        released_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
+diesel::joinable!(cart_lines -> carts (cart_id));
+diesel::joinable!(cart_lines -> product_variants (variant_id));
+diesel::joinable!(carts -> customers (customer_id));
+diesel::joinable!(api_keys -> customers (created_by));
+diesel::joinable!(customer_sessions -> customers (customer_id));
+diesel::joinable!(order_lines -> orders (order_id));
+diesel::joinable!(order_lines -> product_variants (variant_id));
+diesel::joinable!(order_status_history -> orders (order_id));
+diesel::joinable!(orders -> carts (cart_id));
+diesel::joinable!(orders -> customers (customer_id));
+diesel::joinable!(payment_attempts -> orders (order_id));
 diesel::joinable!(product_attributes -> products (product_id));
 diesel::joinable!(product_category_junction -> categories (category_id));
 diesel::joinable!(product_category_junction -> products (product_id));
 diesel::joinable!(product_media -> products (product_id));
+diesel::joinable!(product_revisions -> products (product_id));
 diesel::joinable!(product_variants -> products (product_id));
+diesel::joinable!(stock_levels -> locations (location_id));
+diesel::joinable!(stock_levels -> product_variants (variant_id));
+diesel::joinable!(stock_movements -> locations (location_id));
+diesel::joinable!(stock_movements -> orders (order_id));
+diesel::joinable!(stock_movements -> product_variants (variant_id));
+diesel::joinable!(stock_movements -> stock_reservations (reservation_id));
+diesel::joinable!(stock_reservations -> product_variants (variant_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_keys,
+    audit_log,
+    cart_lines,
+    carts,
     categories,
+    customer_sessions,
+    customers,
+    locations,
+    order_lines,
+    order_status_history,
+    orders,
+    payment_attempts,
+    persisted_queries,
     product_attributes,
     product_category_junction,
     product_media,
+    product_revisions,
     product_variants,
     products,
+    stock_levels,
+    stock_movements,
+    stock_reservations,
 );
@@ -0,0 +1,163 @@
+//! Logging of SQL statements slower than a configurable threshold, optionally with their
+//! `EXPLAIN (ANALYZE, BUFFERS)` plan, to find missing indexes from real traffic
+
+use sqlx::{Execute, PgPool, Postgres, Row, postgres::PgArguments};
+use std::{sync::OnceLock, time::Duration};
+
+static SLOW_QUERY_LOG: OnceLock<SlowQueryLog> = OnceLock::new();
+
+/// Settings for the slow query log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowQueryLog {
+    /// Statements taking longer are logged; zero disables the log
+    pub threshold: Duration,
+    /// Re-run slow `SELECT`s under `EXPLAIN (ANALYZE, BUFFERS)` and log the plan. This
+    /// executes each slow statement twice, so only enable it in development.
+    pub explain: bool,
+}
+
+impl SlowQueryLog {
+    /// Use these settings for every instrumented query; only the first call has an effect
+    pub fn install(self) {
+        if SLOW_QUERY_LOG.set(self).is_err() {
+            tracing::warn!("Slow query log is already configured");
+        }
+    }
+}
+
+/// Arguments of a statement, needed to explain it
+pub struct Explain<'a> {
+    pub pool: &'a PgPool,
+    pub arguments: &'a PgArguments,
+}
+
+/// Take the arguments bound to a query, to run it with `sqlx::query_with` while keeping
+/// a copy to explain it with
+pub fn take_arguments<'q>(
+    mut query: impl Execute<'q, Postgres>,
+) -> Result<PgArguments, sqlx::Error> {
+    Ok(query
+        .take_arguments()
+        .map_err(sqlx::Error::Encode)?
+        .unwrap_or_default())
+}
+
+/// Replace quoted literals with `?` and collapse whitespace, so values that were
+/// formatted into a statement instead of bound never reach the logs
+pub fn redact(sql: &str) -> String {
+    let mut redacted = String::with_capacity(sql.len());
+    let mut in_literal = false;
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (in_literal, c) {
+            (false, '\'') => {
+                in_literal = true;
+                redacted.push_str("'?'");
+            }
+            // A doubled quote escapes a quote inside the literal
+            (true, '\'') if chars.peek() == Some(&'\'') => {
+                chars.next();
+            }
+            (true, '\'') => in_literal = false,
+            (true, _) => {}
+            (false, c) if c.is_whitespace() => {
+                if !redacted.ends_with(' ') {
+                    redacted.push(' ');
+                }
+            }
+            (false, c) => redacted.push(c),
+        }
+    }
+
+    redacted.trim().to_string()
+}
+
+/// Log `sql` if it ran for longer than the configured threshold. Bind parameters are
+/// never logged; with `explain` available, the plan is captured in the background.
+pub fn record(name: &str, sql: &str, elapsed: Duration, explain: Option<Explain<'_>>) {
+    let Some(log) = SLOW_QUERY_LOG.get() else {
+        return;
+    };
+    if log.threshold.is_zero() || elapsed < log.threshold {
+        return;
+    }
+
+    let statement = redact(sql);
+    tracing::warn!(
+        query = name,
+        duration_ms = elapsed.as_millis() as u64,
+        threshold_ms = log.threshold.as_millis() as u64,
+        statement = %statement,
+        "Slow query"
+    );
+
+    let Some(explain) = explain.filter(|_| log.explain) else {
+        return;
+    };
+    // ANALYZE executes the statement, which must not happen twice for writes
+    if !statement.to_ascii_uppercase().starts_with("SELECT") {
+        return;
+    }
+
+    let pool = explain.pool.clone();
+    let arguments = explain.arguments.clone();
+    let sql = format!("EXPLAIN (ANALYZE, BUFFERS) {}", sql);
+    let name = name.to_string();
+    tokio::spawn(async move {
+        match sqlx::query_with(&sql, arguments).fetch_all(&pool).await {
+            Ok(rows) => {
+                let plan = rows
+                    .iter()
+                    .filter_map(|row| row.try_get::<String, _>(0).ok())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                tracing::warn!(query = %name, statement = %statement, "Slow query plan:\n{}", plan);
+            }
+            Err(err) => tracing::warn!("Failed to explain slow query {}: {}", name, err),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literals_are_replaced() {
+        assert_eq!(
+            redact("SELECT id FROM products WHERE sku = 'SKU-1' AND name = 'Shirt'"),
+            "SELECT id FROM products WHERE sku = '?' AND name = '?'"
+        );
+    }
+
+    #[test]
+    fn escaped_quotes_stay_inside_the_literal() {
+        assert_eq!(
+            redact("SELECT 1 WHERE name = 'O''Brien''s secret' AND sku = 'a'''"),
+            "SELECT 1 WHERE name = '?' AND sku = '?'"
+        );
+        assert_eq!(redact("SELECT ''''"), "SELECT '?'");
+    }
+
+    #[test]
+    fn empty_literal_is_replaced() {
+        assert_eq!(redact("SELECT ''"), "SELECT '?'");
+    }
+
+    #[test]
+    fn whitespace_is_collapsed_outside_literals() {
+        assert_eq!(
+            redact("  SELECT id\n\t FROM   products\n WHERE note = 'a   b'  "),
+            "SELECT id FROM products WHERE note = '?'"
+        );
+    }
+
+    #[test]
+    fn bind_parameters_and_numbers_are_kept() {
+        assert_eq!(
+            redact("SELECT id FROM products WHERE id = $1 LIMIT 10"),
+            "SELECT id FROM products WHERE id = $1 LIMIT 10"
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! Structured logging and tracing: spans for GraphQL requests, resolvers, DataLoaders and
+//! SQL, written as text or JSON and optionally exported to an OTLP collector
+
+use async_graphql::{
+    Request, Response, ServerResult, Value,
+    extensions::{
+        Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest, NextRequest,
+        NextResolve, ResolveInfo,
+    },
+    futures_util::future::BoxFuture,
+};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{Resource, trace::SdkTracerProvider};
+use std::{future::Future, str::FromStr, sync::Arc, time::Instant};
+use tokio::task::JoinHandle;
+use tracing::{Instrument, Span, field::Empty};
+use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
+
+use crate::metrics::metrics;
+use crate::persisted_queries::query_hash;
+use crate::slow_query_log::{self, Explain};
+
+/// Service name reported to the trace collector
+const SERVICE_NAME: &str = "rust-store-graphql";
+
+#[derive(Debug, thiserror::Error)]
+pub enum TelemetryError {
+    #[error("unknown value '{0}'")]
+    UnknownValue(String),
+    #[error(transparent)]
+    Exporter(#[from] opentelemetry_otlp::ExporterBuildError),
+}
+
+/// How log lines are written to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line, with the fields of the enclosing spans
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = TelemetryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(TelemetryError::UnknownValue(other.to_string())),
+        }
+    }
+}
+
+/// The installed tracing pipeline; shut it down to flush spans still being exported
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Telemetry {
+    /// Install the global subscriber. With an `otlp_endpoint` (such as
+    /// `http://localhost:4317`), spans are also exported over OTLP/gRPC.
+    pub fn init(format: LogFormat, otlp_endpoint: Option<&str>) -> Self {
+        let exporter = otlp_endpoint.map(|endpoint| {
+            opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint)
+                .build()
+        });
+        let (provider, exporter_error) = match exporter {
+            Some(Ok(exporter)) => {
+                let provider = SdkTracerProvider::builder()
+                    .with_batch_exporter(exporter)
+                    .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+                    .build();
+                (Some(provider), None)
+            }
+            Some(Err(err)) => (None, Some(TelemetryError::from(err))),
+            None => (None, None),
+        };
+
+        tracing_subscriber::registry()
+            .with(
+                EnvFilter::try_from_default_env()
+                    .unwrap_or_else(|_| format!("{}=trace", env!("CARGO_CRATE_NAME")).into()),
+            )
+            .with((format == LogFormat::Text).then(tracing_subscriber::fmt::layer))
+            .with((format == LogFormat::Json).then(|| {
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(true)
+            }))
+            .with(provider.as_ref().map(|provider| {
+                tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME))
+            }))
+            .init();
+
+        if let Some(err) = exporter_error {
+            tracing::error!(
+                "Trace export disabled, failed to build OTLP exporter: {}",
+                err
+            );
+        }
+
+        Self { provider }
+    }
+
+    /// Export the spans still buffered
+    pub fn shutdown(self) {
+        let Some(provider) = self.provider else {
+            return;
+        };
+        if let Err(err) = provider.shutdown() {
+            tracing::warn!("Failed to flush trace export: {}", err);
+        }
+    }
+}
+
+/// Spawner for DataLoaders that keeps their batches inside the span of the resolver
+/// which started them, so loader and SQL spans join the request's trace
+pub fn spawn_in_current_span(future: BoxFuture<'static, ()>) -> JoinHandle<()> {
+    tokio::spawn(future.in_current_span())
+}
+
+/// Rows returned by a query, recorded on its span
+pub trait RowCount {
+    fn row_count(&self) -> usize;
+}
+
+impl<T> RowCount for Vec<T> {
+    fn row_count(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> RowCount for Option<T> {
+    fn row_count(&self) -> usize {
+        usize::from(self.is_some())
+    }
+}
+
+/// Run a SQL query inside a span carrying its statement and row count, recording its
+/// duration under `name` and logging it when slow
+pub async fn instrument_query<T, F>(name: &str, sql: &str, future: F) -> Result<T, sqlx::Error>
+where
+    T: RowCount,
+    F: Future<Output = Result<T, sqlx::Error>>,
+{
+    run_instrumented(name, sql, None, future).await
+}
+
+/// Like [`instrument_query`], also letting the slow query log explain the statement
+pub async fn instrument_explainable_query<T, F>(
+    name: &str,
+    sql: &str,
+    explain: Explain<'_>,
+    future: F,
+) -> Result<T, sqlx::Error>
+where
+    T: RowCount,
+    F: Future<Output = Result<T, sqlx::Error>>,
+{
+    run_instrumented(name, sql, Some(explain), future).await
+}
+
+async fn run_instrumented<T, F>(
+    name: &str,
+    sql: &str,
+    explain: Option<Explain<'_>>,
+    future: F,
+) -> Result<T, sqlx::Error>
+where
+    T: RowCount,
+    F: Future<Output = Result<T, sqlx::Error>>,
+{
+    let span = tracing::debug_span!(
+        "sql.query",
+        db.system = "postgresql",
+        db.operation = name,
+        db.statement = %slow_query_log::redact(sql),
+        rows = Empty,
+    );
+
+    let started = Instant::now();
+    let result = future.instrument(span.clone()).await;
+    let elapsed = started.elapsed();
+    metrics().observe_query(name, elapsed);
+    slow_query_log::record(name, sql, elapsed, explain);
+
+    match &result {
+        Ok(rows) => {
+            span.record("rows", rows.row_count());
+        }
+        Err(err) => {
+            tracing::warn!(parent: &span, "Query {} failed: {}", name, err);
+        }
+    }
+
+    result
+}
+
+/// Schema extension opening a span for each request, tagged with its operation name and
+/// query hash, and a child span for each resolver
+pub struct RequestTracing;
+
+impl ExtensionFactory for RequestTracing {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(RequestTracingExtension {
+            span: tracing::info_span!(
+                "graphql.request",
+                operation_name = Empty,
+                query_hash = Empty,
+                errors = Empty,
+            ),
+        })
+    }
+}
+
+struct RequestTracingExtension {
+    span: Span,
+}
+
+#[async_trait::async_trait]
+impl Extension for RequestTracingExtension {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        let response = next.run(ctx).instrument(self.span.clone()).await;
+        self.span.record("errors", response.errors.len());
+        response
+    }
+
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        // Run the rest of the chain first so persisted query hashes are resolved
+        let request = next.run(ctx, request).await?;
+
+        if let Some(operation_name) = &request.operation_name {
+            self.span.record("operation_name", operation_name.as_str());
+        }
+        self.span
+            .record("query_hash", query_hash(&request.query).as_str());
+
+        Ok(request)
+    }
+
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        if info.is_for_introspection {
+            return next.run(ctx, info).await;
+        }
+
+        let span = tracing::debug_span!(
+            "graphql.resolve",
+            parent_type = info.parent_type,
+            field = info.name,
+            path = %info.path_node,
+        );
+        next.run(ctx, info).instrument(span).await
+    }
+}